@@ -0,0 +1,14 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(ossl300)");
+
+    // openssl-sys reports the version of the linked OpenSSL; FIPS mode is enabled differently
+    // starting with OpenSSL 3
+    if let Ok(version) = env::var("DEP_OPENSSL_VERSION_NUMBER") {
+        let version = u64::from_str_radix(&version, 16).unwrap();
+        if version >= 0x3_00_00_00_0 {
+            println!("cargo:rustc-cfg=ossl300");
+        }
+    }
+}
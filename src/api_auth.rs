@@ -0,0 +1,119 @@
+//! Compatibility with the Ruby `api-auth` gem
+//!
+//! api-auth clients send `Authorization: APIAuth-HMAC-SHA256 <access id>:<signature>`, where the
+//! signature is the base64 HMAC of `method,content-type,content-hash,request-uri,date`.
+
+use std::time::Duration;
+
+use iron;
+use url;
+
+use canonical::Canonicalizer;
+use config::Config;
+use error::Result;
+use encoding::SignatureEncoding;
+use presets;
+use ::{HmacAuthentication, SecretKey, Sha256};
+
+/// Scheme of the `Authorization` header of api-auth's HMAC-SHA256 mode
+const AUTHORIZATION_SCHEME: &'static str = "APIAuth-HMAC-SHA256";
+
+/// Header the signature is moved to from the `Authorization` header, and responses are signed in
+const SIGNATURE_HEADER: &'static str = "x-api-auth-signature";
+
+/// Clock skew api-auth accepts for the `Date` header
+const MAX_SKEW: u64 = 900;
+
+/// Canonicalization of the `api-auth` gem
+///
+/// The request MAC is the HMAC of the uppercase method, `Content-Type`, the content hash,
+/// the path with query, and `Date`, joined with commas. The content hash is the
+/// `X-Authorization-Content-SHA256` header of newer clients or the `Content-MD5` header of older
+/// ones. With the `content-digest` feature, `X-Authorization-Content-SHA256` is checked against
+/// the body; otherwise neither header is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiAuthCanonicalizer;
+
+impl Canonicalizer for ApiAuthCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, _config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let content_hash = match header(req, "x-authorization-content-sha256") {
+            Some(hash) => {
+                try!(check_content_hash(req, &hash));
+                hash
+            },
+            None => header(req, "content-md5").unwrap_or_default()
+        };
+
+        let url: url::Url = req.url.clone().into();
+        let request_uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned()
+        };
+
+        let canonical = format!("{},{},{},{},{}",
+                                req.method.as_ref().to_uppercase(),
+                                header(req, "content-type").unwrap_or_default(),
+                                content_hash,
+                                if request_uri.is_empty() { "/" } else { &request_uri[..] },
+                                header(req, "date").unwrap_or_default());
+
+        Ok(mac(canonical.as_bytes()))
+    }
+}
+
+impl HmacAuthentication<Sha256> {
+    /// Build BeforeMiddleware and AfterMiddleware accepting requests signed by `api-auth`
+    ///
+    /// Requests are verified against the `APIAuth-HMAC-SHA256` `Authorization` header with
+    /// `ApiAuthCanonicalizer`, and the `Date` header must be within 15 minutes. The access id is
+    /// ignored; set `Config::key_id_header` and `Config::keys` on a custom configuration to tell
+    /// clients apart. Responses are signed in the `x-api-auth-signature` header.
+    pub fn api_auth<K>(secret: K)
+        -> (HmacAuthentication<Sha256, ::ConstantTimeVerifier, ApiAuthCanonicalizer>,
+            HmacAuthentication<Sha256, ::ConstantTimeVerifier, ApiAuthCanonicalizer>)
+        where K: Into<SecretKey>
+    {
+        let config = Config {
+            encoding: SignatureEncoding::Base64,
+            authorization_scheme: Some(AUTHORIZATION_SCHEME.to_owned()),
+            require_date: true,
+            date_max_skew: Some(Duration::from_secs(MAX_SKEW)),
+            ..Config::default()
+        };
+
+        presets::preset_with(Sha256::default(), secret, SIGNATURE_HEADER, config,
+                             ApiAuthCanonicalizer)
+    }
+}
+
+/// Check the `X-Authorization-Content-SHA256` header against the body
+#[cfg(feature = "content-digest")]
+fn check_content_hash(req: &mut iron::Request, hash: &str) -> Result<()> {
+    use rustc_serialize::base64::{self, ToBase64};
+    use sha2::{Digest, Sha256};
+
+    use error::Error;
+
+    let body = try!(::canonical::CanonicalRequest::from_request(req)).body;
+    if Sha256::digest(body.as_bytes())[..].to_base64(base64::STANDARD) != hash {
+        let reason = "content hash does not match the body".to_owned();
+        return Err(Error::InvalidContentDigest(reason));
+    }
+
+    Ok(())
+}
+
+/// Without the `content-digest` feature, the content hash is covered but not checked
+#[cfg(not(feature = "content-digest"))]
+fn check_content_hash(_req: &mut iron::Request, _hash: &str) -> Result<()> {
+    Ok(())
+}
+
+/// The trimmed value of a header, if sent
+fn header(req: &iron::Request, name: &str) -> Option<String> {
+    req.headers.get_raw(name)
+               .and_then(|value| ::std::str::from_utf8(&value[0][..]).ok())
+               .map(|value| value.trim().to_owned())
+}
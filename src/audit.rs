@@ -0,0 +1,67 @@
+//! Recording of every verification attempt, e.g. for a SIEM
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use iron;
+
+/// Receives an `AuditEvent` for every request the middleware verifies
+///
+/// Set it with `HmacAuthentication::with_audit`. It is called on the request path, so forward
+/// events to a channel or buffered logger rather than doing slow work inline. Any
+/// `Fn(&AuditEvent)` closure is an audit hook.
+pub trait KeyAudit: Send + Sync + 'static {
+    fn record(&self, event: &AuditEvent);
+}
+
+impl fmt::Debug for dyn KeyAudit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("KeyAudit")
+    }
+}
+
+impl<F> KeyAudit for F
+    where F: Fn(&AuditEvent) + Send + Sync + 'static
+{
+    fn record(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// Whether a request was verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Verified,
+    /// Rejected, with the description of the error
+    Rejected(String),
+}
+
+/// One verification attempt
+///
+/// Secrets and MACs are never included.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    /// The key id sent in `Config::key_id_header`, if any
+    pub key_id: Option<String>,
+    pub outcome: AuditOutcome,
+    pub method: String,
+    pub path: String,
+    pub remote_addr: SocketAddr,
+    pub time: SystemTime,
+}
+
+impl AuditEvent {
+    pub(crate) fn new(req: &iron::Request, key_id: Option<&str>, outcome: AuditOutcome)
+        -> AuditEvent
+    {
+        AuditEvent {
+            key_id: key_id.map(str::to_owned),
+            outcome: outcome,
+            method: req.method.as_ref().to_owned(),
+            path: format!("/{}", req.url.path().join("/")),
+            remote_addr: req.remote_addr,
+            time: SystemTime::now()
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Signatures sent in the standard `Authorization` header
+//!
+//! The credentials are `<scheme> <key id>:<signature>`, or `<scheme> <signature>` when no key id
+//! is named, e.g. `Authorization: HMAC partner:7f2c...`.
+
+use iron;
+
+use config::Config;
+use error::Result;
+use util;
+
+/// Move the credentials of an `Authorization` header using `scheme` into the headers they stand
+/// for
+///
+/// The signature is set as the HMAC header and the key id, if any, as `Config::key_id_header`,
+/// so they are handled as if sent separately. Requests without such an `Authorization` header
+/// are left unchanged.
+pub fn apply(req: &mut iron::Request, config: &Config, scheme: &str, hmac_header: &str)
+    -> Result<()>
+{
+    let credentials = match try!(util::single_header(req, "authorization",
+                                                     config.duplicate_headers)) {
+        Some(value) => {
+            let mut parts = value.splitn(2, ' ');
+            if !parts.next().unwrap_or("").eq_ignore_ascii_case(scheme) {
+                return Ok(());
+            }
+            parts.next().unwrap_or("").trim().to_owned()
+        },
+        None => return Ok(())
+    };
+
+    let mut parts = credentials.rsplitn(2, ':');
+    let signature = parts.next().unwrap_or("");
+    req.headers.set_raw(hmac_header.to_owned(), vec![signature.as_bytes().to_vec()]);
+
+    if let (Some(key_id), Some(header)) = (parts.next(), config.key_id_header.as_ref()) {
+        req.headers.set_raw(header.clone(), vec![key_id.as_bytes().to_vec()]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,337 @@
+//! Secrets fetched from AWS Secrets Manager
+//!
+//! `GetSecretValue` requests are signed with SigV4 using credentials from the environment: the
+//! `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` variables, or the
+//! container credentials endpoint that ECS tasks and EKS Pod Identity provide.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use iron;
+use reqwest;
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::json::Json;
+use sha2::{Digest, Sha256};
+
+use error::{Error, Result};
+use hmac::Algorithm;
+use secret_store::SecretProvider;
+use sigv4;
+use util;
+use ::{HmacDigest, SecretKey};
+
+/// Service name of the credential scope
+const SERVICE: &'static str = "secretsmanager";
+
+/// Field of a key/value secret used for requests that name no key
+const DEFAULT_FIELD: &'static str = "default";
+
+/// Address of the ECS container credentials endpoint
+const CONTAINER_CREDENTIALS_HOST: &'static str = "http://169.254.170.2";
+
+/// How often secrets are fetched again by default
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How long to keep serving cached secrets after a failed refresh before trying again
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The parsed value of a secret
+struct Secrets {
+    default: Option<SecretKey>,
+    keys: HashMap<String, SecretKey>,
+}
+
+/// The secrets of the last successful fetch
+struct Cached {
+    secrets: Secrets,
+    fetched: Instant,
+    retry_at: Option<Instant>,
+    refreshing: bool,
+}
+
+/// AWS credentials signing the requests to Secrets Manager
+struct Credentials {
+    access_key: String,
+    secret_key: SecretKey,
+    session_token: Option<String>,
+}
+
+/// Secrets read from AWS Secrets Manager and fetched again periodically to pick up rotations
+///
+/// A plaintext secret is used for requests that do not name a key. A key/value secret, i.e. a
+/// JSON object, holds the key `id` in field `id` and the secret for requests naming no key in
+/// field `default`. Values are decoded as by `SecretKey::from_env`, so `hex:` and `base64:`
+/// prefixes are understood, and binary secrets are used as they are. The request that triggers a
+/// refetch waits for Secrets Manager while other requests keep being served the cached secrets,
+/// which also stay in use if Secrets Manager cannot be reached.
+pub struct AwsSecretsManagerProvider {
+    client: reqwest::Client,
+    endpoint: String,
+    host: String,
+    region: String,
+    secret_id: String,
+    refresh_interval: Duration,
+    cache: Mutex<Cached>,
+}
+
+impl fmt::Debug for AwsSecretsManagerProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AwsSecretsManagerProvider")
+         .field("endpoint", &self.endpoint)
+         .field("region", &self.region)
+         .field("secret_id", &self.secret_id)
+         .field("refresh_interval", &self.refresh_interval)
+         .finish()
+    }
+}
+
+impl AwsSecretsManagerProvider {
+    /// Read the secret `secret_id`, a name or ARN, from Secrets Manager in `region`
+    ///
+    /// The secret is fetched here, so that a misconfiguration fails at startup with
+    /// `Error::SecretBackendUnavailable` or `Error::InvalidKey`.
+    pub fn new(region: &str, secret_id: &str) -> Result<AwsSecretsManagerProvider> {
+        let endpoint = format!("https://{}.{}.amazonaws.com", SERVICE, region);
+        AwsSecretsManagerProvider::with_endpoint(&endpoint, region, secret_id)
+    }
+
+    /// Read the secret `secret_id` from the Secrets Manager API at `endpoint`
+    ///
+    /// Use this for interface VPC endpoints or to reach a local emulator, e.g.
+    /// `http://localhost:4566`.
+    pub fn with_endpoint(endpoint: &str, region: &str, secret_id: &str)
+        -> Result<AwsSecretsManagerProvider>
+    {
+        let endpoint = endpoint.trim_end_matches('/');
+        let host = match endpoint.find("://") {
+            Some(index) => &endpoint[index + 3..],
+            None => endpoint
+        };
+
+        let provider = AwsSecretsManagerProvider {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.to_owned(),
+            host: host.to_owned(),
+            region: region.to_owned(),
+            secret_id: secret_id.to_owned(),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            cache: Mutex::new(Cached {
+                secrets: Secrets { default: None, keys: HashMap::new() },
+                fetched: Instant::now(),
+                retry_at: None,
+                refreshing: false
+            })
+        };
+
+        let secrets = try!(provider.fetch());
+        provider.cache.lock().unwrap().secrets = secrets;
+
+        Ok(provider)
+    }
+
+    /// Fetch the secret again this often; every five minutes if not set
+    pub fn refresh_interval(mut self, interval: Duration) -> AwsSecretsManagerProvider {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Fetch and parse the current version of the secret
+    fn fetch(&self) -> Result<Secrets> {
+        let credentials = try!(credentials(&self.client));
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let timestamp = sigv4::format_timestamp(seconds);
+        let body = format!("{{\"SecretId\":{}}}", Json::String(self.secret_id.clone()));
+
+        let mut signed = vec![
+            ("content-type", "application/x-amz-json-1.1".to_owned()),
+            ("host", self.host.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(ref token) = credentials.session_token {
+            signed.push(("x-amz-security-token", token.clone()));
+        }
+        signed.push(("x-amz-target", "secretsmanager.GetSecretValue".to_owned()));
+
+        let mut canonical_headers = String::new();
+        for &(name, ref value) in &signed {
+            canonical_headers.push_str(&format!("{}:{}\n", name, value));
+        }
+        let signed_headers = signed.iter().map(|&(name, _)| name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("POST\n/\n\n{}\n{}\n{}", canonical_headers, signed_headers,
+                                        util::to_hex(&Sha256::digest(body.as_bytes())));
+
+        let date = &timestamp[..8];
+        let credential_scope = format!("{}/{}/{}/{}", date, self.region, SERVICE,
+                                       sigv4::TERMINATOR);
+        let string_to_sign = format!("{}\n{}\n{}\n{}", sigv4::ALGORITHM, timestamp,
+                                     credential_scope,
+                                     util::to_hex(&Sha256::digest(canonical_request.as_bytes())));
+        let signing_key = sigv4::signing_key(&credentials.secret_key, date, &self.region, SERVICE,
+                                             sigv4::TERMINATOR);
+        let signature = try!(Algorithm::Sha256.try_hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let mut headers = reqwest::header::Headers::new();
+        for &(name, ref value) in signed.iter().filter(|&&(name, _)| name != "host") {
+            headers.set_raw(name, value.clone());
+        }
+        headers.set_raw("authorization",
+                        format!("{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                                sigv4::ALGORITHM, credentials.access_key, credential_scope,
+                                signed_headers, util::to_hex(&signature)));
+
+        let mut res = try!(self.client.post(&format!("{}/", self.endpoint))
+                                      .headers(headers)
+                                      .body(body)
+                                      .send()
+                                      .map_err(|err| unavailable(&err.to_string())));
+        if !res.status().is_success() {
+            return Err(unavailable(&format!("Secrets Manager responded with {}", res.status())));
+        }
+
+        let json = try!(read_json(&mut res, "Secrets Manager"));
+        if let Some(value) = json.find("SecretString").and_then(Json::as_string) {
+            return parse_secret(value);
+        }
+
+        match json.find("SecretBinary").and_then(Json::as_string).map(FromBase64::from_base64) {
+            Some(Ok(ref value)) if !value.is_empty() => {
+                Ok(Secrets { default: Some(SecretKey::new(&value[..])), keys: HashMap::new() })
+            },
+            _ => Err(Error::InvalidKey(format!("secret {} has no value", self.secret_id)))
+        }
+    }
+}
+
+impl SecretProvider for AwsSecretsManagerProvider {
+    fn secret_for(&self, _req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        let now = Instant::now();
+        let refresh = {
+            let mut cached = self.cache.lock().unwrap();
+            let expired = now.duration_since(cached.fetched) >= self.refresh_interval;
+            let backing_off = cached.retry_at.map_or(false, |retry_at| now < retry_at);
+            if expired && !backing_off && !cached.refreshing {
+                cached.refreshing = true;
+                true
+            } else {
+                false
+            }
+        };
+
+        // Secrets Manager is only asked once the lock is released, so concurrent requests are not
+        // held up
+        let fetched = if refresh { Some(self.fetch()) } else { None };
+
+        let mut cached = self.cache.lock().unwrap();
+        match fetched {
+            Some(Ok(secrets)) => {
+                cached.secrets = secrets;
+                cached.fetched = now;
+                cached.retry_at = None;
+                cached.refreshing = false;
+            },
+            Some(Err(_)) => {
+                cached.retry_at = Some(now + RETRY_INTERVAL);
+                cached.refreshing = false;
+            },
+            None => ()
+        }
+
+        match key_id {
+            Some(id) => cached.secrets.keys.get(id).cloned(),
+            None => cached.secrets.default.clone()
+        }
+    }
+}
+
+/// Parse a plaintext or key/value secret string
+fn parse_secret(value: &str) -> Result<Secrets> {
+    let fields = match Json::from_str(value) {
+        Ok(Json::Object(fields)) => fields,
+        _ => {
+            let secret = try!(SecretKey::from_setting("secret string", value));
+            return Ok(Secrets { default: Some(secret), keys: HashMap::new() });
+        }
+    };
+
+    let mut secrets = Secrets { default: None, keys: HashMap::new() };
+    for (name, value) in &fields {
+        if let Json::String(ref value) = *value {
+            let secret = try!(SecretKey::from_setting(&format!("secret field {}", name), value));
+            if name == DEFAULT_FIELD {
+                secrets.default = Some(secret);
+            } else {
+                secrets.keys.insert(name.clone(), secret);
+            }
+        }
+    }
+    if secrets.default.is_none() && secrets.keys.is_empty() {
+        return Err(Error::InvalidKey("secret has no string fields".to_owned()));
+    }
+
+    Ok(secrets)
+}
+
+/// Credentials from the environment or the container credentials endpoint
+fn credentials(client: &reqwest::Client) -> Result<Credentials> {
+    if let (Ok(access_key), Ok(secret_key)) = (env::var("AWS_ACCESS_KEY_ID"),
+                                               env::var("AWS_SECRET_ACCESS_KEY")) {
+        return Ok(Credentials {
+            access_key: access_key,
+            secret_key: SecretKey::new(secret_key.as_bytes()),
+            session_token: env::var("AWS_SESSION_TOKEN").ok()
+        });
+    }
+
+    let url = match (env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"),
+                     env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI")) {
+        (Ok(uri), _) => format!("{}{}", CONTAINER_CREDENTIALS_HOST, uri),
+        (_, Ok(url)) => url,
+        _ => return Err(unavailable("no AWS credentials found"))
+    };
+
+    let mut headers = reqwest::header::Headers::new();
+    if let Ok(token) = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+        headers.set_raw("authorization", token);
+    } else if let Ok(path) = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE") {
+        let mut token = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut token));
+        headers.set_raw("authorization", token.trim().to_owned());
+    }
+
+    let mut res = try!(client.get(&url[..])
+                             .headers(headers)
+                             .send()
+                             .map_err(|err| unavailable(&err.to_string())));
+    if !res.status().is_success() {
+        return Err(unavailable(&format!("credentials endpoint responded with {}", res.status())));
+    }
+
+    let json = try!(read_json(&mut res, "credentials endpoint"));
+    let field = |name| json.find(name).and_then(Json::as_string).map(str::to_owned);
+    match (field("AccessKeyId"), field("SecretAccessKey")) {
+        (Some(access_key), Some(secret_key)) => Ok(Credentials {
+            access_key: access_key,
+            secret_key: SecretKey::new(secret_key.as_bytes()),
+            session_token: field("Token")
+        }),
+        _ => Err(unavailable("credentials endpoint responded without credentials"))
+    }
+}
+
+/// Read a JSON response body
+fn read_json(res: &mut reqwest::Response, source: &str) -> Result<Json> {
+    let mut body = String::new();
+    try!(res.read_to_string(&mut body));
+    Json::from_str(&body)
+        .map_err(|_| unavailable(&format!("{} responded with invalid JSON", source)))
+}
+
+fn unavailable(reason: &str) -> Error {
+    Error::SecretBackendUnavailable(reason.to_owned())
+}
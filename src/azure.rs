@@ -0,0 +1,140 @@
+//! Azure Storage style Shared Key authorization
+//!
+//! Requests carry `Authorization: SharedKey <account>:<signature>`, where the signature is the
+//! base64 HMAC-SHA256 of the string to sign: the method, the standard headers `Content-Encoding`
+//! through `Range` (with a `Content-Length` of 0 as empty), the `x-ms-` headers, and the
+//! canonicalized resource `/<account><path>` followed by the sorted query parameters.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::base64::{self, ToBase64};
+use url;
+
+use error::{Error, Result};
+use hmac::Algorithm;
+use replay;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// Scheme of the `Authorization` header
+const SCHEME: &'static str = "SharedKey";
+
+/// Standard headers of the string to sign, in order
+const STANDARD_HEADERS: [&'static str; 11] = [
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "content-md5",
+    "content-type",
+    "date",
+    "if-modified-since",
+    "if-match",
+    "if-none-match",
+    "if-unmodified-since",
+    "range",
+];
+
+/// Verify the Shared Key signature of `req` with the keys of `auth`
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let (account, supplied) = try!(credentials(req));
+
+    let date = match header(req, "x-ms-date") {
+        Some(date) => date,
+        None => match header(req, "date") {
+            Some(date) => date,
+            None => return Err(malformed("missing x-ms-date header").into())
+        }
+    };
+    let seconds = try!(replay::parse_http_date(&date));
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds),
+                                 replay::timestamp_max_age(&auth.inner.config)));
+
+    let secret = try!(auth.sha256_key(req, &account));
+    let string_to_sign = string_to_sign(req, &account);
+    let computed = try!(Algorithm::Sha256.try_hmac(&secret, string_to_sign.as_bytes()))
+                       .to_base64(base64::STANDARD);
+
+    let (computed, supplied) = (computed.as_bytes(), supplied.as_bytes());
+    if computed.len() != supplied.len() || !auth.inner.verifier.verify(computed, supplied) {
+        forbidden!();
+    }
+
+    Ok(())
+}
+
+/// The account name and signature of the `Authorization` header
+fn credentials(req: &iron::Request) -> Result<(String, String)> {
+    let value = match header(req, "authorization") {
+        Some(value) => value,
+        None => return Err(malformed("missing Authorization header"))
+    };
+
+    let mut parts = value.splitn(2, ' ');
+    if parts.next() != Some(SCHEME) {
+        return Err(malformed("not a SharedKey authorization"));
+    }
+
+    let mut credentials = parts.next().unwrap_or("").trim().rsplitn(2, ':');
+    match (credentials.next(), credentials.next()) {
+        (Some(signature), Some(account)) => Ok((account.to_owned(), signature.to_owned())),
+        _ => Err(malformed("invalid SharedKey credentials"))
+    }
+}
+
+/// The string to sign for `account`
+fn string_to_sign(req: &iron::Request, account: &str) -> String {
+    let mut lines = vec![req.method.as_ref().to_owned()];
+    for name in &STANDARD_HEADERS {
+        let value = header(req, name).unwrap_or_default();
+        if *name == "content-length" && value == "0" {
+            lines.push(String::new());
+        } else {
+            lines.push(value);
+        }
+    }
+
+    let mut ms_headers: Vec<(String, String)> =
+        req.headers.iter()
+                   .map(|header| (header.name().to_lowercase(), header.value_string()))
+                   .filter(|&(ref name, _)| name.starts_with("x-ms-"))
+                   .map(|(name, value)| {
+                       (name, value.split_whitespace().collect::<Vec<_>>().join(" "))
+                   })
+                   .collect();
+    ms_headers.sort();
+    for (name, value) in ms_headers {
+        lines.push(format!("{}:{}", name, value));
+    }
+
+    let url: url::Url = req.url.clone().into();
+    lines.push(format!("/{}{}", account, url.path()));
+
+    let mut params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, value) in url.query_pairs() {
+        params.entry(name.to_lowercase()).or_insert_with(Vec::new).push(value.into_owned());
+    }
+    for (name, mut values) in params {
+        values.sort();
+        lines.push(format!("{}:{}", name, values.join(",")));
+    }
+
+    lines.join("\n")
+}
+
+/// The trimmed value of a header, if sent
+fn header(req: &iron::Request, name: &str) -> Option<String> {
+    req.headers.get_raw(name)
+               .and_then(|value| ::std::str::from_utf8(&value[0][..]).ok())
+               .map(|value| value.trim().to_owned())
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedSignature(reason.to_owned())
+}
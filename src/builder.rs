@@ -0,0 +1,207 @@
+//! Chained construction of the middleware
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use audit::KeyAudit;
+use canonical::{Canonicalizer, DefaultCanonicalizer};
+use config::{Config, Key};
+use encoding::SignatureEncoding;
+use error::Result;
+use replay::ReplayCache;
+use verifier::{ConstantTimeVerifier, Verifier};
+use ::{HmacAuthentication, HmacDigest, SecretKey};
+
+/// Header carrying the MACs when `HmacAuthenticationBuilder::hmac_header` is not called
+pub const DEFAULT_HMAC_HEADER: &'static str = "x-hmac";
+
+/// Builds both halves of an `HmacAuthentication`, one option at a time
+///
+/// Start with `HmacAuthentication::builder`. The setters cover the common `Config` fields; set
+/// anything else with `config` first, since it replaces every `Config` field set before it. A
+/// custom verifier, canonicalizer, replay cache or audit hook set here is shared by both halves.
+///
+/// ```
+/// use std::time::Duration;
+/// use iron_hmac::{Hmac256Authentication, SecretKey, SignatureEncoding};
+///
+/// let secret = SecretKey::generate(32).unwrap();
+/// let (hmac_before, hmac_after) = Hmac256Authentication::builder(secret)
+///     .hmac_header("x-signature")
+///     .encoding(SignatureEncoding::Base64)
+///     .timestamp_header("x-signature-timestamp", Duration::from_secs(300))
+///     .nonce_header("x-signature-nonce")
+///     .finish()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct HmacAuthenticationBuilder<D, V = ConstantTimeVerifier, C = DefaultCanonicalizer>
+    where D: HmacDigest
+{
+    digest: D,
+    secret: SecretKey,
+    hmac_header_key: String,
+    config: Config,
+    verifier: V,
+    canonicalizer: C,
+    nonces: Option<Arc<dyn ReplayCache>>,
+    audit: Option<Arc<dyn KeyAudit>>,
+}
+
+impl<D: HmacDigest> HmacAuthenticationBuilder<D> {
+    pub fn new<K: Into<SecretKey>>(digest: D, secret: K) -> HmacAuthenticationBuilder<D> {
+        HmacAuthenticationBuilder {
+            digest: digest,
+            secret: secret.into(),
+            hmac_header_key: DEFAULT_HMAC_HEADER.to_owned(),
+            config: Config::default(),
+            verifier: ConstantTimeVerifier,
+            canonicalizer: DefaultCanonicalizer,
+            nonces: None,
+            audit: None
+        }
+    }
+}
+
+impl<D, V, C> HmacAuthenticationBuilder<D, V, C>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    /// Start from `config` instead of `Config::default()`, discarding the options set so far
+    pub fn config(mut self, config: Config) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config = config;
+        self
+    }
+
+    /// Header carrying the request and response MACs; `x-hmac` if not set
+    pub fn hmac_header<S: Into<String>>(mut self, name: S) -> HmacAuthenticationBuilder<D, V, C> {
+        self.hmac_header_key = name.into();
+        self
+    }
+
+    /// See `Config::response_hmac_header`
+    pub fn response_hmac_header<S>(mut self, name: S) -> HmacAuthenticationBuilder<D, V, C>
+        where S: Into<String>
+    {
+        self.config.response_hmac_header = Some(name.into());
+        self
+    }
+
+    /// See `Config::encoding`
+    pub fn encoding(mut self, encoding: SignatureEncoding) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.encoding = encoding;
+        self
+    }
+
+    /// See `Config::signature_prefix`
+    pub fn signature_prefix<S>(mut self, prefix: S) -> HmacAuthenticationBuilder<D, V, C>
+        where S: Into<String>
+    {
+        self.config.signature_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Also cover the request header `name` by the request HMAC; see `Config::signed_headers`
+    pub fn signed_header<S: Into<String>>(mut self, name: S) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.signed_headers.push(name.into());
+        self
+    }
+
+    /// Require a timestamp within `max_age` in the header `name`; see `Config::timestamp_header`
+    pub fn timestamp_header<S>(mut self, name: S, max_age: Duration)
+        -> HmacAuthenticationBuilder<D, V, C>
+        where S: Into<String>
+    {
+        self.config.timestamp_header = Some(name.into());
+        self.config.timestamp_max_age = Some(max_age);
+        self
+    }
+
+    /// See `Config::nonce_header`
+    pub fn nonce_header<S: Into<String>>(mut self, name: S) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.nonce_header = Some(name.into());
+        self
+    }
+
+    /// Require a `Date` header within `max_skew`; see `Config::require_date`
+    pub fn require_date(mut self, max_skew: Duration) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.require_date = true;
+        self.config.date_max_skew = Some(max_skew);
+        self
+    }
+
+    /// See `Config::key_id_header`
+    pub fn key_id_header<S: Into<String>>(mut self, name: S) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.key_id_header = Some(name.into());
+        self
+    }
+
+    /// Accept requests naming the key `id` in the key id header; see `Config::keys`
+    pub fn key<S: Into<String>>(mut self, id: S, key: Key) -> HmacAuthenticationBuilder<D, V, C> {
+        self.config.keys.insert(id.into(), key);
+        self
+    }
+
+    /// Also accept requests signed with `secret`; see `Config::previous_secrets`
+    pub fn previous_secret<K>(mut self, secret: K) -> HmacAuthenticationBuilder<D, V, C>
+        where K: Into<SecretKey>
+    {
+        self.config.previous_secrets.push(secret.into());
+        self
+    }
+
+    /// Compare MACs with `verifier`; see `HmacAuthentication::with_verifier`
+    pub fn verifier<W: Verifier>(self, verifier: W) -> HmacAuthenticationBuilder<D, W, C> {
+        HmacAuthenticationBuilder {
+            digest: self.digest,
+            secret: self.secret,
+            hmac_header_key: self.hmac_header_key,
+            config: self.config,
+            verifier: verifier,
+            canonicalizer: self.canonicalizer,
+            nonces: self.nonces,
+            audit: self.audit
+        }
+    }
+
+    /// Compute request HMACs with `canonicalizer`; see `HmacAuthentication::with_canonicalizer`
+    pub fn canonicalizer<K>(self, canonicalizer: K) -> HmacAuthenticationBuilder<D, V, K>
+        where K: Canonicalizer
+    {
+        HmacAuthenticationBuilder {
+            digest: self.digest,
+            secret: self.secret,
+            hmac_header_key: self.hmac_header_key,
+            config: self.config,
+            verifier: self.verifier,
+            canonicalizer: canonicalizer,
+            nonces: self.nonces,
+            audit: self.audit
+        }
+    }
+
+    /// Remember nonces in `cache`; see `HmacAuthentication::with_replay_cache`
+    pub fn replay_cache<R: ReplayCache>(mut self, cache: R) -> HmacAuthenticationBuilder<D, V, C> {
+        self.nonces = Some(Arc::new(cache));
+        self
+    }
+
+    /// Report every verification attempt to `audit`; see `HmacAuthentication::with_audit`
+    pub fn audit<A: KeyAudit>(mut self, audit: A) -> HmacAuthenticationBuilder<D, V, C> {
+        self.audit = Some(Arc::new(audit));
+        self
+    }
+
+    /// Build the BeforeMiddleware and AfterMiddleware
+    ///
+    /// Returns an error if the options are not valid for the digest, as `with_config` does.
+    pub fn finish(self) -> Result<(HmacAuthentication<D, V, C>, HmacAuthentication<D, V, C>)> {
+        let auth = try!(HmacAuthentication::from_parts(self.digest, Some(self.secret),
+                                                       self.hmac_header_key, self.config));
+        let nonces = self.nonces.unwrap_or_else(|| auth.inner.nonces.clone());
+        let auth = auth.with_options(self.verifier, self.canonicalizer, nonces, self.audit);
+
+        Ok((auth.clone(), auth))
+    }
+}
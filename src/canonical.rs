@@ -0,0 +1,540 @@
+use std::fmt;
+
+use iron;
+use bodyparser;
+use url;
+
+use iron::prelude::*;
+use iron::typemap;
+
+use config::Config;
+#[cfg(feature = "content-digest")]
+use content_digest;
+use error::{Error, Result};
+use util;
+
+/// Defines what the request HMAC covers
+///
+/// Implement this to match an externally mandated canonical request format.
+pub trait Canonicalizer: Clone + Send + Sync + 'static {
+    /// Compute the MAC the client must supply for `req`
+    ///
+    /// `mac` computes the HMAC of its argument with the secret and algorithm selected for the
+    /// request. `config` carries the canonicalization options of `DefaultCanonicalizer`, which
+    /// other implementations may ignore. Read the body through `bodyparser` so it remains
+    /// available to later middleware.
+    fn request_mac<F>(&self, req: &mut iron::Request, config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>;
+}
+
+/// The default canonicalization
+///
+/// The request HMAC is `hmac(hmac(method) + hmac(path) + hmac(body))`. The request is first
+/// normalized as configured by `Config::normalization`, the body is covered as configured by
+/// `Config::body`, and the HMAC is extended as configured by `Config::query`,
+/// `Config::signed_headers`, `Config::sign_content`, `Config::sign_host`,
+/// `Config::sign_scheme_and_port`, and `Config::client_addr`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultCanonicalizer;
+
+impl Canonicalizer for DefaultCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        Ok(try!(self.explain(req, config, mac)).mac)
+    }
+}
+
+impl DefaultCanonicalizer {
+    /// Compute the request HMAC of `req` along with every input to it
+    ///
+    /// This is the computation behind `request_mac`, for debugging signatures that do not match.
+    /// The result is not truncated.
+    pub fn explain<F>(&self, req: &mut iron::Request, config: &Config, mac: F)
+        -> Result<Explanation>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let mut canonical = if config.body.reads_body() {
+            try!(CanonicalRequest::from_request(req))
+        } else {
+            CanonicalRequest::from_head(req)
+        };
+        canonical.normalize(&config.normalization);
+        let mut parts = canonical.parts(config.query);
+
+        match config.body {
+            BodySigning::Full => parts.push(("body", canonical.body.clone())),
+            BodySigning::Omit => (),
+            BodySigning::DigestHeader => {
+                parts.push(("content_digest", try!(content_digest_header(req))));
+            },
+            #[cfg(feature = "content-digest")]
+            BodySigning::VerifiedDigest => {
+                let digest = try!(content_digest::verify(req, canonical.body.as_bytes()));
+                parts.push(("content_digest", digest));
+            }
+        }
+
+        let header_names = config.header_names();
+        if !header_names.is_empty() {
+            parts.push(("headers", try!(header_block(&req.headers, &header_names[..]))));
+        }
+
+        if config.sign_host {
+            parts.push(("authority", try!(authority(req))));
+        }
+
+        if config.sign_scheme_and_port {
+            parts.push(("scheme_and_port", scheme_and_port(req)));
+        }
+
+        if let Some(ref resolver) = config.client_addr {
+            parts.push(("client_addr", try!(resolver.resolve(req)).to_string()));
+        }
+
+        Ok(Explanation::new(parts, mac))
+    }
+}
+
+/// Canonicalization covering only the raw body
+///
+/// The request MAC is `hmac(body)`, as used by webhook providers such as GitHub. The options of
+/// `Config` are ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BodyCanonicalizer;
+
+impl Canonicalizer for BodyCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, _config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        Ok(mac(try!(CanonicalRequest::from_request(req)).body.as_bytes()))
+    }
+}
+
+/// One input to a request HMAC
+#[derive(Debug, Clone)]
+pub struct Component {
+    /// What the value is, e.g. `method` or `headers`
+    pub name: &'static str,
+    /// The canonical value
+    pub value: String,
+    /// HMAC of the value
+    pub mac: Vec<u8>,
+}
+
+/// The inputs to a request HMAC and the HMAC itself
+///
+/// The HMAC is that of the concatenated component HMACs, in order. `Display` renders each
+/// component and HMAC on its own lines with hex encoded HMACs.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub components: Vec<Component>,
+    pub mac: Vec<u8>,
+}
+
+impl Explanation {
+    fn new<F>(parts: Vec<(&'static str, String)>, mac: F) -> Explanation
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let components: Vec<Component> = parts.into_iter().map(|(name, value)| {
+            let value_mac = mac(value.as_bytes());
+            Component { name: name, value: value, mac: value_mac }
+        }).collect();
+
+        let mut merged = Vec::new();
+        for component in &components {
+            merged.extend_from_slice(&component.mac[..]);
+        }
+
+        Explanation {
+            mac: mac(&merged[..]),
+            components: components
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for component in &self.components {
+            try!(writeln!(f, "{}: {:?}", component.name, component.value));
+            try!(writeln!(f, "  hmac: {}", util::to_hex(&component.mac[..])));
+        }
+        write!(f, "hmac: {}", util::to_hex(&self.mac[..]))
+    }
+}
+
+/// Request extension recording which canonicalization a verified request HMAC matched
+///
+/// Read it with `req.extensions.get::<MatchedCanonicalization>()` to track the progress of a
+/// migration configured with `Config::legacy`.
+pub struct MatchedCanonicalization;
+
+impl typemap::Key for MatchedCanonicalization {
+    type Value = Canonicalization;
+}
+
+/// The canonicalization a request HMAC was computed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canonicalization {
+    /// The options of the middleware's `Config`
+    Primary,
+    /// The options of `Config::legacy`
+    Legacy,
+}
+
+/// Canonicalization policy applied to requests before hashing
+///
+/// Clients must sign the normalized form. The default leaves requests as received. When enabled,
+/// the steps run in this order:
+///
+/// 1. The method case is changed as configured by `method_case`.
+/// 2. Percent-encoding in the path is normalized.
+/// 3. Dot segments are removed from the path.
+/// 4. A trailing slash is stripped from the path.
+#[derive(Debug, Clone, Default)]
+pub struct Normalization {
+    /// How the case of the method is treated
+    pub method_case: MethodCase,
+    /// How a trailing slash on the path is treated
+    pub trailing_slash: TrailingSlash,
+    /// Resolve `.` and `..` path segments as in RFC 3986 section 5.2.4
+    pub remove_dot_segments: bool,
+    /// Normalize percent-encoding in the path as in RFC 3986 section 6.2.2
+    ///
+    /// Encoded unreserved characters are decoded, so `/%7Euser` and `/~user` are equal, and the
+    /// hex digits of remaining escapes are uppercased, so `%2f` and `%2F` are equal. Reserved
+    /// characters stay encoded, since `/a%2Fb` and `/a/b` name different resources.
+    pub percent_encoding: bool,
+}
+
+/// Method case handling for `Normalization`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodCase {
+    /// The method is signed as received
+    Keep,
+    /// The method is uppercased, for clients that send `get` but sign `GET`
+    Upper,
+    /// The method is lowercased, for clients that sign `get` but send `GET`
+    Lower,
+}
+
+impl Default for MethodCase {
+    fn default() -> MethodCase {
+        MethodCase::Keep
+    }
+}
+
+/// Trailing slash handling for `Normalization`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// The path is signed as received, so `/foo/` and `/foo` differ
+    Keep,
+    /// A trailing slash is removed from any path but `/`, so `/foo/` and `/foo` are equal
+    Strip,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> TrailingSlash {
+        TrailingSlash::Keep
+    }
+}
+
+/// How the request body is covered by the request HMAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySigning {
+    /// The body is read and covered
+    ///
+    /// This is the default.
+    Full,
+    /// The body is neither read nor covered
+    ///
+    /// Use this for large uploads that must not be buffered when authenticating the method, path,
+    /// and headers is sufficient.
+    Omit,
+    /// The body is not read; the `Content-Digest` header is required and covered in its place
+    ///
+    /// The middleware does not check the digest against the body, so the handler must do so while
+    /// streaming the body.
+    DigestHeader,
+    /// The body is read and checked against the `Content-Digest` header, which is covered in its
+    /// place
+    ///
+    /// The legacy `Digest` header is used if `Content-Digest` is absent. SHA-256 and SHA-512
+    /// digests are supported. Since the body is hashed once, proxies can validate the digest
+    /// independently. Requires the `content-digest` feature.
+    #[cfg(feature = "content-digest")]
+    VerifiedDigest,
+}
+
+impl BodySigning {
+    /// Whether the body must be buffered
+    fn reads_body(self) -> bool {
+        match self {
+            BodySigning::Full => true,
+            BodySigning::Omit | BodySigning::DigestHeader => false,
+            #[cfg(feature = "content-digest")]
+            BodySigning::VerifiedDigest => true,
+        }
+    }
+}
+
+impl Default for BodySigning {
+    fn default() -> BodySigning {
+        BodySigning::Full
+    }
+}
+
+/// How the query string is covered by the request HMAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuerySigning {
+    /// Only the path is signed; the query string may be changed freely
+    ///
+    /// This is the default for compatibility with existing clients.
+    Ignore,
+    /// The path and raw query string, joined by `?`, are signed in place of the path
+    Raw,
+    /// Like `Raw`, but the query parameters are put into canonical form first
+    ///
+    /// Parameters are decoded (`+` as space), percent-encoded again leaving only unreserved
+    /// characters as is, sorted by name and then value, and joined with `&`. Clients whose HTTP
+    /// libraries reorder or re-encode parameters still verify.
+    Sorted,
+}
+
+impl Default for QuerySigning {
+    fn default() -> QuerySigning {
+        QuerySigning::Ignore
+    }
+}
+
+/// The parts of a request covered by its signature
+#[derive(Debug, Clone)]
+pub struct CanonicalRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub body: String,
+}
+
+impl CanonicalRequest {
+    /// Signed parts of a request for `method` and `target`, a path with optional query string
+    pub fn new(method: &str, target: &str, body: &str) -> CanonicalRequest {
+        let mut target = target.splitn(2, '?');
+        CanonicalRequest {
+            method: method.to_owned(),
+            path: target.next().unwrap_or("").to_owned(),
+            query: target.next().map(|query| query.to_owned()),
+            body: body.to_owned(),
+        }
+    }
+
+    /// Compute the request HMAC as `DefaultCanonicalizer` would, along with every input to it
+    ///
+    /// `config` is applied as by `DefaultCanonicalizer::explain`, except that headers, including
+    /// `Content-Digest`, the authority, the scheme and port, and the client address are not
+    /// covered since they are not known here.
+    pub fn explain<F>(&self, config: &Config, mac: F) -> Explanation
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let mut canonical = self.clone();
+        canonical.normalize(&config.normalization);
+
+        let mut parts = canonical.parts(config.query);
+        if config.body == BodySigning::Full {
+            parts.push(("body", canonical.body.clone()));
+        }
+
+        Explanation::new(parts, mac)
+    }
+
+    /// The method and target as signed
+    fn parts(&self, query: QuerySigning) -> Vec<(&'static str, String)> {
+        vec![
+            ("method", self.method.clone()),
+            ("target", self.target(query)),
+        ]
+    }
+
+    /// Extract the signed parts of `req`
+    ///
+    /// The body is read through bodyparser so it remains available to later middleware.
+    pub fn from_request(req: &mut iron::Request) -> Result<CanonicalRequest> {
+        let body = match try!(req.get::<bodyparser::Raw>()) {
+            Some(body) => body,
+            None => "".to_string()
+        };
+
+        Ok(CanonicalRequest { body: body, ..CanonicalRequest::from_head(req) })
+    }
+
+    /// Extract the signed parts of `req` other than the body, which is left empty and unread
+    pub fn from_head(req: &iron::Request) -> CanonicalRequest {
+        let url: url::Url = req.url.clone().into();
+
+        CanonicalRequest {
+            method: req.method.as_ref().to_owned(),
+            path: url.path().to_owned(),
+            query: url.query().map(|query| query.to_owned()),
+            body: String::new(),
+        }
+    }
+
+    /// Apply `normalization` to the signed parts
+    pub fn normalize(&mut self, normalization: &Normalization) {
+        match normalization.method_case {
+            MethodCase::Keep => (),
+            MethodCase::Upper => self.method = self.method.to_uppercase(),
+            MethodCase::Lower => self.method = self.method.to_lowercase(),
+        }
+
+        if normalization.percent_encoding {
+            self.path = normalize_percent_encoding(&self.path);
+        }
+
+        if normalization.remove_dot_segments {
+            self.path = remove_dot_segments(&self.path);
+        }
+
+        if normalization.trailing_slash == TrailingSlash::Strip {
+            while self.path.len() > 1 && self.path.ends_with('/') {
+                self.path.pop();
+            }
+        }
+    }
+
+    /// The path, with the query string if `query` calls for it
+    pub fn target(&self, query: QuerySigning) -> String {
+        match (query, self.query.as_ref()) {
+            (QuerySigning::Raw, Some(raw)) => format!("{}?{}", self.path, raw),
+            (QuerySigning::Sorted, Some(raw)) => format!("{}?{}", self.path, sorted_query(raw)),
+            _ => self.path.clone()
+        }
+    }
+}
+
+/// Canonical form of a query string for `QuerySigning::Sorted`
+pub fn sorted_query(raw: &str) -> String {
+    let mut params: Vec<(String, String)> =
+        url::form_urlencoded::parse(raw.as_bytes())
+            .map(|(name, value)| {
+                (util::percent_encode(name.as_bytes()), util::percent_encode(value.as_bytes()))
+            })
+            .collect();
+    params.sort();
+
+    params.iter()
+          .map(|&(ref name, ref value)| format!("{}={}", name, value))
+          .collect::<Vec<_>>()
+          .join("&")
+}
+
+/// The named request headers as `name:value` lines
+///
+/// Names are lowercased, values are trimmed and multiple values are joined with `, `. A missing
+/// header has an empty value, so removing a signed header invalidates the signature.
+pub fn header_block(headers: &iron::Headers, names: &[String]) -> Result<String> {
+    let mut block = String::new();
+    for name in names {
+        let values = match headers.get_raw(&name[..]) {
+            Some(values) => {
+                let mut decoded = Vec::with_capacity(values.len());
+                for value in values.iter() {
+                    decoded.push(try!(::std::str::from_utf8(&value[..])).trim());
+                }
+                decoded.join(", ")
+            },
+            None => String::new()
+        };
+
+        block.push_str(&name.to_lowercase());
+        block.push(':');
+        block.push_str(&values);
+        block.push('\n');
+    }
+
+    Ok(block)
+}
+
+/// The trimmed value of the required `Content-Digest` header
+fn content_digest_header(req: &iron::Request) -> Result<String> {
+    match req.headers.get_raw("content-digest") {
+        Some(value) => Ok(try!(::std::str::from_utf8(&value[0][..])).trim().to_owned()),
+        None => Err(Error::InvalidContentDigest("missing Content-Digest header".to_owned()))
+    }
+}
+
+/// The lowercased authority the request was sent to
+///
+/// This is the `Host` header, or the host and port of the request URL if the header is absent.
+pub fn authority(req: &iron::Request) -> Result<String> {
+    let authority = match req.headers.get_raw("host") {
+        Some(value) => try!(::std::str::from_utf8(&value[0][..])).trim().to_owned(),
+        None => {
+            let url: url::Url = req.url.clone().into();
+            match url.port() {
+                Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+                None => url.host_str().unwrap_or("").to_owned()
+            }
+        }
+    };
+
+    Ok(authority.to_lowercase())
+}
+
+/// The scheme and port the request was received on, as `scheme:port`
+pub fn scheme_and_port(req: &iron::Request) -> String {
+    let url: url::Url = req.url.clone().into();
+    format!("{}:{}", url.scheme(), url.port_or_known_default().unwrap_or(0))
+}
+
+/// Decode escaped unreserved characters and uppercase the hex digits of other escapes
+fn normalize_percent_encoding(path: &str) -> String {
+    fn hex_value(byte: u8) -> Option<u8> {
+        (byte as char).to_digit(16).map(|value| value as u8)
+    }
+
+    let bytes = path.as_bytes();
+    let mut normalized = String::with_capacity(path.len());
+    let mut start = 0;
+    let mut i = 0;
+
+    while i + 2 < bytes.len() {
+        if bytes[i] == b'%' {
+            if let (Some(high), Some(low)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                normalized.push_str(&path[start..i]);
+                normalized.push_str(&util::percent_encode(&[high << 4 | low]));
+                i += 3;
+                start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    normalized.push_str(&path[start..]);
+    normalized
+}
+
+/// Resolve `.` and `..` segments of an absolute path
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+
+    while let Some(part) = parts.next() {
+        let last = parts.peek().is_none();
+        match part {
+            "." | ".." => {
+                if part == ".." {
+                    segments.pop();
+                }
+                // A trailing dot segment leaves the path ending in a slash
+                if last {
+                    segments.push("");
+                }
+            },
+            _ => segments.push(part)
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
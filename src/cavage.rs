@@ -0,0 +1,129 @@
+//! draft-cavage HTTP Signatures compatibility
+//!
+//! The signature is sent in the `Signature` header, or in the `Authorization` header under the
+//! `Signature` scheme, as parameters such as
+//! `keyId="partner",algorithm="hmac-sha256",headers="(request-target) date",signature="..."`.
+//! The signing string has a `name: value` line for each entry of `headers`, where
+//! `(request-target)` is the lowercase method and the path with query, and `(created)` and
+//! `(expires)` are the signature parameters of the same name. `headers` defaults to `date`.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::base64::FromBase64;
+use url;
+
+use error::{Error, Result};
+use http_signatures;
+use replay;
+use structured;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// Scheme of signatures sent in the `Authorization` header
+const AUTHORIZATION_SCHEME: &'static str = "Signature";
+
+/// Algorithm name meaning the algorithm is derived from the key
+const HS2019: &'static str = "hs2019";
+
+/// Verify the draft-cavage signature of `req` with the keys of `auth`
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let params = try!(signature_params(req));
+    let param = |name: &str| {
+        params.iter()
+              .find(|&&(ref param, _)| param == name)
+              .map(|&(_, ref value)| &value[..])
+    };
+
+    let headers: Vec<String> = param("headers").unwrap_or("date")
+                                               .split_whitespace()
+                                               .map(|name| name.to_lowercase())
+                                               .collect();
+    if !headers.iter().any(|name| name == "(request-target)") {
+        return Err(malformed("(request-target) must be covered").into());
+    }
+
+    let supplied = match param("signature") {
+        Some(signature) => try!(signature.from_base64().map_err(Error::from)),
+        None => return Err(malformed("missing signature").into())
+    };
+
+    let algorithm = param("algorithm").and_then(|name| {
+        if name.eq_ignore_ascii_case(HS2019) { None } else { Some(name) }
+    });
+    let signing_string = try!(signing_string(req, &headers, &param));
+    let mac = try!(auth.keyed_mac(req, param("keyId"), algorithm));
+    let computed = try!(mac(signing_string.as_bytes()));
+
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+    if let Some(created) = param("created") {
+        let created: u64 = try!(created.parse().map_err(|_| malformed("invalid created")));
+        try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(created), max_age));
+    }
+    if let Some(expires) = param("expires") {
+        let expires: u64 = try!(expires.parse().map_err(|_| malformed("invalid expires")));
+        try!(replay::check_expiry(expires));
+    }
+
+    Ok(())
+}
+
+/// The parameters of the `Signature` header, or of `Authorization: Signature ...`
+fn signature_params(req: &iron::Request) -> Result<Vec<(String, String)>> {
+    if req.headers.get_raw("signature").is_some() {
+        let value = try!(http_signatures::field_value(req, "signature"));
+        return Ok(structured::parse(&value));
+    }
+
+    if let Some(value) = req.headers.get_raw("authorization") {
+        let value = try!(::std::str::from_utf8(&value[0][..])).trim();
+        let mut parts = value.splitn(2, ' ');
+        if parts.next().unwrap_or("").eq_ignore_ascii_case(AUTHORIZATION_SCHEME) {
+            return Ok(structured::parse(parts.next().unwrap_or("")));
+        }
+    }
+
+    Err(malformed("missing Signature header"))
+}
+
+/// The signing string covering `headers`
+fn signing_string<'a, F>(req: &iron::Request, headers: &[String], param: &F) -> Result<String>
+    where F: Fn(&str) -> Option<&'a str>
+{
+    let url: url::Url = req.url.clone().into();
+
+    let mut lines = Vec::with_capacity(headers.len());
+    for name in headers {
+        let value = match &name[..] {
+            "(request-target)" => match url.query() {
+                Some(query) => format!("{} {}?{}", req.method.as_ref().to_lowercase(),
+                                       url.path(), query),
+                None => format!("{} {}", req.method.as_ref().to_lowercase(), url.path())
+            },
+            "(created)" | "(expires)" => {
+                match param(&name[1..name.len() - 1]) {
+                    Some(value) => value.to_owned(),
+                    None => return Err(malformed(&format!("missing {} parameter", name)))
+                }
+            },
+            _ => try!(http_signatures::field_value(req, name))
+        };
+        lines.push(format!("{}: {}", name, value));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedSignature(reason.to_owned())
+}
@@ -0,0 +1,84 @@
+//! Resolution of the client address covered by `Config::client_addr`
+
+use std::fmt;
+use std::net::IpAddr;
+
+use iron;
+
+use error::{Error, Result};
+
+/// Determines the address of the client that sent a request
+///
+/// Implement this for proxy setups the provided resolvers do not cover.
+pub trait ClientAddrResolver: Send + Sync + 'static {
+    /// The client address of `req`
+    ///
+    /// Fail with `Error::UnresolvedClientAddr` if the request does not identify its client.
+    fn resolve(&self, req: &iron::Request) -> Result<IpAddr>;
+}
+
+impl fmt::Debug for dyn ClientAddrResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ClientAddrResolver")
+    }
+}
+
+/// The peer address of the connection
+///
+/// Use this when clients connect directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoteAddr;
+
+impl ClientAddrResolver for RemoteAddr {
+    fn resolve(&self, req: &iron::Request) -> Result<IpAddr> {
+        Ok(req.remote_addr.ip())
+    }
+}
+
+/// An address from the `X-Forwarded-For` header appended to by trusted proxies
+///
+/// Each proxy appends the address it received the request from, so with `trusted_proxies`
+/// proxies in front of the server the client is that many entries from the end. Entries further
+/// left were supplied by the client and are ignored.
+#[derive(Debug, Clone)]
+pub struct ForwardedFor {
+    header: String,
+    trusted_proxies: usize,
+}
+
+impl ForwardedFor {
+    /// Resolve from `X-Forwarded-For` behind `trusted_proxies` proxies
+    pub fn new(trusted_proxies: usize) -> ForwardedFor {
+        ForwardedFor::with_header("x-forwarded-for", trusted_proxies)
+    }
+
+    /// Resolve from a header with the format of `X-Forwarded-For`, e.g. `x-real-ip`
+    pub fn with_header<S: Into<String>>(header: S, trusted_proxies: usize) -> ForwardedFor {
+        ForwardedFor {
+            header: header.into(),
+            trusted_proxies: trusted_proxies
+        }
+    }
+}
+
+impl ClientAddrResolver for ForwardedFor {
+    fn resolve(&self, req: &iron::Request) -> Result<IpAddr> {
+        let mut entries = Vec::new();
+        if let Some(values) = req.headers.get_raw(&self.header[..]) {
+            for value in values.iter() {
+                let value = try!(::std::str::from_utf8(&value[..]));
+                entries.extend(value.split(',').map(|entry| entry.trim().to_owned()));
+            }
+        }
+
+        if self.trusted_proxies == 0 || entries.len() < self.trusted_proxies {
+            return Err(Error::UnresolvedClientAddr(format!("missing {} entry", self.header)));
+        }
+
+        let entry = &entries[entries.len() - self.trusted_proxies];
+        match entry.parse() {
+            Ok(addr) => Ok(addr),
+            Err(_) => Err(Error::UnresolvedClientAddr(format!("invalid address {}", entry)))
+        }
+    }
+}
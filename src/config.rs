@@ -0,0 +1,683 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use iron::typemap;
+
+use canonical::{BodySigning, Normalization, QuerySigning};
+use client_addr::ClientAddrResolver;
+use encoding::SignatureEncoding;
+use hmac::{fips, Algorithm, HmacDigest};
+use error::{Error, Result};
+use rotation::SecretRotation;
+use secret_store::{SecretProvider, SecretStore};
+use signature::SignatureScheme;
+use ::SecretKey;
+
+#[cfg(feature = "aws-sigv4")]
+use sigv4::AwsSigV4;
+
+/// Smallest truncated MAC length in bytes permitted by RFC 2104
+const MIN_TRUNCATED_LEN: usize = 10;
+
+/// Smallest secret length in bytes accepted on construction unless `min_secret_len` is set
+const DEFAULT_MIN_SECRET_LEN: usize = 32;
+
+/// A secret and the algorithm requests signed with it use
+///
+/// A key may be limited to a validity window, outside of which requests signed with it are
+/// rejected with `Error::KeyNotValid`. Its scopes and metadata are passed on to handlers as a
+/// `VerifiedKey`.
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub secret: SecretKey,
+    pub algorithm: Algorithm,
+    /// Time before which the key may not be used
+    pub not_before: Option<SystemTime>,
+    /// Time after which the key is retired
+    pub not_after: Option<SystemTime>,
+    /// What requests signed with the key may do, e.g. `read` or `admin`
+    pub scopes: Vec<String>,
+    /// Anything else handlers should know about the key's holder, e.g. an account id
+    pub metadata: HashMap<String, String>,
+}
+
+impl Key {
+    pub fn new<K: Into<SecretKey>>(secret: K, algorithm: Algorithm) -> Key {
+        Key {
+            secret: secret.into(),
+            algorithm: algorithm,
+            not_before: None,
+            not_after: None,
+            scopes: Vec::new(),
+            metadata: HashMap::new()
+        }
+    }
+
+    /// Grant the key `scope`
+    pub fn with_scope<S: Into<String>>(mut self, scope: S) -> Key {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    /// Attach the metadata `name` with `value` to the key
+    pub fn with_metadata<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Key {
+        self.metadata.insert(name.into(), value.into());
+        self
+    }
+
+    /// Limit the key to requests received from `not_before` on
+    pub fn valid_from(mut self, not_before: SystemTime) -> Key {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    /// Retire the key after `not_after`
+    pub fn valid_until(mut self, not_after: SystemTime) -> Key {
+        self.not_after = Some(not_after);
+        self
+    }
+
+    /// Check that the key `id` is within its validity window
+    pub(crate) fn check_validity(&self, id: &str) -> Result<()> {
+        let now = SystemTime::now();
+        let early = self.not_before.map_or(false, |not_before| now < not_before);
+        let late = self.not_after.map_or(false, |not_after| now > not_after);
+        if early || late {
+            return Err(Error::KeyNotValid(id.to_owned()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Request extension describing the key a verified request was signed with
+///
+/// After successful verification of a request naming a key of `Config::keys` or
+/// `Config::secret_store`, handlers can read it with `req.extensions.get::<VerifiedKey>()` to
+/// authorize the request, e.g. to refuse writes to read-only keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedKey {
+    /// The key id the request named
+    pub id: String,
+    pub scopes: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl VerifiedKey {
+    /// Whether the key was granted `scope`
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+impl typemap::Key for VerifiedKey {
+    type Value = VerifiedKey;
+}
+
+/// Request extension identifying the secret and key id a verified request was signed with
+///
+/// It is the hex encoded first 8 bytes of an HMAC-SHA256 keyed with the secret, so logs can
+/// correlate traffic to a key without revealing it. Compute the fingerprint of a known key with
+/// `SecretKey::fingerprint`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyFingerprint(pub String);
+
+impl fmt::Display for KeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl typemap::Key for KeyFingerprint {
+    type Value = KeyFingerprint;
+}
+
+/// A version of the signing scheme clients may declare
+///
+/// Requests declaring the version are verified with the canonicalization options of `config`,
+/// as for `Config::legacy`, and signed with `algorithm` if set.
+#[derive(Debug, Clone, Default)]
+pub struct Version {
+    pub algorithm: Option<Algorithm>,
+    pub config: Config,
+}
+
+impl Version {
+    pub fn new(algorithm: Option<Algorithm>, config: Config) -> Version {
+        Version {
+            algorithm: algorithm,
+            config: config
+        }
+    }
+}
+
+/// What to do when a request carries more than one of the accepted HMAC header names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderConflict {
+    /// Verify the header named first, the HMAC header ahead of `fallback_hmac_headers`
+    PreferFirst,
+    /// Accept only if every header carries the same value
+    RequireEqual,
+    /// Reject the request
+    Reject,
+}
+
+impl Default for HeaderConflict {
+    fn default() -> HeaderConflict {
+        HeaderConflict::PreferFirst
+    }
+}
+
+/// What to do when a request carries the HMAC header more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateHeaders {
+    /// Reject the request, so a proxy cannot be tricked into forwarding a bogus duplicate
+    Reject,
+    /// Accept if any of the values verifies
+    TryAny,
+    /// Accept only if every value verifies
+    RequireAll,
+}
+
+impl Default for DuplicateHeaders {
+    fn default() -> DuplicateHeaders {
+        DuplicateHeaders::Reject
+    }
+}
+
+/// How requests are authenticated
+///
+/// A middleware verifies requests with exactly one scheme. Every scheme other than `Hmac` reads
+/// its signature from where its own format puts it instead of from the HMAC header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scheme {
+    /// The MACs in the HMAC header, computed as configured by the other `Config` options
+    Hmac,
+
+    /// Legacy OAuth 1.0 (RFC 5849) compatibility mode
+    ///
+    /// Requests are verified against the base64 `oauth_signature` parameter, which may be sent in
+    /// the `Authorization` header, the query, or a form encoded body, computed over the OAuth
+    /// signature base string. The middleware's secret is the consumer secret and its digest should
+    /// be `Sha1` for HMAC-SHA1. `oauth_timestamp` must be within `timestamp_max_age`, and an
+    /// `oauth_nonce` may not be reused by the same consumer. The HMAC header is not used and
+    /// responses are not signed.
+    OAuth1,
+
+    /// HTTP Message Signatures (RFC 9421) mode
+    ///
+    /// Requests are verified against the `Signature` and `Signature-Input` headers instead of the
+    /// HMAC header, with the key named by the `keyid` parameter or the middleware's secret.
+    /// Signatures must cover `@method` and one of `@target-uri`, `@request-target`, or `@path`.
+    /// Responses are signed in the HMAC header as usual.
+    HttpMessageSignatures,
+
+    /// draft-cavage HTTP Signatures compatibility mode
+    ///
+    /// Requests are verified against the `Signature` header, or an `Authorization` header with
+    /// the `Signature` scheme, instead of the HMAC header. The key is named by the `keyId`
+    /// parameter, or is the middleware's secret when absent, and the `algorithm` parameter must
+    /// match it unless it is `hs2019`. The `headers` parameter must include `(request-target)`.
+    /// Responses are signed in the HMAC header as usual.
+    DraftCavage,
+
+    /// AWS Signature Version 4 compatibility mode, scoped to a region and service
+    ///
+    /// Requests are verified against the SigV4 `Authorization` and `X-Amz-Date` headers instead
+    /// of the HMAC header. The access key id names the secret in `keys`, which must use
+    /// `Algorithm::Sha256`; when `keys` is empty the middleware's secret is used. `X-Amz-Date`
+    /// must be within `timestamp_max_age`, and `UNSIGNED-PAYLOAD` is only accepted with
+    /// `BodySigning::Omit`. Responses are signed in the HMAC header as usual.
+    #[cfg(feature = "aws-sigv4")]
+    AwsSigV4(AwsSigV4),
+
+    /// Azure Storage style Shared Key mode
+    ///
+    /// Requests are verified against a `SharedKey <account>:<signature>` `Authorization` header
+    /// instead of the HMAC header. The account names the secret in `keys`, which must use
+    /// `Algorithm::Sha256` and hold the decoded account key, e.g. from `SecretKey::from_base64`;
+    /// when `keys` is empty the middleware's secret is used. `x-ms-date`, or `Date`, must be within
+    /// `timestamp_max_age`. Responses are signed in the HMAC header as usual.
+    AzureSharedKey,
+
+    /// Hawk authentication mode
+    ///
+    /// Requests are verified against a `Hawk` `Authorization` header instead of the HMAC header.
+    /// The `id` attribute names the key in `keys`, `ts` must be within `timestamp_max_age`, and
+    /// the `nonce` may not be reused with the same id. With the `content-digest` feature a `hash`
+    /// attribute is checked against the body. Responses are signed in the HMAC header as usual.
+    Hawk,
+
+    /// Stripe webhook signature mode
+    ///
+    /// Requests are verified against the `Stripe-Signature` header instead of the HMAC header,
+    /// with the middleware's secret. Its timestamp must be within `timestamp_max_age`, and any of
+    /// several `v1` signatures may match. Responses are signed in the HMAC header as usual.
+    Stripe,
+
+    /// Mailgun webhook signature mode
+    ///
+    /// Requests are verified against the `timestamp`, `token`, and `signature` fields of a JSON
+    /// or form encoded body instead of the HMAC header, with the middleware's secret. The timestamp
+    /// must be within `timestamp_max_age` and a token may not be reused. Responses are signed in
+    /// the HMAC header as usual.
+    Mailgun,
+
+    /// GitLab secret token mode
+    ///
+    /// Requests are verified by comparing the `X-Gitlab-Token` header with the middleware's
+    /// secret instead of by an HMAC. Responses are signed in the HMAC header as usual.
+    GitLabToken,
+
+    /// GitLab signing token mode
+    ///
+    /// Requests are verified against GitLab's `webhook-id`, `webhook-timestamp`, and
+    /// `webhook-signature` headers instead of the HMAC header, with the middleware's secret. The
+    /// timestamp must be within `timestamp_max_age`, any of several `v1` signatures may match,
+    /// and a message id may not be reused. Responses are signed in the HMAC header as usual.
+    GitLabSigningToken,
+}
+
+impl Default for Scheme {
+    fn default() -> Scheme {
+        Scheme::Hmac
+    }
+}
+
+/// Optional middleware behavior
+///
+/// Start from `Config::default()`, which matches the behavior of `middleware` apart from
+/// rejecting short secrets, and override the fields of interest.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Encoding of the MACs in the HMAC header of requests and responses; hex if not set
+    pub encoding: SignatureEncoding,
+
+    /// Accept request MACs in hex or base64 regardless of `encoding`
+    ///
+    /// The encoding of each request MAC is guessed with `SignatureEncoding::detect`. Responses are
+    /// still encoded with `encoding`. This eases migrating clients from one encoding to another.
+    pub lenient_decoding: bool,
+
+    /// Prefix of the MACs in the HMAC header, e.g. `sha256=` for GitHub style clients
+    ///
+    /// The prefix is stripped from request MACs when present and prepended to response MACs.
+    pub signature_prefix: Option<String>,
+
+    /// Other headers that may carry the request MAC, in order of preference, e.g. `x-signature`
+    ///
+    /// A request without the HMAC header is verified against the first of these it carries,
+    /// which helps while clients migrate from one header name to another. Responses are not signed
+    /// in these headers.
+    pub fallback_hmac_headers: Vec<String>,
+
+    /// Handling of requests carrying more than one of the HMAC header and `fallback_hmac_headers`
+    ///
+    /// Defaults to `HeaderConflict::PreferFirst`. Requests rejected by the policy fail with
+    /// `Error::MalformedSignature`.
+    pub header_conflict: HeaderConflict,
+
+    /// Handling of requests carrying the HMAC header, or a fallback header, more than once
+    ///
+    /// Defaults to `DuplicateHeaders::Reject`, failing with `Error::MalformedSignature`. A version
+    /// prefix is read from the first value only. Headers read as a single value, such as the key
+    /// id, version, algorithm, timestamp, nonce, and `Authorization` headers, are held to the same
+    /// policy, except that they are only accepted more than once with equal values.
+    pub duplicate_headers: DuplicateHeaders,
+
+    /// Header the response MAC is set in, e.g. `x-response-hmac`; the HMAC header if not set
+    ///
+    /// With this, the HMAC header passed to the constructor only carries request MACs.
+    pub response_hmac_header: Option<String>,
+
+    /// Secret responses are signed with instead of the secret the request was verified with
+    ///
+    /// Use this when the party receiving responses issued its own key. The algorithm is still
+    /// the one the request selected.
+    pub response_secret: Option<SecretKey>,
+
+    /// Pass responses through without a MAC
+    ///
+    /// Use this when nobody verifies responses, e.g. for webhooks, whose senders ignore them.
+    pub unsigned_responses: bool,
+
+    /// Response header carrying the `KeyFingerprint` of verified requests, e.g.
+    /// `x-hmac-key-fingerprint`
+    pub fingerprint_header: Option<String>,
+
+    /// Read the HMAC header as a list of signature parameters
+    ///
+    /// The header value looks like `keyId=abc,alg=hmac-sha256,ts=1699999999,nonce=f81d,sig=7f2c`.
+    /// `sig` carries the MAC, and `keyId`, `alg`, `ts`, and `nonce` are used as if sent in
+    /// `key_id_header`, `algorithm_header`, `timestamp_header`, and `nonce_header` respectively,
+    /// replacing any such header. Each parameter is ignored unless its header is configured.
+    /// Responses carry the bare MAC.
+    pub structured_signature: bool,
+
+    /// Accept the MAC in the `Authorization` header under this scheme token, e.g. `HMAC`
+    ///
+    /// The credentials are `<key id>:<mac>`, or just the MAC, e.g. `Authorization: HMAC
+    /// partner:7f2c...`. The key id is used as if sent in `key_id_header`, and ignored if that is
+    /// not configured. Requests without such an `Authorization` header may still use the HMAC
+    /// header, which also carries the response MAC. This helps when proxies strip custom headers.
+    pub authorization_scheme: Option<String>,
+
+    /// Verify and emit only the leading bytes of each MAC
+    ///
+    /// Following RFC 2104, the length must be at least 10 bytes and at least half of the digest
+    /// output length.
+    pub truncate: Option<usize>,
+
+    /// Header in which clients may declare the algorithm they signed with, e.g. `x-hmac-alg`
+    ///
+    /// When a request carries the header, its value must be the `Algorithm::name` of one of
+    /// `allowed_algorithms`. That algorithm is then used to verify the request and to sign the
+    /// response, whose algorithm header is set accordingly. Requests without the header are
+    /// handled with the middleware's own digest.
+    pub algorithm_header: Option<String>,
+
+    /// Algorithms clients may select through `algorithm_header`
+    pub allowed_algorithms: Vec<Algorithm>,
+
+    /// Put OpenSSL into FIPS mode and refuse to construct the middleware otherwise
+    ///
+    /// Requires the `hmac-openssl-fips` feature and a digest computed by `Backend::OpenSsl`.
+    /// Construction fails with `Error::FipsUnavailable` if either is missing or OpenSSL cannot
+    /// enter FIPS mode.
+    pub fips: bool,
+
+    /// Header in which clients name the key they signed with, e.g. `x-hmac-key-id`
+    ///
+    /// When a request carries the header, its value must be a key id in `keys`. That key's secret
+    /// and algorithm are then used to verify the request and to sign the response, taking
+    /// precedence over `algorithm_header`. Requests naming an unknown key are rejected; requests
+    /// without the header are handled with the middleware's own secret and digest.
+    pub key_id_header: Option<String>,
+
+    /// Keys clients may select through `key_id_header`, by key id
+    pub keys: HashMap<String, Key>,
+
+    /// Keys clients may select by id when not in `keys`, e.g. a `MemorySecretStore`
+    ///
+    /// The store is consulted on every request, so keys can be added or revoked while the server
+    /// runs. Unlike `keys`, its keys are not checked against `fips` and `truncate` on
+    /// construction.
+    pub secret_store: Option<Arc<dyn SecretStore>>,
+
+    /// Secrets requests may also be signed with while clients move to the middleware's secret
+    ///
+    /// When a request verified with the middleware's secret does not match, each of these is
+    /// tried in turn, so a new secret can be rolled out to clients over days. Responses are
+    /// signed with the middleware's secret. Keys selected by id, the secrets of
+    /// `secret_provider`, and the standard signing schemes are not affected.
+    pub previous_secrets: Vec<SecretKey>,
+
+    /// Source of the secret of each request, replacing the middleware's own secret
+    ///
+    /// The provider is asked for the secret of every request, including to sign its response,
+    /// with the key id the request named if that is not in `keys` or `secret_store`. Requests
+    /// for which it has no secret are rejected with `Error::NoSecret`, or `Error::UnknownKeyId`
+    /// if they named a key. The middleware's digest is used with the secret.
+    pub secret_provider: Option<Arc<dyn SecretProvider>>,
+
+    /// Secrets replacing the middleware's own secret and `previous_secrets`, which can be changed
+    /// while the server runs
+    ///
+    /// Set by `HmacAuthentication::with_secret_rotation`, which returns the handle. Keys selected
+    /// by id and the secrets of `secret_provider` take precedence.
+    pub secret_rotation: Option<Arc<SecretRotation>>,
+
+    /// Public key of the clients, whose signatures are verified instead of an HMAC
+    ///
+    /// The signed message is built by the middleware's canonicalizer from this configuration, see
+    /// `SignatureScheme`, and replay protection applies as configured. Responses are signed if the
+    /// scheme holds a private key and passed through unsigned otherwise. The middleware needs no
+    /// secret of its own, and only `Scheme::Hmac` is affected.
+    pub public_key: Option<Arc<dyn SignatureScheme>>,
+
+    /// Smallest secret length in bytes accepted on construction; 32 bytes if not set
+    ///
+    /// The middleware's secret, `keys`, `previous_secrets`, and `response_secret` are checked,
+    /// failing with `Error::InvalidKey`. Short secrets weaken the MAC, as RFC 2104 notes. Set
+    /// `Some(0)` to accept any secret, e.g. one issued by a third party.
+    pub min_secret_len: Option<usize>,
+
+    /// Service requests are signed for with a key derived per day, e.g. `billing`
+    ///
+    /// When set, clients sign with `SecretKey::date_scoped` for the current UTC date and this
+    /// service instead of the secret itself, like the credential scope of AWS Signature Version 4.
+    /// A leaked derived key is useless for other services and expires at midnight UTC, without
+    /// distributing new secrets. Keys of the dates within `date_max_skew` of the current time are
+    /// accepted, and responses are signed with the key of the current date.
+    pub date_scoped_service: Option<String>,
+
+    /// Header carrying the time the request was signed, in seconds since the Unix epoch, e.g.
+    /// `x-hmac-timestamp`
+    ///
+    /// When set, requests must carry the header, it is covered by the request HMAC as if it were
+    /// the first of `signed_headers`, and requests whose timestamp is more than
+    /// `timestamp_max_age` away from the current time are rejected. Without this, a captured
+    /// request can be replayed forever.
+    pub timestamp_header: Option<String>,
+
+    /// Freshness window for `timestamp_header`; five minutes if not set
+    pub timestamp_max_age: Option<Duration>,
+
+    /// Header carrying a unique value per request, e.g. `x-hmac-nonce`
+    ///
+    /// When set, requests must carry the header, it is covered by the request HMAC after
+    /// `timestamp_header`, and requests reusing a nonce seen within twice `timestamp_max_age` are
+    /// rejected. Combine this with `timestamp_header` so that requests older than the nonces
+    /// remembered are rejected as well.
+    pub nonce_header: Option<String>,
+
+    /// Require the standard `Date` header and reject requests whose date is off by more than
+    /// `date_max_skew`
+    ///
+    /// The header is covered by the request HMAC after `nonce_header`. This provides replay
+    /// protection for clients that can only set standard headers.
+    pub require_date: bool,
+
+    /// Allowed difference between `Date` and the current time; five minutes if not set
+    pub date_max_skew: Option<Duration>,
+
+    /// Header carrying a client chosen key identifying a logical operation across retries, e.g.
+    /// `idempotency-key`
+    ///
+    /// When set, requests must carry a non-empty value, the header is covered by the request HMAC
+    /// after the `Date` header, and the verified value is available to handlers as the
+    /// `IdempotencyKey` request extension. Deduplication is left to the handler.
+    pub idempotency_key_header: Option<String>,
+
+    /// Header carrying the time after which the request is no longer valid, in seconds since the
+    /// Unix epoch, e.g. `x-hmac-expires`
+    ///
+    /// When set, requests must carry the header, it is covered by the request HMAC after
+    /// `idempotency_key_header`, and requests received after the expiry are rejected. Unlike
+    /// `timestamp_header`, this lets the signer mint a signature well ahead of its use.
+    pub expires_header: Option<String>,
+
+    /// Normalization applied to requests before the request HMAC is computed
+    pub normalization: Normalization,
+
+    /// Whether and how the body is covered by the request HMAC
+    ///
+    /// Defaults to `BodySigning::Full`. Under the other modes the body is not buffered, and
+    /// `hmac(body)` is dropped from the inner HMACs or replaced by `hmac(content-digest)`.
+    pub body: BodySigning,
+
+    /// Whether and how the query string is covered by the request HMAC
+    ///
+    /// Defaults to `QuerySigning::Ignore`, under which `?amount=10` and `?amount=10000` verify
+    /// identically. New deployments should use `QuerySigning::Raw`, for which clients sign
+    /// `path?query` in place of the path when the request has a query string.
+    pub query: QuerySigning,
+
+    /// Request headers whose values are covered by the request HMAC, e.g. `content-type`
+    ///
+    /// When not empty, the HMAC of the header block is appended to the inner HMACs:
+    /// `hmac(hmac(method) + hmac(path) + hmac(body) + hmac(headers))`. The block has one
+    /// `name:value\n` line per header in the order given here, with the name lowercased and the
+    /// value trimmed; multiple values are joined by `, ` and a missing header has an empty value.
+    pub signed_headers: Vec<String>,
+
+    /// Cover the `Content-Type` and `Content-Length` headers
+    ///
+    /// This is shorthand for listing `content-type` and `content-length` ahead of
+    /// `signed_headers`, but after the headers implied by `timestamp_header`, `nonce_header`,
+    /// `require_date`, `idempotency_key_header`, and `expires_header`, as api-auth style signing
+    /// schemes require.
+    pub sign_content: bool,
+
+    /// Cover the request's authority (the `Host` header, e.g. `api.example.com:8443`)
+    ///
+    /// The HMAC of the lowercased authority is appended to the inner HMACs, after that of the
+    /// header block, so a signature made for one host does not verify on another.
+    pub sign_host: bool,
+
+    /// Cover the scheme and port the request was received on, as `scheme:port`, e.g. `https:443`
+    ///
+    /// The port is the default for the scheme unless the request URL names another. The HMAC is
+    /// appended to the inner HMACs after that of the authority, so signatures made for one
+    /// listener do not verify on another.
+    pub sign_scheme_and_port: bool,
+
+    /// Cover the client's IP address, as determined by the given resolver
+    ///
+    /// The HMAC of the address, e.g. `10.0.0.1` or `::1`, is appended to the inner HMACs after
+    /// that of the scheme and port, so a signature is only valid from the address it was made
+    /// for. Use `RemoteAddr` for direct connections and `ForwardedFor` behind proxies. Requests
+    /// whose address cannot be resolved are rejected.
+    pub client_addr: Option<Arc<dyn ClientAddrResolver>>,
+
+    /// How requests are authenticated; `Scheme::Hmac` if not set
+    pub scheme: Scheme,
+
+    /// Canonicalization options of a legacy scheme accepted while clients migrate
+    ///
+    /// When a request HMAC does not match, it is computed again with the `normalization`, `query`,
+    /// header, host, and client address options of this configuration and accepted if that
+    /// matches; its other options are ignored. Verified requests carry the
+    /// `MatchedCanonicalization` extension telling which scheme matched. Responses are signed the
+    /// same way under either scheme.
+    pub legacy: Option<Box<Config>>,
+
+    /// Versions of the signing scheme clients may declare, by name, e.g. `v2`
+    ///
+    /// Clients declare a version by prefixing the HMAC header value with its name and `=`, e.g.
+    /// `v2=7f2c...`, or through `version_header`. Requests declaring an unknown version are
+    /// rejected; requests declaring none are handled with this configuration. The algorithm of a
+    /// key selected through `key_id_header` takes precedence over that of the version.
+    pub versions: HashMap<String, Version>,
+
+    /// Header in which clients may declare the version they signed with, e.g. `x-hmac-version`
+    ///
+    /// Takes precedence over a version prefix on the HMAC header value.
+    pub version_header: Option<String>,
+}
+
+impl Config {
+    /// Check the configuration against the digest and secret it will be used with
+    ///
+    /// Without a secret provider, the middleware needs a secret of its own or a rotation.
+    pub(crate) fn validate<D>(&self, digest: &D, secret: Option<&SecretKey>) -> Result<()>
+        where D: HmacDigest
+    {
+        if self.fips {
+            let keys_capable = self.keys.values().all(|key| key.algorithm.fips_capable());
+            let versions_capable = self.versions.values().all(|version| {
+                version.algorithm.map_or(true, |algorithm| algorithm.fips_capable())
+            });
+            if !digest.fips_capable() || !keys_capable || !versions_capable {
+                return Err(Error::FipsUnavailable);
+            }
+
+            try!(fips::enable());
+        }
+
+        if self.secret_provider.is_none() {
+            match secret {
+                Some(secret) => try!(self.check_secret_len("secret", secret)),
+                None if self.secret_rotation.is_some() || self.public_key.is_some() => (),
+                None => return Err(Error::NoSecret)
+            }
+        }
+        for (id, key) in &self.keys {
+            try!(self.check_secret_len(&format!("key {}", id), &key.secret));
+        }
+        for secret in self.previous_secrets.iter().chain(self.response_secret.as_ref()) {
+            try!(self.check_secret_len("secret", secret));
+        }
+
+        if let Some(len) = self.truncate {
+            try!(check_truncation(len, digest.output_len()));
+
+            for key in self.keys.values() {
+                try!(check_truncation(len, key.algorithm.output_len()));
+            }
+
+            for algorithm in self.versions.values().filter_map(|version| version.algorithm) {
+                try!(check_truncation(len, algorithm.output_len()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check `secret` against `min_secret_len`
+    fn check_secret_len(&self, name: &str, secret: &SecretKey) -> Result<()> {
+        let min = self.min_secret_len.unwrap_or(DEFAULT_MIN_SECRET_LEN);
+        if secret.len() < min {
+            return Err(Error::InvalidKey(format!("{} is {} bytes, shorter than the minimum of {}",
+                                                 name, secret.len(), min)));
+        }
+
+        Ok(())
+    }
+
+    /// Names of the request headers covered by the request HMAC
+    pub(crate) fn header_names(&self) -> Vec<String> {
+        let mut names = Vec::with_capacity(self.signed_headers.len() + 7);
+        if let Some(ref header) = self.timestamp_header {
+            names.push(header.clone());
+        }
+        if let Some(ref header) = self.nonce_header {
+            names.push(header.clone());
+        }
+        if self.require_date {
+            names.push("date".to_owned());
+        }
+        if let Some(ref header) = self.idempotency_key_header {
+            names.push(header.clone());
+        }
+        if let Some(ref header) = self.expires_header {
+            names.push(header.clone());
+        }
+        if self.sign_content {
+            names.push("content-type".to_owned());
+            names.push("content-length".to_owned());
+        }
+        names.extend(self.signed_headers.iter().cloned());
+        names
+    }
+
+    /// Apply the configured truncation to a computed MAC
+    pub(crate) fn truncate(&self, mut mac: Vec<u8>) -> Vec<u8> {
+        if let Some(len) = self.truncate {
+            mac.truncate(len);
+        }
+
+        mac
+    }
+}
+
+/// Check a truncation length against a digest output length
+fn check_truncation(len: usize, output_len: usize) -> Result<()> {
+    if len < MIN_TRUNCATED_LEN || len < output_len / 2 || len > output_len {
+        return Err(Error::InvalidTruncation(len));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Verification of `Content-Digest` (RFC 9530) and legacy `Digest` (RFC 3230) headers
+
+use iron;
+use rustc_serialize::base64::FromBase64;
+use sha2::{Digest, Sha256, Sha512};
+
+use error::{Error, Result};
+use util;
+
+/// Check the request's content digest against `body` and return the header value
+///
+/// `Content-Digest` is preferred over `Digest`. Entries for algorithms other than SHA-256 and
+/// SHA-512 are ignored, but at least one must be present and all supported entries must match.
+pub fn verify(req: &iron::Request, body: &[u8]) -> Result<String> {
+    let (value, structured) = match req.headers.get_raw("content-digest") {
+        Some(value) => (value, true),
+        None => match req.headers.get_raw("digest") {
+            Some(value) => (value, false),
+            None => return Err(invalid("missing Content-Digest header"))
+        }
+    };
+    let value = try!(::std::str::from_utf8(&value[0][..])).trim().to_owned();
+
+    let mut verified = false;
+    for entry in value.split(',') {
+        let mut parts = entry.trim().splitn(2, '=');
+        let algorithm = parts.next().unwrap_or("").to_lowercase();
+        let encoded = parts.next().unwrap_or("");
+        let encoded = if structured { encoded.trim_matches(':') } else { encoded };
+
+        let computed = match &algorithm[..] {
+            "sha-256" => Sha256::digest(body).to_vec(),
+            "sha-512" => Sha512::digest(body).to_vec(),
+            _ => continue
+        };
+
+        let supplied = match encoded.from_base64() {
+            Ok(supplied) => supplied,
+            Err(_) => return Err(invalid("malformed digest"))
+        };
+
+        if computed.len() != supplied.len() || !util::contant_time_equals(&computed, &supplied) {
+            return Err(invalid("digest does not match the body"));
+        }
+        verified = true;
+    }
+
+    if !verified {
+        return Err(invalid("no supported digest algorithm"));
+    }
+
+    Ok(value)
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidContentDigest(reason.to_owned())
+}
@@ -0,0 +1,70 @@
+//! Text encodings of MACs in headers
+
+use rustc_serialize::base64::{self, FromBase64, ToBase64};
+
+use error::Result;
+use util;
+
+/// How MACs are encoded in the request and response headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    /// Lowercase hex; uppercase is accepted on requests
+    ///
+    /// This is the default.
+    Hex,
+    /// Uppercase hex, for clients that compare response MACs case sensitively
+    ///
+    /// Requests are decoded as with `Hex`.
+    UpperHex,
+    /// Standard base64 with padding
+    ///
+    /// Requests may use either base64 alphabet, with or without padding.
+    Base64,
+    /// URL-safe base64 without padding (RFC 4648 section 5)
+    ///
+    /// Requests are decoded as with `Base64`.
+    Base64Url,
+}
+
+impl Default for SignatureEncoding {
+    fn default() -> SignatureEncoding {
+        SignatureEncoding::Hex
+    }
+}
+
+impl SignatureEncoding {
+    /// Guess the encoding of a MAC supplied in a request header
+    ///
+    /// A string of an even number of hex digits is taken to be hex and anything else base64. The
+    /// base64 encoding of a MAC consists only of hex digits with negligible probability.
+    pub fn detect(encoded: &str) -> SignatureEncoding {
+        let is_hex = encoded.len() % 2 == 0 && encoded.chars().all(|c| c.is_digit(16));
+        if is_hex {
+            SignatureEncoding::Hex
+        } else {
+            SignatureEncoding::Base64
+        }
+    }
+
+    /// Encode `mac` for a response header
+    pub fn encode(&self, mac: &[u8]) -> String {
+        match *self {
+            SignatureEncoding::Hex => util::to_hex(mac),
+            SignatureEncoding::UpperHex => util::to_hex(mac).to_uppercase(),
+            SignatureEncoding::Base64 => mac.to_base64(base64::STANDARD),
+            SignatureEncoding::Base64Url => mac.to_base64(base64::URL_SAFE),
+        }
+    }
+
+    /// Decode a MAC supplied in a request header
+    pub fn decode(&self, encoded: &str) -> Result<Vec<u8>> {
+        match *self {
+            SignatureEncoding::Hex | SignatureEncoding::UpperHex => {
+                util::from_hex(encoded.as_bytes())
+            },
+            SignatureEncoding::Base64 | SignatureEncoding::Base64Url => {
+                Ok(try!(encoded.from_base64()))
+            },
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use std::convert::Infallible;
 use std::fmt;
 use std::io;
 use iron::{IronError, status};
 use std::str::Utf8Error;
 
+use rustc_serialize::base64::FromBase64Error;
 use rustc_serialize::hex::FromHexError;
 
 /// Error type for the hmac middleware
@@ -20,6 +22,51 @@ pub enum Error {
     Utf8Error(Utf8Error),
     /// Error decoding hex
     DecodingHex(FromHexError),
+    /// Error decoding base64
+    DecodingBase64(FromBase64Error),
+    /// The configured MAC truncation length is too short or exceeds the digest length
+    InvalidTruncation(usize),
+    /// The request declared an unknown or disallowed algorithm. The String value contains the
+    /// declared name.
+    UnsupportedAlgorithm(String),
+    /// Key material could not be parsed. The String value describes the problem.
+    InvalidKey(String),
+    /// FIPS mode was requested but is not available
+    FipsUnavailable,
+    /// The request named a key id that is not configured. The String value contains the key id.
+    UnknownKeyId(String),
+    /// The request is outside the freshness window or lacks the data to tell. The String value
+    /// describes the problem.
+    StaleRequest(String),
+    /// The request's nonce is missing or was already used. The String value describes the problem.
+    ReplayedRequest(String),
+    /// The replay cache could not be reached. The String value describes the problem.
+    ReplayCacheUnavailable(String),
+    /// The client address could not be determined. The String value describes the problem.
+    UnresolvedClientAddr(String),
+    /// The required idempotency key header is missing or empty. The String value contains the
+    /// expected header name.
+    MissingIdempotencyKey(String),
+    /// The request declared an unknown signing scheme version. The String value contains the
+    /// declared version.
+    UnsupportedVersion(String),
+    /// A URL to be signed could not be parsed. The String value describes the problem.
+    InvalidUrl(String),
+    /// The request's content digest is missing, malformed, or does not match the body. The String
+    /// value describes the problem.
+    InvalidContentDigest(String),
+    /// A signature header of a standard signing scheme could not be parsed or lacks required
+    /// parts. The String value describes the problem.
+    MalformedSignature(String),
+    /// The secret provider has no secret for the request
+    NoSecret,
+    /// The request named a key outside its validity window. The String value contains the key id.
+    KeyNotValid(String),
+    /// A secret backend could not be reached or gave an unusable response. The String value
+    /// describes the problem.
+    SecretBackendUnavailable(String),
+    /// The HMAC backend failed to compute an HMAC. The String value describes the problem.
+    HmacFailed(String),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -33,6 +80,35 @@ impl fmt::Display for Error {
             Error::Bodyparser(ref err) => write!(f, "Bodyparser({})", err),
             Error::Utf8Error(ref err) => write!(f, "Utf8Error({})", err),
             Error::DecodingHex(ref err) => write!(f, "DecodingHex({})", err),
+            Error::DecodingBase64(ref err) => write!(f, "DecodingBase64({})", err),
+            Error::InvalidTruncation(len) => write!(f, "Invalid MAC truncation length ({})", len),
+            Error::UnsupportedAlgorithm(ref name) => write!(f, "Unsupported algorithm ({})", name),
+            Error::InvalidKey(ref reason) => write!(f, "Invalid key ({})", reason),
+            Error::FipsUnavailable => write!(f, "FIPS mode is unavailable"),
+            Error::UnknownKeyId(ref id) => write!(f, "Unknown key id ({})", id),
+            Error::StaleRequest(ref reason) => write!(f, "Stale request ({})", reason),
+            Error::ReplayedRequest(ref reason) => write!(f, "Replayed request ({})", reason),
+            Error::ReplayCacheUnavailable(ref reason) => {
+                write!(f, "Replay cache unavailable ({})", reason)
+            },
+            Error::UnresolvedClientAddr(ref reason) => {
+                write!(f, "Unresolved client address ({})", reason)
+            },
+            Error::MissingIdempotencyKey(ref key) => {
+                write!(f, "Missing idempotency key (key = {})", key)
+            },
+            Error::UnsupportedVersion(ref name) => write!(f, "Unsupported version ({})", name),
+            Error::InvalidUrl(ref reason) => write!(f, "Invalid URL ({})", reason),
+            Error::InvalidContentDigest(ref reason) => {
+                write!(f, "Invalid content digest ({})", reason)
+            },
+            Error::MalformedSignature(ref reason) => write!(f, "Malformed signature ({})", reason),
+            Error::NoSecret => write!(f, "No secret for the request"),
+            Error::KeyNotValid(ref id) => write!(f, "Key not valid ({})", id),
+            Error::SecretBackendUnavailable(ref reason) => {
+                write!(f, "Secret backend unavailable ({})", reason)
+            },
+            Error::HmacFailed(ref reason) => write!(f, "HMAC computation failed ({})", reason),
         }
     }
 }
@@ -46,15 +122,35 @@ impl ::std::error::Error for Error {
             Error::Bodyparser(ref err) => err.description(),
             Error::Utf8Error(ref err) => err.description(),
             Error::DecodingHex(ref err) => err.description(),
+            Error::DecodingBase64(ref err) => err.description(),
+            Error::InvalidTruncation(_) => "Invalid MAC truncation length",
+            Error::UnsupportedAlgorithm(_) => "The requested algorithm is not supported",
+            Error::InvalidKey(_) => "Key material could not be parsed",
+            Error::FipsUnavailable => "FIPS mode is unavailable",
+            Error::UnknownKeyId(_) => "The requested key id is not configured",
+            Error::StaleRequest(_) => "The request is outside the freshness window",
+            Error::ReplayedRequest(_) => "The request's nonce is missing or was already used",
+            Error::ReplayCacheUnavailable(_) => "The replay cache could not be reached",
+            Error::UnresolvedClientAddr(_) => "The client address could not be determined",
+            Error::MissingIdempotencyKey(_) => "The required idempotency key is missing",
+            Error::UnsupportedVersion(_) => "The requested scheme version is not supported",
+            Error::InvalidUrl(_) => "The URL could not be parsed",
+            Error::InvalidContentDigest(_) => "The content digest is missing or invalid",
+            Error::MalformedSignature(_) => "The signature could not be parsed",
+            Error::NoSecret => "The secret provider has no secret for the request",
+            Error::KeyNotValid(_) => "The requested key is not yet valid or was retired",
+            Error::SecretBackendUnavailable(_) => "The secret backend could not be reached",
+            Error::HmacFailed(_) => "The HMAC backend failed to compute an HMAC",
         }
     }
 
-    fn cause(&self) -> Option<&::std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         match *self {
             Error::IoError(ref err) => Some(err),
             Error::Bodyparser(ref err) => Some(err),
             Error::Utf8Error(ref err) => Some(err),
             Error::DecodingHex(ref err) => Some(err),
+            Error::DecodingBase64(ref err) => Some(err),
             _ => None
         }
     }
@@ -65,12 +161,30 @@ impl From<Error> for IronError {
         match err {
             Error::MissingHmacHeader(_) => IronError::new(err, status::BadRequest),
             Error::InvalidHmac => IronError::new(err, status::Forbidden),
+            Error::UnsupportedAlgorithm(_) => IronError::new(err, status::BadRequest),
             Error::DecodingHex(_) => IronError::new(err, status::Forbidden),
+            Error::DecodingBase64(_) => IronError::new(err, status::Forbidden),
+            Error::UnknownKeyId(_) => IronError::new(err, status::Forbidden),
+            Error::StaleRequest(_) => IronError::new(err, status::Forbidden),
+            Error::ReplayedRequest(_) => IronError::new(err, status::Forbidden),
+            Error::UnresolvedClientAddr(_) => IronError::new(err, status::Forbidden),
+            Error::MissingIdempotencyKey(_) => IronError::new(err, status::BadRequest),
+            Error::UnsupportedVersion(_) => IronError::new(err, status::BadRequest),
+            Error::InvalidContentDigest(_) => IronError::new(err, status::Forbidden),
+            Error::MalformedSignature(_) => IronError::new(err, status::BadRequest),
+            Error::NoSecret => IronError::new(err, status::Forbidden),
+            Error::KeyNotValid(_) => IronError::new(err, status::Forbidden),
             _ => IronError::new(err, status::InternalServerError)
         }
     }
 }
 
+impl From<Infallible> for Error {
+    fn from(err: Infallible) -> Error {
+        match err {}
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::IoError(err)
@@ -94,3 +208,9 @@ impl From<FromHexError> for Error {
         Error::DecodingHex(err)
     }
 }
+
+impl From<FromBase64Error> for Error {
+    fn from(err: FromBase64Error) -> Error {
+        Error::DecodingBase64(err)
+    }
+}
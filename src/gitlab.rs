@@ -0,0 +1,110 @@
+//! GitLab webhook secret and signing tokens
+//!
+//! Deliveries carry the webhook's secret token verbatim in `X-Gitlab-Token`. Newer GitLab versions
+//! can sign deliveries with a signing token instead: they carry `webhook-id`,
+//! `webhook-timestamp`, and `webhook-signature: v1,<base64>` headers, where the signature is the
+//! HMAC-SHA256 of `<id>.<timestamp>.<body>`. The signature header may list several space separated
+//! signatures while the token is rotated.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::base64::FromBase64;
+
+use canonical::CanonicalRequest;
+use error::{Error, Result};
+use replay;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, SecretKey, Verifier};
+
+/// Header carrying the secret token
+const TOKEN_HEADER: &'static str = "x-gitlab-token";
+
+/// Prefix of signing tokens in their text form
+const SIGNING_TOKEN_PREFIX: &'static str = "whsec_";
+
+/// Check the `X-Gitlab-Token` header of `req` against the secret of `auth`
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let token = match req.headers.get_raw(TOKEN_HEADER) {
+        Some(value) => &value[0][..],
+        None => forbidden!(Error::MissingHmacHeader(TOKEN_HEADER.to_owned()))
+    };
+
+    let secret = try!(auth.secret_for(req, None));
+    if token.len() != secret.len() || !auth.inner.verifier.verify(token, &secret[..]) {
+        forbidden!();
+    }
+
+    Ok(())
+}
+
+/// Decode a `whsec_` prefixed base64 signing token
+pub fn decode_signing_token(token: &str) -> Result<SecretKey> {
+    let token = token.trim();
+    SecretKey::from_base64(if token.starts_with(SIGNING_TOKEN_PREFIX) {
+        &token[SIGNING_TOKEN_PREFIX.len()..]
+    } else {
+        token
+    })
+}
+
+/// Verify the signature of a delivery signed with the signing token of `auth`
+///
+/// The timestamp must be within `Config::timestamp_max_age`, any `v1` signature may match, and a
+/// message id may not be reused.
+pub fn verify_signed<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let id = try!(header(req, "webhook-id"));
+    let timestamp = try!(header(req, "webhook-timestamp"));
+    let signatures = try!(header(req, "webhook-signature"));
+
+    let body = try!(CanonicalRequest::from_request(req)).body;
+    let mac = try!(auth.keyed_mac(req, None, None));
+    let computed = try!(mac(format!("{}.{}.{}", id, timestamp, body).as_bytes()));
+
+    let matched = signatures.split_whitespace()
+                            .filter_map(|signature| {
+                                let mut parts = signature.splitn(2, ',');
+                                match (parts.next(), parts.next()) {
+                                    (Some("v1"), Some(encoded)) => encoded.from_base64().ok(),
+                                    _ => None
+                                }
+                            })
+                            .any(|supplied| {
+                                computed.len() == supplied.len() &&
+                                    auth.inner.verifier.verify(&computed[..], &supplied[..])
+                            });
+    if !matched {
+        forbidden!();
+    }
+
+    let seconds: u64 = match timestamp.parse() {
+        Ok(seconds) => seconds,
+        Err(_) => return Err(Error::StaleRequest(format!("invalid timestamp {}", timestamp)).into())
+    };
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), max_age));
+
+    if !try!(auth.inner.nonces.insert_if_absent(&id, max_age * 2)) {
+        return Err(Error::ReplayedRequest(format!("message {} was already delivered", id)).into());
+    }
+
+    Ok(())
+}
+
+/// The trimmed value of a required header
+fn header(req: &iron::Request, name: &str) -> Result<String> {
+    match req.headers.get_raw(name) {
+        Some(value) => Ok(try!(::std::str::from_utf8(&value[0][..])).trim().to_owned()),
+        None => Err(Error::MissingHmacHeader(name.to_owned()))
+    }
+}
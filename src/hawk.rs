@@ -0,0 +1,143 @@
+//! Hawk authentication
+//!
+//! Requests carry `Authorization: Hawk id="...", ts="...", nonce="...", mac="..."` with optional
+//! `hash`, `ext`, `app`, and `dlg` attributes. The `mac` is the base64 MAC of the normalized
+//! `hawk.1.header` string of the timestamp, nonce, method, resource, host, port, payload hash, and
+//! ext data.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::base64::FromBase64;
+use url;
+
+use error::{Error, Result};
+use http_signatures;
+use replay;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// Scheme of the `Authorization` header
+const SCHEME: &'static str = "Hawk";
+
+/// Verify the Hawk `Authorization` header of `req` with the keys of `auth`
+///
+/// The `id` attribute names the key in `Config::keys`, whose algorithm is used for the MAC.
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let attributes = try!(attributes(req));
+    let attribute = |name: &str| {
+        attributes.iter()
+                  .find(|&&(ref attribute, _)| attribute == name)
+                  .map(|&(_, ref value)| &value[..])
+    };
+    let required = |name: &str| {
+        attribute(name).ok_or_else(|| malformed(&format!("missing {} attribute", name)))
+    };
+
+    let id = try!(required("id"));
+    let ts = try!(required("ts"));
+    let nonce = try!(required("nonce"));
+    let supplied = try!(try!(required("mac")).from_base64().map_err(Error::from));
+
+    if let Some(hash) = attribute("hash") {
+        try!(check_payload_hash(req, hash));
+    }
+
+    let url: url::Url = req.url.clone().into();
+    let resource = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned()
+    };
+    let ext = attribute("ext").unwrap_or("").replace('\\', "\\\\").replace('\n', "\\n");
+
+    let mut normalized = format!("hawk.1.header\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n",
+                                 ts, nonce, req.method.as_ref().to_uppercase(), resource,
+                                 req.url.host().to_string().to_lowercase(), req.url.port(),
+                                 attribute("hash").unwrap_or(""), ext);
+    if let Some(app) = attribute("app") {
+        normalized.push_str(&format!("{}\n{}\n", app, attribute("dlg").unwrap_or("")));
+    }
+
+    let mac = try!(auth.keyed_mac(req, Some(id), None));
+    let computed = try!(mac(normalized.as_bytes()));
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+    let seconds: u64 = match ts.parse() {
+        Ok(seconds) => seconds,
+        Err(_) => return Err(Error::StaleRequest(format!("invalid timestamp {}", ts)).into())
+    };
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), max_age));
+
+    if !try!(auth.inner.nonces.insert_if_absent(&format!("{}:{}", id, nonce), max_age * 2)) {
+        return Err(Error::ReplayedRequest(format!("nonce {} was already used", nonce)).into());
+    }
+
+    Ok(())
+}
+
+/// The attributes of the Hawk `Authorization` header
+fn attributes(req: &iron::Request) -> Result<Vec<(String, String)>> {
+    let value = match req.headers.get_raw("authorization") {
+        Some(value) => try!(::std::str::from_utf8(&value[0][..])).trim().to_owned(),
+        None => return Err(malformed("missing Authorization header"))
+    };
+
+    let mut parts = value.splitn(2, ' ');
+    if parts.next() != Some(SCHEME) {
+        return Err(malformed("not a Hawk authorization"));
+    }
+
+    let mut attributes = Vec::new();
+    for member in http_signatures::split_members(parts.next().unwrap_or("")) {
+        let (name, value) = try!(http_signatures::split_member(member));
+        if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+            return Err(malformed(&format!("unquoted {} attribute", name)));
+        }
+        attributes.push((name.to_owned(), value[1..value.len() - 1].to_owned()));
+    }
+
+    Ok(attributes)
+}
+
+/// Check the `hash` attribute against the body
+///
+/// The payload hash is the base64 SHA-256 of `hawk.1.payload`, the content type without
+/// parameters, and the body, each followed by a newline.
+#[cfg(feature = "content-digest")]
+fn check_payload_hash(req: &mut iron::Request, hash: &str) -> Result<()> {
+    use rustc_serialize::base64::{self, ToBase64};
+    use sha2::{Digest, Sha256};
+
+    let content_type = match req.headers.get_raw("content-type") {
+        Some(value) => try!(::std::str::from_utf8(&value[0][..])).to_owned(),
+        None => String::new()
+    };
+    let content_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    let body = try!(::canonical::CanonicalRequest::from_request(req)).body;
+
+    let payload = format!("hawk.1.payload\n{}\n{}\n", content_type, body);
+    if Sha256::digest(payload.as_bytes())[..].to_base64(base64::STANDARD) != hash {
+        return Err(Error::InvalidContentDigest("payload hash does not match the body".to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Without the `content-digest` feature, the payload hash is covered but not checked
+#[cfg(not(feature = "content-digest"))]
+fn check_payload_hash(_req: &mut iron::Request, _hash: &str) -> Result<()> {
+    Ok(())
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedSignature(reason.to_owned())
+}
@@ -0,0 +1,68 @@
+//! HKDF subkey derivation (RFC 5869)
+
+use zeroize::Zeroize;
+
+use error::{Error, Result};
+use hmac::HmacDigest;
+use ::SecretKey;
+
+/// Derives per-purpose subkeys from one master secret with HKDF
+///
+/// Services sharing the master secret derive the same subkey for the same `info`, e.g.
+/// `b"request-signing"`, so only the master secret needs distributing. HKDF is defined over HMAC;
+/// with the native keyed hashes (`Blake2b`, `Blake3`) the same construction is used with the keyed
+/// hash in place of HMAC.
+#[derive(Debug, Clone)]
+pub struct Hkdf<D: HmacDigest> {
+    digest: D,
+    prk: SecretKey
+}
+
+impl<D: HmacDigest> Hkdf<D> {
+    /// Extract a pseudorandom key from `master` and `salt`
+    ///
+    /// An empty salt is equivalent to the all-zero salt of RFC 5869.
+    pub fn new<K: Into<SecretKey>>(digest: D, salt: &[u8], master: K) -> Hkdf<D> {
+        let prk = digest.hmac(&SecretKey::new(salt), &master.into()[..]);
+
+        Hkdf {
+            digest: digest,
+            prk: SecretKey::from_vec(prk)
+        }
+    }
+
+    /// Expand the pseudorandom key into a `len` byte subkey bound to `info`
+    ///
+    /// Returns an error if `len` exceeds 255 times the digest output length.
+    pub fn derive(&self, info: &[u8], len: usize) -> Result<SecretKey> {
+        let mut okm = Vec::with_capacity(len);
+        let mut block = Vec::new();
+        let mut counter = 1u8;
+
+        while okm.len() < len {
+            let mut input = Vec::with_capacity(block.len() + info.len() + 1);
+            input.extend_from_slice(&block[..]);
+            input.extend_from_slice(info);
+            input.push(counter);
+
+            block.zeroize();
+            block = try!(self.digest.try_hmac(&self.prk, &input[..]));
+            okm.extend_from_slice(&block[..]);
+
+            if counter == 255 && okm.len() < len {
+                return Err(Error::InvalidKey(format!("cannot derive {} bytes with HKDF", len)));
+            }
+            counter = counter.wrapping_add(1);
+        }
+
+        block.zeroize();
+        okm[len..].zeroize();
+        okm.truncate(len);
+        Ok(SecretKey::from_vec(okm))
+    }
+
+    /// The digest subkeys are derived with
+    pub fn digest(&self) -> &D {
+        &self.digest
+    }
+}
@@ -0,0 +1,132 @@
+use ::SecretKey;
+use error::Result;
+use super::try_hmac;
+
+#[cfg(not(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+              feature = "hmac-sha2", feature = "hmac-sodium")))]
+compile_error!("at least one HMAC backend feature must be enabled: hmac-rust-crypto, \
+                hmac-openssl, hmac-ring, hmac-sha2, or hmac-sodium");
+
+/// Crypto library used to compute SHA-2 HMACs
+///
+/// Any number of backend features may be enabled at once. Each enabled feature adds a variant,
+/// and the implementation is picked when constructing the middleware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// rust-crypto; requires the `hmac-rust-crypto` feature
+    #[cfg(feature = "hmac-rust-crypto")]
+    RustCrypto,
+    /// OpenSSL; requires the `hmac-openssl` feature
+    #[cfg(feature = "hmac-openssl")]
+    OpenSsl,
+    /// ring; requires the `hmac-ring` feature
+    #[cfg(feature = "hmac-ring")]
+    Ring,
+    /// RustCrypto hmac and sha2 crates; requires the `hmac-sha2` feature
+    #[cfg(feature = "hmac-sha2")]
+    Sha2,
+    /// libsodium; requires the `hmac-sodium` feature. Does not provide SHA-384.
+    #[cfg(feature = "hmac-sodium")]
+    Sodium,
+}
+
+/// Enabled backends in order of preference
+const BACKENDS: &'static [Backend] = &[
+    #[cfg(feature = "hmac-rust-crypto")]
+    Backend::RustCrypto,
+    #[cfg(feature = "hmac-openssl")]
+    Backend::OpenSsl,
+    #[cfg(feature = "hmac-ring")]
+    Backend::Ring,
+    #[cfg(feature = "hmac-sha2")]
+    Backend::Sha2,
+    #[cfg(feature = "hmac-sodium")]
+    Backend::Sodium,
+];
+
+impl Backend {
+    /// All backends compiled into this build, in order of preference
+    pub fn available() -> &'static [Backend] {
+        BACKENDS
+    }
+
+    /// Whether this backend can compute HMAC-SHA384
+    pub fn supports_sha384(&self) -> bool {
+        #[cfg(feature = "hmac-sodium")]
+        {
+            if *self == Backend::Sodium {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub(crate) fn hmac256(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "hmac-rust-crypto")]
+            Backend::RustCrypto => try_hmac::<super::rust_crypto::RustCryptoHmac256>(secret, data),
+            #[cfg(feature = "hmac-openssl")]
+            Backend::OpenSsl => try_hmac::<super::ssl::OpensslHmac256>(secret, data),
+            #[cfg(feature = "hmac-ring")]
+            Backend::Ring => try_hmac::<super::ring_hmac::RingHmac256>(secret, data),
+            #[cfg(feature = "hmac-sha2")]
+            Backend::Sha2 => try_hmac::<super::sha2_hmac::Sha2Hmac256>(secret, data),
+            #[cfg(feature = "hmac-sodium")]
+            Backend::Sodium => try_hmac::<super::sodium::SodiumHmac256>(secret, data),
+        }
+    }
+
+    /// Whether this backend can operate in FIPS mode
+    pub fn fips_capable(&self) -> bool {
+        #[cfg(feature = "hmac-openssl")]
+        {
+            if *self == Backend::OpenSsl {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+              feature = "hmac-sha2"))]
+    pub(crate) fn hmac384(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "hmac-rust-crypto")]
+            Backend::RustCrypto => try_hmac::<super::rust_crypto::RustCryptoHmac384>(secret, data),
+            #[cfg(feature = "hmac-openssl")]
+            Backend::OpenSsl => try_hmac::<super::ssl::OpensslHmac384>(secret, data),
+            #[cfg(feature = "hmac-ring")]
+            Backend::Ring => try_hmac::<super::ring_hmac::RingHmac384>(secret, data),
+            #[cfg(feature = "hmac-sha2")]
+            Backend::Sha2 => try_hmac::<super::sha2_hmac::Sha2Hmac384>(secret, data),
+            #[cfg(feature = "hmac-sodium")]
+            Backend::Sodium => {
+                Err(::Error::UnsupportedAlgorithm("hmac-sha384 with Sodium".to_owned()))
+            },
+        }
+    }
+
+    pub(crate) fn hmac512(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "hmac-rust-crypto")]
+            Backend::RustCrypto => try_hmac::<super::rust_crypto::RustCryptoHmac512>(secret, data),
+            #[cfg(feature = "hmac-openssl")]
+            Backend::OpenSsl => try_hmac::<super::ssl::OpensslHmac512>(secret, data),
+            #[cfg(feature = "hmac-ring")]
+            Backend::Ring => try_hmac::<super::ring_hmac::RingHmac512>(secret, data),
+            #[cfg(feature = "hmac-sha2")]
+            Backend::Sha2 => try_hmac::<super::sha2_hmac::Sha2Hmac512>(secret, data),
+            #[cfg(feature = "hmac-sodium")]
+            Backend::Sodium => try_hmac::<super::sodium::SodiumHmac512>(secret, data),
+        }
+    }
+}
+
+impl Default for Backend {
+    /// The first enabled backend in the order rust-crypto, OpenSSL, ring, sha2, libsodium
+    fn default() -> Backend {
+        BACKENDS[0]
+    }
+}
@@ -0,0 +1,49 @@
+use ::SecretKey;
+use super::HmacBuilder;
+
+use crypto::mac::Mac;
+use crypto::blake2b::Blake2b;
+
+/// Output length in bytes of the keyed hash; this is also the maximum BLAKE2b key length
+const BLAKE2B_BYTES: usize = 64;
+
+/// BLAKE2b in its native keyed mode rather than the HMAC construction
+pub struct RustCryptoBlake2bKeyed {
+    inner: Blake2b
+}
+
+impl HmacBuilder for RustCryptoBlake2bKeyed {
+    fn new(secret: &SecretKey) -> RustCryptoBlake2bKeyed {
+        let inner = if secret.is_empty() {
+            Blake2b::new(BLAKE2B_BYTES)
+        } else if secret.len() > BLAKE2B_BYTES {
+            // Like HMAC, keys which are too long are hashed first
+            let mut key = [0u8; BLAKE2B_BYTES];
+            Blake2b::blake2b(&mut key, secret, &[]);
+            Blake2b::new_keyed(BLAKE2B_BYTES, &key)
+        } else {
+            Blake2b::new_keyed(BLAKE2B_BYTES, secret)
+        };
+
+        RustCryptoBlake2bKeyed {
+            inner: inner
+        }
+    }
+
+    fn output_len() -> usize {
+        BLAKE2B_BYTES
+    }
+
+    // Add more input data
+    fn input(&mut self, data: &[u8]) -> &mut RustCryptoBlake2bKeyed {
+        self.inner.input(data);
+        self
+    }
+
+    // Return the keyed hash
+    fn finalize(mut self) -> Vec<u8> {
+        let mut result = vec![0; self.inner.output_bytes()];
+        self.inner.raw_result(&mut result[..]);
+        result
+    }
+}
@@ -0,0 +1,40 @@
+use ::SecretKey;
+use super::HmacBuilder;
+
+use blake3::{hash, Hasher, KEY_LEN, OUT_LEN};
+
+/// BLAKE3 in its native keyed mode
+pub struct Blake3Keyed {
+    inner: Hasher
+}
+
+impl HmacBuilder for Blake3Keyed {
+    fn new(secret: &SecretKey) -> Blake3Keyed {
+        // BLAKE3 keys are exactly 32 bytes; any other length is hashed to fit
+        let mut key = [0u8; KEY_LEN];
+        if secret.len() == KEY_LEN {
+            key.copy_from_slice(secret);
+        } else {
+            key = *hash(secret).as_bytes();
+        }
+
+        Blake3Keyed {
+            inner: Hasher::new_keyed(&key)
+        }
+    }
+
+    fn output_len() -> usize {
+        OUT_LEN
+    }
+
+    // Add more input data
+    fn input(&mut self, data: &[u8]) -> &mut Blake3Keyed {
+        self.inner.update(data);
+        self
+    }
+
+    // Return the keyed hash
+    fn finalize(self) -> Vec<u8> {
+        self.inner.finalize().as_bytes().to_vec()
+    }
+}
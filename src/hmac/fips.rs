@@ -0,0 +1,49 @@
+//! Switching OpenSSL into FIPS mode
+
+use error::{Error, Result};
+
+/// Put OpenSSL into FIPS mode
+///
+/// With OpenSSL 3 the `fips` provider is loaded and FIPS properties are made the default for
+/// all algorithm fetches. Older versions use `FIPS_mode_set`, which requires a FIPS capable
+/// build of the library.
+#[cfg(all(feature = "hmac-openssl-fips", ossl300))]
+pub fn enable() -> Result<()> {
+    use std::mem;
+    use std::ptr;
+    use openssl::provider::Provider;
+    use openssl_sys;
+
+    let provider = match Provider::load(None, "fips") {
+        Ok(provider) => provider,
+        Err(_) => return Err(Error::FipsUnavailable)
+    };
+
+    // The provider must stay loaded for the life of the process
+    mem::forget(provider);
+
+    unsafe {
+        if openssl_sys::EVP_default_properties_enable_fips(ptr::null_mut(), 1) != 1 ||
+           openssl_sys::EVP_default_properties_is_fips_enabled(ptr::null_mut()) != 1 {
+            return Err(Error::FipsUnavailable);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "hmac-openssl-fips", not(ossl300)))]
+pub fn enable() -> Result<()> {
+    use openssl::fips;
+
+    match fips::enable(true) {
+        Ok(()) if fips::enabled() => Ok(()),
+        _ => Err(Error::FipsUnavailable)
+    }
+}
+
+/// FIPS mode is only available with the `hmac-openssl-fips` feature
+#[cfg(not(feature = "hmac-openssl-fips"))]
+pub fn enable() -> Result<()> {
+    Err(Error::FipsUnavailable)
+}
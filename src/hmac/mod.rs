@@ -1,33 +1,479 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
 use ::SecretKey;
+use error::{Error, Result};
 
 #[cfg(feature = "hmac-rust-crypto")]
 mod rust_crypto;
 
-#[cfg(feature = "hmac-rust-crypto")]
-pub type Hmac256 = rust_crypto::RustCryptoHmac256;
-
 #[cfg(feature = "hmac-openssl")]
 mod ssl;
 
-#[cfg(feature = "hmac-openssl")]
-pub type Hmac256 = ssl::OpensslHmac256;
+#[cfg(feature = "hmac-ring")]
+mod ring_hmac;
+
+#[cfg(feature = "hmac-sha2")]
+mod sha2_hmac;
+
+#[cfg(feature = "hmac-sodium")]
+mod sodium;
+
+mod backend;
+pub mod fips;
+
+pub use self::backend::Backend;
+
+#[cfg(feature = "hmac-sha3")]
+mod sha3;
+
+#[cfg(feature = "hmac-sha3")]
+pub type HmacSha3_256 = sha3::RustCryptoHmacSha3_256;
+
+#[cfg(feature = "hmac-sha1")]
+mod sha1;
+
+#[cfg(feature = "hmac-sha1")]
+pub type HmacSha1 = sha1::RustCryptoHmacSha1;
+
+#[cfg(feature = "hmac-blake2b")]
+mod blake2b;
 
+#[cfg(feature = "hmac-blake2b")]
+pub type Blake2bKeyed = blake2b::RustCryptoBlake2bKeyed;
 
+#[cfg(feature = "hmac-blake3")]
+mod blake3;
+
+
+/// An incremental MAC implementation
+///
+/// Implement this to supply a custom or hardware-backed MAC, and use it with the middleware
+/// through `BuilderDigest`.
 pub trait HmacBuilder {
-    // Create the HMAC builder
+    /// Create the HMAC builder
     fn new(secret: &SecretKey) -> Self;
 
-    // Add more input data
+    /// Length in bytes of the digests returned by `finalize`
+    fn output_len() -> usize;
+
+    /// Add more input data
     fn input(&mut self, data: &[u8]) -> &mut Self;
 
-    // Return the hmac digest
+    /// Return the hmac digest
     fn finalize(self) -> Vec<u8>;
+
+    /// Return the hmac digest, or an error if the implementation failed to compute it
+    ///
+    /// The middleware finishes every HMAC with this method. The default calls `finalize`, which
+    /// suits implementations that cannot fail.
+    fn try_finalize(self) -> Result<Vec<u8>> where Self: Sized {
+        Ok(self.finalize())
+    }
+}
+
+/// Digest computing HMACs with the `HmacBuilder` `H`
+///
+/// A fresh builder is created for every HMAC.
+pub struct BuilderDigest<H> {
+    builder: PhantomData<fn() -> H>
+}
+
+impl<H> BuilderDigest<H> {
+    pub fn new() -> BuilderDigest<H> {
+        BuilderDigest { builder: PhantomData }
+    }
+}
+
+impl<H> Default for BuilderDigest<H> {
+    fn default() -> BuilderDigest<H> {
+        BuilderDigest::new()
+    }
+}
+
+impl<H> Clone for BuilderDigest<H> {
+    fn clone(&self) -> BuilderDigest<H> {
+        BuilderDigest::new()
+    }
+}
+
+impl<H> fmt::Debug for BuilderDigest<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BuilderDigest")
+    }
+}
+
+impl<H: HmacBuilder + 'static> HmacDigest for BuilderDigest<H> {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        hmac::<H>(secret, data)
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        try_hmac::<H>(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        H::output_len()
+    }
+}
+
+/// A hash function the middleware can compute HMACs with
+///
+/// `HmacAuthentication` is generic over this trait so that any digest can be plugged in. The
+/// provided implementations are the `Sha256`, `Sha384`, and `Sha512` markers and `Algorithm`, which
+/// selects the hash at runtime.
+pub trait HmacDigest: Clone + Send + Sync + 'static {
+    /// Compute an HMAC of `data` keyed with `secret`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the implementation fails to compute the HMAC, which of the provided backends only
+    /// OpenSSL can. Use `try_hmac` to handle the failure instead.
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8>;
+
+    /// Compute an HMAC of `data` keyed with `secret`, or return an error if that fails
+    ///
+    /// The middleware computes its HMACs with this method. The default calls `hmac`, which suits
+    /// implementations that cannot fail.
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.hmac(secret, data))
+    }
+
+    /// Length in bytes of the HMACs this digest computes
+    fn output_len(&self) -> usize;
+
+    /// Whether HMACs are computed by an implementation that can operate in FIPS mode
+    ///
+    /// Only the OpenSSL backend qualifies; the default is false.
+    fn fips_capable(&self) -> bool {
+        false
+    }
+}
+
+/// HMAC-SHA256 digest
+///
+/// `Default` uses `Backend::default()`; other backends are selected with `From<Backend>`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256 {
+    backend: Backend
+}
+
+impl From<Backend> for Sha256 {
+    fn from(backend: Backend) -> Sha256 {
+        Sha256 { backend: backend }
+    }
+}
+
+impl HmacDigest for Sha256 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        expect_hmac(self.try_hmac(secret, data))
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        self.backend.hmac256(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn fips_capable(&self) -> bool {
+        self.backend.fips_capable()
+    }
+}
+
+/// HMAC-SHA384 digest
+///
+/// Only available when a backend other than `hmac-sodium` is enabled since libsodium lacks SHA-384.
+/// `Default` uses `Backend::default()`; other backends are selected with `TryFrom<Backend>`, which
+/// rejects `Backend::Sodium`.
+#[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+          feature = "hmac-sha2"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha384 {
+    backend: Backend
+}
+
+#[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+          feature = "hmac-sha2"))]
+impl ::std::convert::TryFrom<Backend> for Sha384 {
+    type Error = Error;
+
+    /// Returns `Error::UnsupportedAlgorithm` if `backend` does not provide SHA-384
+    fn try_from(backend: Backend) -> Result<Sha384> {
+        if !backend.supports_sha384() {
+            return Err(Error::UnsupportedAlgorithm(format!("hmac-sha384 with {:?}", backend)));
+        }
+
+        Ok(Sha384 { backend: backend })
+    }
+}
+
+#[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+          feature = "hmac-sha2"))]
+impl HmacDigest for Sha384 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        expect_hmac(self.try_hmac(secret, data))
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        self.backend.hmac384(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        48
+    }
+
+    fn fips_capable(&self) -> bool {
+        self.backend.fips_capable()
+    }
 }
 
-/// Compute an HMAC using SHA-256 hashing
-pub fn hmac256(secret: &SecretKey, data: &[u8]) -> Vec<u8> {
-    let mut hmac = Hmac256::new(secret);
+/// HMAC-SHA512 digest
+///
+/// `Default` uses `Backend::default()`; other backends are selected with `From<Backend>`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha512 {
+    backend: Backend
+}
+
+impl From<Backend> for Sha512 {
+    fn from(backend: Backend) -> Sha512 {
+        Sha512 { backend: backend }
+    }
+}
+
+impl HmacDigest for Sha512 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        expect_hmac(self.try_hmac(secret, data))
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        self.backend.hmac512(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        64
+    }
+
+    fn fips_capable(&self) -> bool {
+        self.backend.fips_capable()
+    }
+}
+
+/// HMAC-SHA3-256 digest
+///
+/// Requires the `hmac-sha3` feature.
+#[cfg(feature = "hmac-sha3")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha3_256;
+
+#[cfg(feature = "hmac-sha3")]
+impl HmacDigest for Sha3_256 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        hmac::<HmacSha3_256>(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        32
+    }
+}
+
+/// HMAC-SHA1 digest
+///
+/// SHA-1 is deprecated; this exists only to verify requests from legacy clients such as OAuth 1.0
+/// consumers (see `Scheme::OAuth1`) and is deliberately not an `Algorithm`. Requires the
+/// `hmac-sha1` feature.
+#[cfg(feature = "hmac-sha1")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha1;
+
+#[cfg(feature = "hmac-sha1")]
+impl HmacDigest for Sha1 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        hmac::<HmacSha1>(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        20
+    }
+}
+
+/// BLAKE2b-512 keyed hash
+///
+/// This uses BLAKE2b's native keyed mode instead of the HMAC construction. Secrets longer than 64
+/// bytes are hashed with unkeyed BLAKE2b-512 before use. Requires the `hmac-blake2b` feature.
+#[cfg(feature = "hmac-blake2b")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake2b;
+
+#[cfg(feature = "hmac-blake2b")]
+impl HmacDigest for Blake2b {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        hmac::<Blake2bKeyed>(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        Blake2bKeyed::output_len()
+    }
+}
+
+/// BLAKE3 keyed hash
+///
+/// This uses BLAKE3's native keyed mode, which is considerably faster than HMAC-SHA256 on large
+/// bodies. 32 byte secrets are used as the key directly; secrets of any other length are hashed
+/// with unkeyed BLAKE3 to obtain the key. Requires the `hmac-blake3` feature.
+#[cfg(feature = "hmac-blake3")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3;
+
+#[cfg(feature = "hmac-blake3")]
+impl HmacDigest for Blake3 {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        hmac::<blake3::Blake3Keyed>(secret, data)
+    }
+
+    fn output_len(&self) -> usize {
+        blake3::Blake3Keyed::output_len()
+    }
+}
+
+/// Hash algorithm used for HMAC computation, selected at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// HMAC-SHA256
+    Sha256,
+    /// HMAC-SHA384; not available when `hmac-sodium` is the only backend
+    #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+              feature = "hmac-sha2"))]
+    Sha384,
+    /// HMAC-SHA512
+    Sha512,
+    /// HMAC-SHA3-256; requires the `hmac-sha3` feature
+    #[cfg(feature = "hmac-sha3")]
+    Sha3_256,
+    /// BLAKE2b-512 keyed hash; requires the `hmac-blake2b` feature
+    #[cfg(feature = "hmac-blake2b")]
+    Blake2b,
+    /// BLAKE3 keyed hash; requires the `hmac-blake3` feature
+    #[cfg(feature = "hmac-blake3")]
+    Blake3,
+}
+
+impl HmacDigest for Algorithm {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        expect_hmac(self.try_hmac(secret, data))
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            Algorithm::Sha256 => Sha256::default().try_hmac(secret, data),
+            #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+                      feature = "hmac-sha2"))]
+            Algorithm::Sha384 => Sha384::default().try_hmac(secret, data),
+            Algorithm::Sha512 => Sha512::default().try_hmac(secret, data),
+            #[cfg(feature = "hmac-sha3")]
+            Algorithm::Sha3_256 => Sha3_256.try_hmac(secret, data),
+            #[cfg(feature = "hmac-blake2b")]
+            Algorithm::Blake2b => Blake2b.try_hmac(secret, data),
+            #[cfg(feature = "hmac-blake3")]
+            Algorithm::Blake3 => Blake3.try_hmac(secret, data),
+        }
+    }
+
+    fn output_len(&self) -> usize {
+        match *self {
+            Algorithm::Sha256 => Sha256::default().output_len(),
+            #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+                      feature = "hmac-sha2"))]
+            Algorithm::Sha384 => Sha384::default().output_len(),
+            Algorithm::Sha512 => Sha512::default().output_len(),
+            #[cfg(feature = "hmac-sha3")]
+            Algorithm::Sha3_256 => Sha3_256.output_len(),
+            #[cfg(feature = "hmac-blake2b")]
+            Algorithm::Blake2b => Blake2b.output_len(),
+            #[cfg(feature = "hmac-blake3")]
+            Algorithm::Blake3 => Blake3.output_len(),
+        }
+    }
+
+    fn fips_capable(&self) -> bool {
+        match *self {
+            Algorithm::Sha256 => Sha256::default().fips_capable(),
+            #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+                      feature = "hmac-sha2"))]
+            Algorithm::Sha384 => Sha384::default().fips_capable(),
+            Algorithm::Sha512 => Sha512::default().fips_capable(),
+            #[allow(unreachable_patterns)]
+            _ => false
+        }
+    }
+}
+
+impl Algorithm {
+    /// Name of the algorithm as used in the algorithm header
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Algorithm::Sha256 => "hmac-sha256",
+            #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+                      feature = "hmac-sha2"))]
+            Algorithm::Sha384 => "hmac-sha384",
+            Algorithm::Sha512 => "hmac-sha512",
+            #[cfg(feature = "hmac-sha3")]
+            Algorithm::Sha3_256 => "hmac-sha3-256",
+            #[cfg(feature = "hmac-blake2b")]
+            Algorithm::Blake2b => "blake2b",
+            #[cfg(feature = "hmac-blake3")]
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    /// Parse an algorithm name as returned by `name`, ignoring case
+    fn from_str(s: &str) -> ::std::result::Result<Algorithm, Error> {
+        let algorithms = [
+            Algorithm::Sha256,
+            #[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+                      feature = "hmac-sha2"))]
+            Algorithm::Sha384,
+            Algorithm::Sha512,
+            #[cfg(feature = "hmac-sha3")]
+            Algorithm::Sha3_256,
+            #[cfg(feature = "hmac-blake2b")]
+            Algorithm::Blake2b,
+            #[cfg(feature = "hmac-blake3")]
+            Algorithm::Blake3,
+        ];
+
+        algorithms.iter()
+                  .find(|algorithm| algorithm.name().eq_ignore_ascii_case(s.trim()))
+                  .cloned()
+                  .ok_or_else(|| Error::UnsupportedAlgorithm(s.to_owned()))
+    }
+}
+
+/// Compute an HMAC with the given builder
+pub fn hmac<H: HmacBuilder>(secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+    let mut hmac = H::new(secret);
     hmac.input(data);
     hmac.finalize()
 }
 
+/// Compute an HMAC with the given builder, returning an error if it fails
+pub fn try_hmac<H: HmacBuilder>(secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+    let mut hmac = H::new(secret);
+    hmac.input(data);
+    hmac.try_finalize()
+}
+
+/// The HMAC of `result`, panicking as documented on `HmacDigest::hmac` if it failed
+fn expect_hmac(result: Result<Vec<u8>>) -> Vec<u8> {
+    match result {
+        Ok(hmac) => hmac,
+        Err(err) => panic!("{}", err)
+    }
+}
@@ -0,0 +1,39 @@
+use ring::hmac::{self, Context, Key};
+
+use super::HmacBuilder;
+use ::SecretKey;
+
+macro_rules! ring_hmac {
+    ($name:ident, $algorithm:expr, $len:expr) => {
+        pub struct $name {
+            inner: Context
+        }
+
+        impl HmacBuilder for $name {
+            fn new(secret: &SecretKey) -> $name {
+                $name {
+                    inner: Context::with_key(&Key::new($algorithm, &secret[..]))
+                }
+            }
+
+            fn output_len() -> usize {
+                $len
+            }
+
+            // Add more input data
+            fn input(&mut self, data: &[u8]) -> &mut $name {
+                self.inner.update(data);
+                self
+            }
+
+            // Return the hmac digest
+            fn finalize(self) -> Vec<u8> {
+                self.inner.sign().as_ref().to_vec()
+            }
+        }
+    };
+}
+
+ring_hmac!(RingHmac256, hmac::HMAC_SHA256, 32);
+ring_hmac!(RingHmac384, hmac::HMAC_SHA384, 48);
+ring_hmac!(RingHmac512, hmac::HMAC_SHA512, 64);
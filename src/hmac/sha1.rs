@@ -0,0 +1,35 @@
+use ::SecretKey;
+use super::HmacBuilder;
+
+use crypto::mac::Mac;
+use crypto::hmac::Hmac;
+use crypto::sha1::Sha1;
+
+pub struct RustCryptoHmacSha1 {
+    inner: Hmac<Sha1>
+}
+
+impl HmacBuilder for RustCryptoHmacSha1 {
+    fn new(secret: &SecretKey) -> RustCryptoHmacSha1 {
+        RustCryptoHmacSha1 {
+            inner: Hmac::new(Sha1::new(), secret)
+        }
+    }
+
+    fn output_len() -> usize {
+        20
+    }
+
+    // Add more input data
+    fn input(&mut self, data: &[u8]) -> &mut RustCryptoHmacSha1 {
+        self.inner.input(data);
+        self
+    }
+
+    // Return the hmac digest
+    fn finalize(mut self) -> Vec<u8> {
+        let mut result = vec![0; self.inner.output_bytes()];
+        self.inner.raw_result(&mut result[..]);
+        result
+    }
+}
@@ -0,0 +1,41 @@
+use rustcrypto_hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha384, Sha512};
+
+use super::HmacBuilder;
+use ::SecretKey;
+
+macro_rules! sha2_hmac {
+    ($name:ident, $digest:ident, $len:expr) => {
+        pub struct $name {
+            inner: Hmac<$digest>
+        }
+
+        impl HmacBuilder for $name {
+            fn new(secret: &SecretKey) -> $name {
+                $name {
+                    // HMAC accepts keys of any length
+                    inner: Hmac::new_from_slice(&secret[..]).unwrap()
+                }
+            }
+
+            fn output_len() -> usize {
+                $len
+            }
+
+            // Add more input data
+            fn input(&mut self, data: &[u8]) -> &mut $name {
+                self.inner.update(data);
+                self
+            }
+
+            // Return the hmac digest
+            fn finalize(self) -> Vec<u8> {
+                self.inner.finalize().into_bytes().to_vec()
+            }
+        }
+    };
+}
+
+sha2_hmac!(Sha2Hmac256, Sha256, 32);
+sha2_hmac!(Sha2Hmac384, Sha384, 48);
+sha2_hmac!(Sha2Hmac512, Sha512, 64);
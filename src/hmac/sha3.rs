@@ -0,0 +1,35 @@
+use ::SecretKey;
+use super::HmacBuilder;
+
+use crypto::mac::Mac;
+use crypto::hmac::Hmac;
+use crypto::sha3::Sha3;
+
+pub struct RustCryptoHmacSha3_256 {
+    inner: Hmac<Sha3>
+}
+
+impl HmacBuilder for RustCryptoHmacSha3_256 {
+    fn new(secret: &SecretKey) -> RustCryptoHmacSha3_256 {
+        RustCryptoHmacSha3_256 {
+            inner: Hmac::new(Sha3::sha3_256(), secret)
+        }
+    }
+
+    fn output_len() -> usize {
+        32
+    }
+
+    // Add more input data
+    fn input(&mut self, data: &[u8]) -> &mut RustCryptoHmacSha3_256 {
+        self.inner.input(data);
+        self
+    }
+
+    // Return the hmac digest
+    fn finalize(mut self) -> Vec<u8> {
+        let mut result = vec![0; self.inner.output_bytes()];
+        self.inner.raw_result(&mut result[..]);
+        result
+    }
+}
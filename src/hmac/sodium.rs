@@ -0,0 +1,52 @@
+use std::sync::Once;
+
+use sodiumoxide;
+use sodiumoxide::crypto::auth::{hmacsha256, hmacsha512};
+
+use super::HmacBuilder;
+use ::SecretKey;
+
+static SODIUM_INIT: Once = Once::new();
+
+/// Initialize libsodium once per process before the first HMAC is computed
+fn init() {
+    SODIUM_INIT.call_once(|| {
+        // Failure only disables the optimized implementations; HMAC remains usable
+        let _ = sodiumoxide::init();
+    });
+}
+
+macro_rules! sodium_hmac {
+    ($name:ident, $module:ident, $len:expr) => {
+        pub struct $name {
+            inner: $module::State
+        }
+
+        impl HmacBuilder for $name {
+            fn new(secret: &SecretKey) -> $name {
+                init();
+                $name {
+                    inner: $module::State::init(&secret[..])
+                }
+            }
+
+            fn output_len() -> usize {
+                $len
+            }
+
+            // Add more input data
+            fn input(&mut self, data: &[u8]) -> &mut $name {
+                self.inner.update(data);
+                self
+            }
+
+            // Return the hmac digest
+            fn finalize(self) -> Vec<u8> {
+                self.inner.finalize().as_ref().to_vec()
+            }
+        }
+    };
+}
+
+sodium_hmac!(SodiumHmac256, hmacsha256, hmacsha256::TAGBYTES);
+sodium_hmac!(SodiumHmac512, hmacsha512, hmacsha512::TAGBYTES);
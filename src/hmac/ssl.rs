@@ -1,30 +1,60 @@
-use std::io::Write;
-
-use openssl::crypto::hash::Type;
-use openssl::crypto::hmac::HMAC;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
 
 use super::HmacBuilder;
+use error::{Error, Result};
 use ::SecretKey;
 
-pub struct OpensslHmac256 {
-    inner: HMAC
-}
+macro_rules! openssl_hmac {
+    ($name:ident, $digest:expr, $len:expr) => {
+        pub struct $name {
+            // OpenSSL can reject the key, e.g. a short one in FIPS mode; reported by try_finalize
+            key: ::std::result::Result<PKey<Private>, ErrorStack>,
+            data: Vec<u8>
+        }
+
+        impl HmacBuilder for $name {
+            fn new(secret: &SecretKey) -> $name {
+                $name {
+                    key: PKey::hmac(&secret[..]),
+                    data: Vec::new()
+                }
+            }
+
+            fn output_len() -> usize {
+                $len
+            }
 
-impl HmacBuilder for OpensslHmac256 {
-    fn new(secret: &SecretKey) -> OpensslHmac256 {
-        OpensslHmac256 {
-            inner: HMAC::new(Type::SHA256, &secret[..])
+            // Add more input data
+            fn input(&mut self, data: &[u8]) -> &mut $name {
+                self.data.extend_from_slice(data);
+                self
+            }
+
+            // Return the hmac digest
+            fn finalize(self) -> Vec<u8> {
+                match self.try_finalize() {
+                    Ok(hmac) => hmac,
+                    Err(err) => panic!("{}", err)
+                }
+            }
+
+            fn try_finalize(self) -> Result<Vec<u8>> {
+                let key = try!(self.key.map_err(failed));
+                let mut signer = try!(Signer::new($digest, &key).map_err(failed));
+                try!(signer.update(&self.data[..]).map_err(failed));
+                signer.sign_to_vec().map_err(failed)
+            }
         }
-    }
-
-    // Add more input data
-    fn input(&mut self, data: &[u8]) -> &mut OpensslHmac256 {
-        self.inner.write_all(data).unwrap();
-        self
-    }
-
-    // Return the hmac digest
-    fn finalize(mut self) -> Vec<u8> {
-        self.inner.finish()
-    }
+    };
 }
+
+fn failed(err: ErrorStack) -> Error {
+    Error::HmacFailed(format!("OpenSSL: {}", err))
+}
+
+openssl_hmac!(OpensslHmac256, MessageDigest::sha256(), 32);
+openssl_hmac!(OpensslHmac384, MessageDigest::sha384(), 48);
+openssl_hmac!(OpensslHmac512, MessageDigest::sha512(), 64);
@@ -0,0 +1,252 @@
+//! HTTP Message Signatures (RFC 9421) verification
+//!
+//! The first signature named in the `Signature-Input` header is verified against the
+//! corresponding member of the `Signature` header. Covered components may be header fields and
+//! the derived components `@method`, `@target-uri`, `@authority`, `@scheme`, `@request-target`,
+//! `@path`, and `@query`; component parameters are not supported. The `keyid` and `alg`
+//! signature parameters select the key, `created` must be within `Config::timestamp_max_age`,
+//! `expires` must not have passed, and a `nonce` may not be reused. A covered `Content-Digest`
+//! header is checked against the body when the `content-digest` feature is enabled.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::base64::FromBase64;
+use url;
+
+use canonical;
+use error::{Error, Result};
+use replay;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// A parsed `Signature-Input` member
+struct SignatureInput {
+    label: String,
+    components: Vec<String>,
+    params: Vec<(String, String)>,
+    /// The serialized inner list and parameters, as covered by `@signature-params`
+    serialized: String,
+}
+
+impl SignatureInput {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+                   .find(|&&(ref param, _)| param == name)
+                   .map(|&(_, ref value)| &value[..])
+    }
+}
+
+/// Verify the RFC 9421 signature of `req` with the keys of `auth`
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let input = try!(signature_input(req));
+    let supplied = try!(signature(req, &input.label));
+
+    let covers_target = input.components.iter().any(|component| {
+        component == "@target-uri" || component == "@request-target" || component == "@path"
+    });
+    if !input.components.iter().any(|component| component == "@method") || !covers_target {
+        return Err(malformed("the method and path must be covered").into());
+    }
+
+    if input.components.iter().any(|component| component == "content-digest") {
+        try!(check_content_digest(req));
+    }
+
+    let base = try!(signature_base(req, &input));
+    let mac = try!(auth.keyed_mac(req, input.param("keyid"), input.param("alg")));
+    let computed = try!(mac(base.as_bytes()));
+
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    try!(check_params(auth, &input));
+    Ok(())
+}
+
+/// Check the `created`, `expires`, and `nonce` signature parameters
+fn check_params<D, V, C>(auth: &HmacAuthentication<D, V, C>, input: &SignatureInput)
+    -> Result<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+
+    if let Some(created) = input.param("created") {
+        let created: u64 = try!(created.parse().map_err(|_| malformed("invalid created")));
+        try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(created), max_age));
+    }
+
+    if let Some(expires) = input.param("expires") {
+        try!(replay::check_expiry(try!(expires.parse().map_err(|_| malformed("invalid expires")))));
+    }
+
+    if let Some(nonce) = input.param("nonce") {
+        if !try!(auth.inner.nonces.insert_if_absent(nonce, max_age * 2)) {
+            return Err(Error::ReplayedRequest(format!("nonce {} was already used", nonce)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check a covered `Content-Digest` header against the body
+#[cfg(feature = "content-digest")]
+fn check_content_digest(req: &mut iron::Request) -> Result<()> {
+    let body = try!(canonical::CanonicalRequest::from_request(req)).body;
+    try!(::content_digest::verify(req, body.as_bytes()));
+    Ok(())
+}
+
+/// Without the `content-digest` feature, the digest is covered but not checked
+#[cfg(not(feature = "content-digest"))]
+fn check_content_digest(_req: &mut iron::Request) -> Result<()> {
+    Ok(())
+}
+
+/// The signature base of RFC 9421 section 2.5
+fn signature_base(req: &iron::Request, input: &SignatureInput) -> Result<String> {
+    let url: url::Url = req.url.clone().into();
+    let request_target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned()
+    };
+
+    let mut base = String::new();
+    for component in &input.components {
+        let value = match &component[..] {
+            "@method" => req.method.as_ref().to_owned(),
+            "@target-uri" => url.as_str().to_owned(),
+            "@authority" => try!(canonical::authority(req)),
+            "@scheme" => url.scheme().to_owned(),
+            "@request-target" => request_target.clone(),
+            "@path" => url.path().to_owned(),
+            "@query" => format!("?{}", url.query().unwrap_or("")),
+            name if name.starts_with('@') => {
+                return Err(malformed(&format!("unsupported component {}", name)));
+            },
+            name => try!(field_value(req, name))
+        };
+
+        base.push_str(&format!("\"{}\": {}\n", component, value));
+    }
+
+    base.push_str(&format!("\"@signature-params\": {}", input.serialized));
+    Ok(base)
+}
+
+/// The trimmed values of a header field joined with `, `
+pub fn field_value(req: &iron::Request, name: &str) -> Result<String> {
+    let values = match req.headers.get_raw(name) {
+        Some(values) => values,
+        None => return Err(malformed(&format!("missing {} header", name)))
+    };
+
+    let mut decoded = Vec::with_capacity(values.len());
+    for value in values.iter() {
+        decoded.push(try!(::std::str::from_utf8(&value[..])).trim());
+    }
+
+    Ok(decoded.join(", "))
+}
+
+/// The first member of the `Signature-Input` header
+fn signature_input(req: &iron::Request) -> Result<SignatureInput> {
+    let value = try!(field_value(req, "signature-input"));
+    let member = match split_members(&value).into_iter().next() {
+        Some(member) => member,
+        None => return Err(malformed("empty Signature-Input"))
+    };
+
+    let (label, serialized) = try!(split_member(member));
+    let close = match serialized.find(')') {
+        Some(close) if serialized.starts_with('(') => close,
+        _ => return Err(malformed("Signature-Input is not an inner list"))
+    };
+
+    let mut components = Vec::new();
+    for item in serialized[1..close].split_whitespace() {
+        if item.len() < 2 || !item.starts_with('"') || !item.ends_with('"') {
+            return Err(malformed(&format!("unsupported component {}", item)));
+        }
+        components.push(item[1..item.len() - 1].to_lowercase());
+    }
+
+    let params = serialized[close + 1..]
+        .split(';')
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| {
+            let mut parts = param.trim().splitn(2, '=');
+            let name = parts.next().unwrap_or("").to_owned();
+            let value = parts.next().unwrap_or("").trim_matches('"').to_owned();
+            (name, value)
+        })
+        .collect();
+
+    Ok(SignatureInput {
+        label: label.to_owned(),
+        components: components,
+        params: params,
+        serialized: serialized.to_owned()
+    })
+}
+
+/// The decoded member of the `Signature` header with `label`
+fn signature(req: &iron::Request, label: &str) -> Result<Vec<u8>> {
+    let value = try!(field_value(req, "signature"));
+    for member in split_members(&value) {
+        let (name, encoded) = try!(split_member(member));
+        if name == label {
+            if encoded.len() < 2 || !encoded.starts_with(':') || !encoded.ends_with(':') {
+                return Err(malformed("signature is not a byte sequence"));
+            }
+            return Ok(try!(encoded[1..encoded.len() - 1].from_base64()));
+        }
+    }
+
+    Err(malformed(&format!("missing signature {}", label)))
+}
+
+/// Split a dictionary into its members, ignoring commas in strings and inner lists
+pub fn split_members(value: &str) -> Vec<&str> {
+    let mut members = Vec::new();
+    let mut in_string = false;
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, c) in value.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                members.push(value[start..index].trim());
+                start = index + 1;
+            },
+            _ => ()
+        }
+    }
+    members.push(value[start..].trim());
+
+    members.into_iter().filter(|member| !member.is_empty()).collect()
+}
+
+/// Split a dictionary member into its name and value
+pub fn split_member(member: &str) -> Result<(&str, &str)> {
+    match member.find('=') {
+        Some(index) => Ok((member[..index].trim(), member[index + 1..].trim())),
+        None => Err(malformed(&format!("invalid member {}", member)))
+    }
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedSignature(reason.to_owned())
+}
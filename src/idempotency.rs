@@ -0,0 +1,39 @@
+//! Signed idempotency keys
+
+use iron;
+use iron::typemap;
+
+use config::Config;
+use error::{Error, Result};
+
+/// Request extension holding the verified idempotency key
+///
+/// After successful verification, handlers can read the key with
+/// `req.extensions.get::<IdempotencyKey>()` to deduplicate retried requests.
+pub struct IdempotencyKey;
+
+impl typemap::Key for IdempotencyKey {
+    type Value = String;
+}
+
+/// Require the idempotency key header, if configured, and expose its value to handlers
+///
+/// Call this only once the request HMAC, which covers the header, has been verified.
+pub fn check_idempotency_key(req: &mut iron::Request, config: &Config) -> Result<()> {
+    let header = match config.idempotency_key_header {
+        Some(ref header) => header,
+        None => return Ok(())
+    };
+
+    let key = match req.headers.get_raw(&header[..]) {
+        Some(value) => try!(::std::str::from_utf8(&value[0][..])).trim().to_owned(),
+        None => return Err(Error::MissingIdempotencyKey(header.clone()))
+    };
+
+    if key.is_empty() {
+        return Err(Error::MissingIdempotencyKey(header.clone()));
+    }
+
+    req.extensions.insert::<IdempotencyKey>(key);
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Keys declared in a TOML or JSON keystore file
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json;
+use toml;
+
+use error::{Error, Result};
+use ::{Algorithm, Key, SecretKey};
+
+/// A keystore file as written by ops
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeystoreFile {
+    secret: Option<String>,
+    #[serde(default)]
+    keys: HashMap<String, KeyEntry>,
+}
+
+/// One key of a keystore file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    secret: String,
+    algorithm: Option<String>,
+    not_before: Option<u64>,
+    not_after: Option<u64>,
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+/// The secret and keys of a keystore file
+///
+/// Lets ops manage every client key declaratively. Secrets are encoded as for
+/// `SecretKey::from_env`, `algorithm` is a name as returned by `Algorithm::name`, `hmac-sha256` if
+/// not set, and `not_before` and `not_after` are seconds since the Unix epoch:
+///
+/// ```toml
+/// # used when the request names no key
+/// secret = "hex:3f2a0c9d5e7b..."
+///
+/// [keys.partner]
+/// secret = "base64:nB1L4OBmcg..."
+/// algorithm = "hmac-sha512"
+/// not_after = 1798761600
+/// scopes = ["read"]
+/// metadata = { account = "42" }
+/// ```
+///
+/// JSON keystores have the same fields. Build the middleware with
+/// `HmacAuthentication::with_keystore`, or copy `keys` into `Config::keys` yourself.
+#[derive(Debug, Clone, Default)]
+pub struct Keystore {
+    /// Secret of requests that name no key
+    pub secret: Option<SecretKey>,
+    pub keys: HashMap<String, Key>,
+}
+
+impl Keystore {
+    /// Read the keystore at `path`, parsed as TOML or JSON by its `.toml` or `.json` extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keystore> {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Keystore::from_toml(&contents),
+            Some("json") => Keystore::from_json(&contents),
+            _ => Err(Error::InvalidKey(format!("{} is not a .toml or .json keystore",
+                                               path.display())))
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Keystore> {
+        match toml::from_str(contents) {
+            Ok(file) => Keystore::from_file(file),
+            Err(err) => Err(Error::InvalidKey(format!("invalid keystore: {}", err)))
+        }
+    }
+
+    pub fn from_json(contents: &str) -> Result<Keystore> {
+        match serde_json::from_str(contents) {
+            Ok(file) => Keystore::from_file(file),
+            Err(err) => Err(Error::InvalidKey(format!("invalid keystore: {}", err)))
+        }
+    }
+
+    fn from_file(file: KeystoreFile) -> Result<Keystore> {
+        let secret = match file.secret {
+            Some(ref secret) => Some(try!(SecretKey::from_setting("keystore secret", secret))),
+            None => None
+        };
+
+        let mut keys = HashMap::new();
+        for (id, entry) in file.keys {
+            let name = format!("keystore key {}", id);
+            let algorithm = match entry.algorithm {
+                Some(ref algorithm) => try!(algorithm.parse::<Algorithm>()),
+                None => Algorithm::Sha256
+            };
+
+            let mut key = Key::new(try!(SecretKey::from_setting(&name, &entry.secret)), algorithm);
+            key.not_before = entry.not_before.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            key.not_after = entry.not_after.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            key.scopes = entry.scopes;
+            key.metadata = entry.metadata;
+            keys.insert(id, key);
+        }
+
+        Ok(Keystore {
+            secret: secret,
+            keys: keys
+        })
+    }
+}
@@ -1,7 +1,8 @@
 //! [Iron] middleware for HMAC authentication
 //!
 //! This package contains `BeforeMiddleware` for authenticating HTTP requests and `AfterMiddleware`
-//! for signing response. The HMAC stragegy is presently hardcoded as follows using an SHA-256 hash.
+//! for signing response. The HMAC stragegy is presently hardcoded as follows using an SHA-256 hash
+//! by default.
 //!
 //! For requests, the expected hmac is
 //!
@@ -9,6 +10,8 @@
 //! hmac(hmac(request.method) + hmac(request.path) + hmac(request.body))
 //! ```
 //!
+//! where `request.path` excludes the query string unless `Config::query` says otherwise.
+//!
 //! The response is signed with an hmac generated with
 //!
 //! ```plain
@@ -26,55 +29,634 @@
 //! let (hmac_before, hmac_after) = Hmac256Authentication::middleware(secret, header_name);
 //! ```
 //!
+//! Other hash algorithms are available through `with_algorithm`
+//!
+//! ```no_run
+//! use iron_hmac::{Algorithm, HmacAuthentication};
+//!
+//! let (hmac_before, hmac_after) =
+//!     HmacAuthentication::with_algorithm(Algorithm::Sha512, "secret", "x-my-hmac");
+//! ```
+//!
+//! `HmacAuthentication` is generic over the `HmacDigest` trait, so the hash can also be fixed at
+//! compile time or supplied by the user.
+//!
+//! ```no_run
+//! use iron_hmac::{HmacAuthentication, Sha512};
+//!
+//! let (hmac_before, hmac_after) = HmacAuthentication::<Sha512>::middleware("secret", "x-my-hmac");
+//! ```
+//!
+//! `Hmac512Authentication` is provided as a shorthand for the SHA-512 case.
+//!
+//! ```no_run
+//! use iron_hmac::Hmac512Authentication;
+//!
+//! let (hmac_before, hmac_after) = Hmac512Authentication::middleware("secret", "x-my-hmac");
+//! ```
+//!
+//! A custom or hardware-backed MAC can be supplied by implementing `HmacBuilder` and using it
+//! through `BuilderDigest`.
+//!
+//! ```no_run
+//! # use iron_hmac::SecretKey;
+//! use iron_hmac::{BuilderDigest, HmacAuthentication, HmacBuilder};
+//!
+//! struct HsmHmac { /* ... */ }
+//!
+//! impl HmacBuilder for HsmHmac {
+//!     // ...
+//! #   fn new(_: &SecretKey) -> HsmHmac { HsmHmac {} }
+//! #   fn output_len() -> usize { 32 }
+//! #   fn input(&mut self, _: &[u8]) -> &mut HsmHmac { self }
+//! #   fn finalize(self) -> Vec<u8> { Vec::new() }
+//! }
+//!
+//! let (hmac_before, hmac_after) =
+//!     HmacAuthentication::<BuilderDigest<HsmHmac>>::middleware("secret", "x-my-hmac");
+//! ```
+//!
+//! The constructors above accept secrets of any length. `try_middleware` rejects secrets shorter
+//! than 32 bytes, and so do `with_config` and the builder, unless `Config::min_secret_len` says
+//! otherwise. Optional behavior such as MAC truncation is enabled by passing a `Config` to
+//! `with_config`.
+//!
+//! ```no_run
+//! use iron_hmac::{Config, Hmac256Authentication, SecretKey, Sha256};
+//!
+//! let secret = SecretKey::from_env("HMAC_SECRET").unwrap();
+//! let config = Config { truncate: Some(16), ..Config::default() };
+//! let (hmac_before, hmac_after) =
+//!     Hmac256Authentication::with_config(Sha256::default(), secret, "x-my-hmac", config)
+//!         .unwrap();
+//! ```
+//!
+//! `HmacAuthentication::builder` sets the same options one at a time.
+//!
+//! Tenants signing with different secrets or algorithms can share one middleware by naming their
+//! key in a header. The scopes and metadata of the key a request was signed with are available to
+//! handlers as the `VerifiedKey` request extension, and a `KeyFingerprint` of the key to correlate
+//! logs with.
+//!
+//! ```no_run
+//! use iron_hmac::{Algorithm, Config, Hmac256Authentication, Key, SecretKey, Sha256};
+//!
+//! let mut config = Config {
+//!     key_id_header: Some("x-hmac-key-id".to_owned()),
+//!     ..Config::default()
+//! };
+//! let partner_secret = SecretKey::from_env("HMAC_PARTNER_SECRET").unwrap();
+//! config.keys.insert("partner".to_owned(), Key::new(partner_secret, Algorithm::Sha512));
+//! let secret = SecretKey::from_env("HMAC_SECRET").unwrap();
+//! let (hmac_before, hmac_after) =
+//!     Hmac256Authentication::with_config(Sha256::default(), secret, "x-my-hmac", config)
+//!         .unwrap();
+//! ```
+//!
+//! Keys that change while the server runs belong in a `SecretStore` such as `MemorySecretStore`,
+//! set as `Config::secret_store`, from which a single client's key can be revoked. Set as
+//! `Config::secret_provider`, a `RouteSecretProvider` chooses the secret of each request by path
+//! prefix, and a `TenantSecretProvider` by the tenant named by the host or a header. To choose it
+//! some other way, implement `SecretProvider`, wrapped in a `CachingSecretProvider` if looking
+//! secrets up is expensive.
+//!
+//! `HmacAuthentication::with_secret_file` uses a `FileSecretProvider`, which reloads the secrets
+//! whenever their file changes, and `HmacAuthentication::with_secret_rotation` returns a
+//! `SecretRotation` handle to replace the secret from an admin endpoint or signal handler. With
+//! the `secrets-vault` and `secrets-aws` features, `VaultSecretProvider` and
+//! `AwsSecretsManagerProvider` fetch secrets from HashiCorp Vault and AWS Secrets Manager and pick
+//! up rotations by fetching them again periodically. With the `secrets-sqlite` feature,
+//! `SqliteSecretProvider` looks each key up in a database table. With the `keystore` feature,
+//! `HmacAuthentication::with_keystore` takes every client key from a TOML or JSON `Keystore` file.
+//!
+//! Rather than distributing several raw secrets, per-purpose subkeys can be derived from one master
+//! secret with HKDF.
+//!
+//! ```no_run
+//! use iron_hmac::{Hkdf, Hmac256Authentication, Sha256};
+//!
+//! let hkdf = Hkdf::new(Sha256::default(), b"salt", "master secret");
+//! let (hmac_before, hmac_after) =
+//!     Hmac256Authentication::with_derived_key(&hkdf, "request-signing", "x-my-hmac").unwrap();
+//! ```
+//!
+//! Human-memorable shared secrets should be stretched with PBKDF2 before use.
+//!
+//! ```no_run
+//! use iron_hmac::{Hmac256Authentication, Pbkdf2, SecretKey, Sha256};
+//!
+//! let params = Pbkdf2::new(Sha256::default(), b"deployment salt");
+//! let secret = SecretKey::from_passphrase("correct horse battery staple", &params).unwrap();
+//! let (hmac_before, hmac_after) = Hmac256Authentication::middleware(secret, "x-my-hmac");
+//! ```
+//!
+//! Replay protection is enabled by having clients sign a timestamp and a nonce.
+//!
+//! ```no_run
+//! use iron_hmac::{Config, Hmac256Authentication, SecretKey, Sha256};
+//!
+//! let secret = SecretKey::from_env("HMAC_SECRET").unwrap();
+//! let config = Config {
+//!     timestamp_header: Some("x-hmac-timestamp".to_owned()),
+//!     nonce_header: Some("x-hmac-nonce".to_owned()),
+//!     ..Config::default()
+//! };
+//! let (hmac_before, hmac_after) =
+//!     Hmac256Authentication::with_config(Sha256::default(), secret, "x-my-hmac", config)
+//!         .unwrap();
+//! ```
+//!
+//! Nonces are remembered in a `MemoryReplayCache` by default. Deployments of several instances
+//! should share replay state through another `ReplayCache`, such as `RedisReplayCache`, which is
+//! enabled with the `replay-redis` feature, passed to `with_replay_cache`. At very high request
+//! rates, `BloomReplayCache` bounds memory use at the cost of a small false positive rate.
+//!
+//! Every verification attempt, with the key id and outcome but never the secret, is reported to a
+//! `KeyAudit` hook set with `with_audit`, e.g. to feed key usage into a SIEM.
+//!
+//! MACs are compared in constant time by `ConstantTimeVerifier`. Another comparison primitive can
+//! be used by implementing `Verifier` and passing it to `with_verifier`.
+//!
+//! What a request HMAC covers is defined by a `Canonicalizer`. `DefaultCanonicalizer` implements
+//! the scheme above along with the options in `Config`; an externally mandated format can be
+//! matched by implementing `Canonicalizer` and passing it to `with_canonicalizer`.
+//!
 //! The middleware is linked in the usual way.
 //!
 //! # Building
 //!
-//! If you wish to use the openssl backed implementation, set `default-features = false` in addition
-//! to adding `features = ["hmac-openssl"]`.
+//! SHA-2 HMACs are computed by one of several backends, each enabled by a cargo feature:
+//! `hmac-rust-crypto` (the default), `hmac-openssl`, `hmac-ring` ([ring]), `hmac-sha2` (the
+//! maintained RustCrypto [hmac] and [sha2] crates, recommended over the unmaintained rust-crypto),
+//! and `hmac-sodium` (libsodium, which does not provide SHA-384). Any combination may be enabled;
+//! `Backend::default()` is the first enabled one in that order, and another can be chosen at
+//! construction time.
+//!
+//! ```no_run
+//! use iron_hmac::{Backend, Hmac256Authentication};
+//!
+//! let (hmac_before, hmac_after) =
+//!     Hmac256Authentication::middleware_with_backend(Backend::default(), "secret", "x-my-hmac")
+//!         .unwrap();
+//! ```
+//!
+//! For regulated environments, the `hmac-openssl-fips` feature together with `Config::fips` puts
+//! OpenSSL into FIPS mode when the middleware is constructed, failing construction if FIPS mode is
+//! unavailable or the digest is not computed by `Backend::OpenSsl`.
+//!
+//! HMAC-SHA3-256 support (`Sha3_256` and `Algorithm::Sha3_256`) is enabled with the `hmac-sha3`
+//! feature. BLAKE2b keyed hashing (`Blake2b` and `Algorithm::Blake2b`) is enabled with the
+//! `hmac-blake2b` feature, and BLAKE3 keyed hashing (`Blake3` and `Algorithm::Blake3`) with the
+//! `hmac-blake3` feature.
+//!
+//! With the `content-digest` feature, `BodySigning::VerifiedDigest` checks the `Content-Digest`
+//! header (RFC 9530) against the body and covers the digest in place of the body.
+//!
+//! Requests from legacy OAuth 1.0 clients, such as LTI tool consumers, can be verified by setting
+//! `Config::scheme` to `Scheme::OAuth1` and using the `Sha1` digest, which is enabled with the
+//! `hmac-sha1` feature. `Scheme::HttpMessageSignatures` verifies HTTP Message Signatures (RFC 9421)
+//! sent in the `Signature` and `Signature-Input` headers instead of the HMAC header, and
+//! `Scheme::DraftCavage` accepts the older draft-cavage `Signature` header format. With the
+//! `aws-sigv4` feature, `Scheme::AwsSigV4` accepts requests signed with AWS Signature Version 4,
+//! e.g. by the AWS SDKs, against the configured keys, and `Scheme::AzureSharedKey` accepts Azure
+//! Storage style `SharedKey` authorization. `Scheme::Hawk` verifies requests with the Hawk
+//! authentication scheme. Rails clients using the `api-auth` gem are accepted by
+//! `Hmac256Authentication::api_auth`.
+//!
+//! # Webhooks
+//!
+//! Webhook deliveries signed over the raw body are verified with `BodyCanonicalizer`. Supported
+//! providers are listed by `Preset`, and `HmacAuthentication::from_preset` builds the middleware
+//! for any of them. Each also has its own constructor with concrete types: GitHub
+//! deliveries are verified by `Hmac256Authentication::github_webhook`, Meta Graph API deliveries
+//! by `Hmac256Authentication::meta_webhook`, Shopify deliveries by
+//! `Hmac256Authentication::shopify_webhook`, and Stripe deliveries by
+//! `Hmac256Authentication::stripe_webhook`. Mailgun deliveries, which carry their signature in
+//! the body, are verified by `Hmac256Authentication::mailgun_webhook`. GitLab deliveries are
+//! verified by `Hmac256Authentication::gitlab_webhook` for secret tokens and
+//! `Hmac256Authentication::gitlab_signed_webhook` for signing tokens. Slack slash commands and
+//! other Slack requests are verified by `Hmac256Authentication::slack`. With the `hmac-sha1`
+//! feature, Twilio webhook requests are verified by `HmacAuthentication::<Sha1>::twilio` and
+//! legacy Meta `X-Hub-Signature` headers by `HmacAuthentication::<Sha1>::meta_webhook_sha1`.
+//!
+//! # Signatures
+//!
+//! With `Config::public_key` set, asymmetric signatures are verified instead of HMACs, so services
+//! can authenticate requests without sharing a symmetric secret. The signed message is built from
+//! the same canonical request and configuration as the HMAC, and replay protection applies alike.
+//! `SignatureAuthentication` is a shorthand middleware pair for a single scheme. Ed25519
+//! (`Ed25519Authentication`) is enabled with the `ed25519` feature and ECDSA P-256
+//! (`EcdsaP256Authentication`) with the `ecdsa-p256` feature. Verification of RSA-SHA256
+//! signatures made with a published public key is enabled with the `rsa-sha256` feature; pass
+//! `RsaSha256::from_pem` to `Hmac256Authentication::with_public_key`.
+//!
+//! # Signed URLs
+//!
+//! `SignedUrls` hands out links carrying their own HMAC and expiry as query parameters, and as
+//! BeforeMiddleware verifies them, so browsers can be given short-lived download links.
+//!
+//! ```no_run
+//! use iron_hmac::{Sha256, SignedUrls};
+//!
+//! let urls = SignedUrls::new(Sha256::default(), "secret");
+//! let link = urls.sign("https://example.com/report.pdf", 1700000000).unwrap();
+//! ```
 //!
 //! [Iron]: https://github.com/iron/iron
+//! [ring]: https://github.com/briansmith/ring
+//! [hmac]: https://crates.io/crates/hmac
+//! [sha2]: https://crates.io/crates/sha2
 
 #![deny(warnings)]
 
-#[cfg(feature = "hmac-rust-crypto")]
+#[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-sha3", feature = "hmac-blake2b"))]
 extern crate crypto;
 
 #[cfg(feature = "hmac-openssl")]
 extern crate openssl;
 
+#[cfg(feature = "hmac-openssl-fips")]
+extern crate openssl_sys;
+
+#[cfg(feature = "hmac-ring")]
+extern crate ring;
+
+#[cfg(feature = "hmac-sha2")]
+extern crate hmac as rustcrypto_hmac;
+
+#[cfg(any(feature = "hmac-sha2", feature = "content-digest", feature = "aws-sigv4"))]
+extern crate sha2;
+
+#[cfg(feature = "hmac-sodium")]
+extern crate sodiumoxide;
+
+#[cfg(feature = "hmac-blake3")]
+extern crate blake3;
+
+#[cfg(feature = "replay-redis")]
+extern crate redis;
+
+#[cfg(feature = "ed25519")]
+extern crate ed25519_dalek;
+
+#[cfg(feature = "ecdsa-p256")]
+extern crate p256;
+
+#[cfg(feature = "rsa-sha256")]
+extern crate rsa;
+
+#[cfg(any(feature = "secrets-vault", feature = "secrets-aws"))]
+extern crate reqwest;
+
+#[cfg(all(feature = "mlock", unix))]
+extern crate libc;
+
+#[cfg(feature = "secrets-sqlite")]
+extern crate rusqlite;
+
+#[cfg(feature = "keystore")]
+extern crate serde;
+
+#[cfg(feature = "keystore")]
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(feature = "keystore")]
+extern crate serde_json;
+
+#[cfg(feature = "keystore")]
+extern crate toml;
+
 extern crate iron;
 extern crate bodyparser;
 extern crate persistent;
+extern crate rand;
 extern crate rustc_serialize;
 extern crate constant_time_eq;
 extern crate url;
+extern crate zeroize;
 
 use iron::prelude::*;
 use iron::{BeforeMiddleware, AfterMiddleware};
+use rand::{OsRng, Rng};
+use rustc_serialize::base64::{self, FromBase64, ToBase64};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroize;
 
 mod error;
 #[macro_use]
 mod macros;
+mod mlock;
 mod util;
 mod hmac;
+mod config;
+mod api_auth;
+mod audit;
+#[cfg(feature = "secrets-aws")]
+mod aws_secrets;
+mod authorization;
+mod azure;
+mod builder;
+mod canonical;
+mod cavage;
+mod client_addr;
+#[cfg(feature = "content-digest")]
+mod content_digest;
+mod encoding;
+mod gitlab;
+mod hawk;
+mod hkdf;
+mod http_signatures;
+mod idempotency;
+#[cfg(feature = "keystore")]
+mod keystore;
+mod mailgun;
+mod oauth1;
+mod pbkdf2;
+mod presets;
+mod replay;
+mod rotation;
+mod secret_file;
+mod secret_store;
+mod signature;
+#[cfg(feature = "aws-sigv4")]
+mod sigv4;
+mod signed_url;
+#[cfg(feature = "secrets-sqlite")]
+mod sqlite;
+mod stripe;
+mod structured;
+mod tenant;
+mod verifier;
+#[cfg(feature = "secrets-vault")]
+mod vault;
 
-use hmac::{Hmac256, hmac256, HmacBuilder};
+pub use hmac::{Algorithm, Backend, BuilderDigest, HmacBuilder, HmacDigest, Sha256, Sha512};
+#[cfg(any(feature = "hmac-rust-crypto", feature = "hmac-openssl", feature = "hmac-ring",
+          feature = "hmac-sha2"))]
+pub use hmac::Sha384;
+#[cfg(feature = "hmac-sha3")]
+pub use hmac::Sha3_256;
+#[cfg(feature = "hmac-sha1")]
+pub use hmac::Sha1;
+#[cfg(feature = "hmac-blake2b")]
+pub use hmac::Blake2b;
+#[cfg(feature = "hmac-blake3")]
+pub use hmac::Blake3;
+
+pub use api_auth::ApiAuthCanonicalizer;
+pub use audit::{AuditEvent, AuditOutcome, KeyAudit};
+#[cfg(feature = "secrets-aws")]
+pub use aws_secrets::AwsSecretsManagerProvider;
+pub use builder::{HmacAuthenticationBuilder, DEFAULT_HMAC_HEADER};
+pub use canonical::{BodyCanonicalizer, BodySigning, CanonicalRequest, Canonicalization,
+                    Canonicalizer, Component, DefaultCanonicalizer, Explanation,
+                    MatchedCanonicalization, MethodCase, Normalization, QuerySigning,
+                    TrailingSlash};
+pub use client_addr::{ClientAddrResolver, ForwardedFor, RemoteAddr};
+pub use config::{Config, DuplicateHeaders, HeaderConflict, Key, KeyFingerprint, Scheme,
+                 VerifiedKey, Version};
+pub use encoding::SignatureEncoding;
+pub use error::Error;
+pub use hkdf::Hkdf;
+pub use idempotency::IdempotencyKey;
+#[cfg(feature = "keystore")]
+pub use keystore::Keystore;
+pub use pbkdf2::Pbkdf2;
+pub use presets::{BodyAuthentication, Preset, PresetAuthentication, PresetCanonicalizer,
+                  PresetDigest, SlackCanonicalizer, TwilioCanonicalizer};
+pub use replay::{BloomReplayCache, MemoryReplayCache, ReplayCache};
+pub use rotation::SecretRotation;
+#[cfg(feature = "replay-redis")]
+pub use replay::RedisReplayCache;
+pub use secret_file::FileSecretProvider;
+pub use secret_store::{CachingSecretProvider, MemorySecretStore, RouteSecretProvider,
+                       SecretProvider, SecretStore};
+pub use signature::{SignatureAuthentication, SignatureScheme};
+pub use signed_url::SignedUrls;
+#[cfg(feature = "secrets-sqlite")]
+pub use sqlite::SqliteSecretProvider;
+pub use tenant::{TenantSecretProvider, TenantSecrets, TenantSource};
+#[cfg(feature = "aws-sigv4")]
+pub use sigv4::AwsSigV4;
+pub use verifier::{ConstantTimeVerifier, Verifier};
+#[cfg(feature = "secrets-vault")]
+pub use vault::VaultSecretProvider;
+#[cfg(feature = "mlock")]
+pub use mlock::secrets_locked;
+#[cfg(feature = "ed25519")]
+pub use signature::{Ed25519, Ed25519Authentication};
+#[cfg(feature = "ecdsa-p256")]
+pub use signature::{EcdsaP256, EcdsaP256Authentication};
+#[cfg(feature = "rsa-sha256")]
+pub use signature::RsaSha256;
 
 use error::Result;
-use error::Error;
 
 /// Key used for HMAC computation
 ///
 /// SecretKey is a newtype for Vec<u8>, and deref returns a &[u8]. The Vec<u8> representation is
-/// necessary since the key length cannot be known at compile time.
-#[derive(Debug, Clone)]
+/// necessary since the key length cannot be known at compile time. The key bytes are zeroed when
+/// the SecretKey is dropped, and `Debug` only prints a fingerprint of them. With the `mlock`
+/// feature, the key bytes are also locked into RAM so they are never swapped to disk; copies made
+/// before the SecretKey was built, e.g. the encoded string a key was decoded from, are not.
 pub struct SecretKey(Vec<u8>);
 
 impl SecretKey {
     pub fn new(s: &[u8]) -> SecretKey {
-        SecretKey(::std::convert::From::from(s))
+        SecretKey::from_vec(s.to_vec())
+    }
+
+    /// Take ownership of `bytes`, locking them into memory with the `mlock` feature
+    fn from_vec(bytes: Vec<u8>) -> SecretKey {
+        mlock::lock(bytes.as_ptr(), bytes.capacity());
+        SecretKey(bytes)
+    }
+
+    /// The raw key bytes
+    ///
+    /// Prefer passing the SecretKey itself around; this is for handing the key to code outside
+    /// this crate, e.g. a client library signing requests.
+    pub fn expose_secret(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    /// Decode a hex encoded key
+    ///
+    /// Surrounding whitespace is ignored. Returns `Error::DecodingHex` if `s` is not valid hex.
+    pub fn from_hex(s: &str) -> Result<SecretKey> {
+        Ok(SecretKey::from_vec(try!(util::from_hex(s.trim().as_bytes()))))
+    }
+
+    /// Decode a standard or URL-safe base64 encoded key
+    ///
+    /// Surrounding whitespace is ignored. Returns `Error::DecodingBase64` if `s` is not valid
+    /// base64.
+    pub fn from_base64(s: &str) -> Result<SecretKey> {
+        Ok(SecretKey::from_vec(try!(s.trim().from_base64())))
+    }
+
+    /// Read a key from the environment variable `name`
+    ///
+    /// A value starting with `hex:` or `base64:` is decoded accordingly, the rest of one starting
+    /// with `raw:` is used as raw bytes, and so is any other value. Returns `Error::InvalidKey`
+    /// naming the variable if it is unset, empty, or cannot be decoded, so a misconfigured
+    /// deployment fails at startup rather than rejecting every request.
+    pub fn from_env(name: &str) -> Result<SecretKey> {
+        match std::env::var(name) {
+            Ok(value) => SecretKey::from_setting(name, &value),
+            Err(std::env::VarError::NotPresent) => {
+                Err(Error::InvalidKey(format!("{} is not set", name)))
+            },
+            Err(std::env::VarError::NotUnicode(_)) => {
+                Err(Error::InvalidKey(format!("{} is not valid UTF-8", name)))
+            }
+        }
+    }
+
+    /// Read keys from every environment variable starting with `prefix`, by key id
+    ///
+    /// The key id is the rest of the variable name, lowercased, so with the prefix `HMAC_KEY_`
+    /// the variable `HMAC_KEY_PARTNER` holds the key `partner`. Values are decoded as by
+    /// `from_env`. Returns `Error::InvalidKey` if no variable starts with `prefix` or any of them
+    /// is invalid.
+    pub fn map_from_env(prefix: &str) -> Result<HashMap<String, SecretKey>> {
+        let mut keys = HashMap::new();
+        for (name, value) in std::env::vars_os() {
+            let name = match name.into_string() {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+            if !name.starts_with(prefix) || name.len() == prefix.len() {
+                continue;
+            }
+
+            let value = match value.into_string() {
+                Ok(value) => value,
+                Err(_) => return Err(Error::InvalidKey(format!("{} is not valid UTF-8", name)))
+            };
+            let key = try!(SecretKey::from_setting(&name, &value));
+            keys.insert(name[prefix.len()..].to_lowercase(), key);
+        }
+
+        if keys.is_empty() {
+            return Err(Error::InvalidKey(format!("no variable starting with {} is set", prefix)));
+        }
+
+        Ok(keys)
+    }
+
+    /// Decode the value of the setting `name`, e.g. an environment variable, as by `from_env`
+    fn from_setting(name: &str, value: &str) -> Result<SecretKey> {
+        let key = if value.starts_with("hex:") {
+            match SecretKey::from_hex(&value["hex:".len()..]) {
+                Ok(key) => key,
+                Err(_) => return Err(Error::InvalidKey(format!("{} is not valid hex", name)))
+            }
+        } else if value.starts_with("base64:") {
+            match SecretKey::from_base64(&value["base64:".len()..]) {
+                Ok(key) => key,
+                Err(_) => return Err(Error::InvalidKey(format!("{} is not valid base64", name)))
+            }
+        } else if value.starts_with("raw:") {
+            SecretKey::new(value["raw:".len()..].as_bytes())
+        } else {
+            SecretKey::new(value.as_bytes())
+        };
+
+        if key.is_empty() {
+            return Err(Error::InvalidKey(format!("{} is empty", name)));
+        }
+
+        Ok(key)
+    }
+
+    /// Generate a random key of `len` bytes using the operating system's CSPRNG
+    ///
+    /// 32 bytes is a good choice for HMAC-SHA256.
+    pub fn generate(len: usize) -> Result<SecretKey> {
+        let mut rng = try!(OsRng::new());
+        let mut key = vec![0; len];
+        rng.fill_bytes(&mut key[..]);
+        Ok(SecretKey::from_vec(key))
+    }
+
+    /// Hex encoding of the key, e.g. for handing a generated key to clients
+    pub fn to_hex(&self) -> String {
+        util::to_hex(&self.0[..])
+    }
+
+    /// Standard base64 encoding of the key
+    pub fn to_base64(&self) -> String {
+        self.0.to_base64(base64::STANDARD)
+    }
+
+    /// Derive a key from a human-memorable passphrase with PBKDF2
+    ///
+    /// Use this instead of `new` for low-entropy secrets so they are hardened against brute force.
+    pub fn from_passphrase<D>(passphrase: &str, params: &Pbkdf2<D>) -> Result<SecretKey>
+        where D: HmacDigest
+    {
+        params.stretch(passphrase.as_bytes())
+    }
+
+    /// Fingerprint of the key and `key_id`, as set as the `KeyFingerprint` of requests signed with
+    /// them
+    pub fn fingerprint(&self, key_id: Option<&str>) -> KeyFingerprint {
+        let mut data = b"iron-hmac key fingerprint".to_vec();
+        if let Some(key_id) = key_id {
+            data.push(0);
+            data.extend_from_slice(key_id.as_bytes());
+        }
+
+        KeyFingerprint(util::to_hex(&Algorithm::Sha256.hmac(self, &data[..])[..8]))
+    }
+
+    /// Derive the signing key of the UTC date of `time` and `service` as
+    /// `hmac(hmac(secret, date), service)`, with the date formatted as `YYYYMMDD`
+    ///
+    /// Clients of a middleware with `Config::date_scoped_service` sign with the key of the current
+    /// time instead of the secret.
+    pub fn date_scoped<H>(&self, digest: &H, time: SystemTime, service: &str) -> SecretKey
+        where H: HmacDigest
+    {
+        let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |seconds| seconds.as_secs());
+        let date = util::format_date(seconds);
+        let date_key = SecretKey::from_vec(digest.hmac(self, date.as_bytes()));
+        SecretKey::from_vec(digest.hmac(&date_key, service.as_bytes()))
+    }
+}
+
+impl Clone for SecretKey {
+    fn clone(&self) -> SecretKey {
+        SecretKey::from_vec(self.0.clone())
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        let (ptr, capacity) = (self.0.as_ptr(), self.0.capacity());
+        self.0.zeroize();
+        mlock::unlock(ptr, capacity);
+    }
+}
+
+/// Prints `SecretKey(redacted, fingerprint 1a2b3c4d)`, so keys do not leak into logs
+///
+/// The fingerprint is the start of the HMAC-SHA256 of a fixed label keyed with the secret, which
+/// tells keys apart without revealing them.
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fingerprint = self.fingerprint(None);
+        write!(f, "SecretKey(redacted, fingerprint {})", &fingerprint.0[..8])
     }
 }
 
@@ -86,6 +668,16 @@ impl Deref for SecretKey {
     }
 }
 
+/// Parses keys encoded as for `SecretKey::from_env`, e.g. from a configuration file or a
+/// command line argument
+impl FromStr for SecretKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SecretKey> {
+        SecretKey::from_setting("key", s)
+    }
+}
+
 impl Into<SecretKey> for &'static str {
     fn into(self) -> SecretKey {
         SecretKey::new(self.as_bytes())
@@ -94,102 +686,922 @@ impl Into<SecretKey> for &'static str {
 
 impl Into<SecretKey> for String {
     fn into(self) -> SecretKey {
-        SecretKey::new(&self[..].as_bytes())
+        SecretKey::from_vec(self.into_bytes())
     }
 }
 
 /// Iron middleware for validation hmac headers on requests and signing responses.
+///
+/// The middleware is generic over the `HmacDigest` used to compute HMACs, the `Verifier` used to
+/// compare them, and the `Canonicalizer` defining what a request HMAC covers.
 #[derive(Debug, Clone)]
-pub struct Hmac256Authentication {
-    secret: SecretKey,
-    hmac_header_key: String
+pub struct HmacAuthentication<D, V = ConstantTimeVerifier, C = DefaultCanonicalizer>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    inner: Arc<Inner<D, V, C>>
+}
+
+/// State of the middleware, shared by its BeforeMiddleware and AfterMiddleware halves
+///
+/// The `with_` methods give the half they are called on an `Inner` of its own, while
+/// `HmacAuthenticationBuilder` sets the same options before splitting. The secret and `Config`
+/// are behind their own `Arc`s, so either way they exist once in memory. There is no secret when
+/// `Config::secret_provider` supplies them all.
+#[derive(Debug)]
+struct Inner<D, V, C> {
+    digest: D,
+    secret: Option<Arc<SecretKey>>,
+    hmac_header_key: String,
+    config: Arc<Config>,
+    verifier: V,
+    canonicalizer: C,
+    nonces: Arc<dyn ReplayCache>,
+    audit: Option<Arc<dyn KeyAudit>>
 }
 
-impl Hmac256Authentication {
-    /// Build Hmac256Authentication BeforeMiddleware and AfterMiddleware
+/// HmacAuthentication using SHA-256
+pub type Hmac256Authentication = HmacAuthentication<Sha256>;
+
+/// HmacAuthentication using SHA-512
+pub type Hmac512Authentication = HmacAuthentication<Sha512>;
+
+impl<D: HmacDigest + Default> HmacAuthentication<D> {
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware
     ///
     /// The `secret` parameter is used for all HMAC generation. The `hmac_header_key` is used to
-    /// lookup the request's HMAC.
+    /// lookup the request's HMAC. The length of `secret` is not checked; use `try_middleware` to
+    /// reject short secrets.
     pub fn middleware<K: Into<SecretKey>, S: Into<String>>(secret: K, hmac_header_key: S)
-        -> (Hmac256Authentication, Hmac256Authentication) {
+        -> (HmacAuthentication<D>, HmacAuthentication<D>) {
 
-        let auth = Hmac256Authentication {
-            secret: secret.into(),
-            hmac_header_key: hmac_header_key.into()
-        };
+        HmacAuthentication::with_digest(D::default(), secret, hmac_header_key)
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware, rejecting short secrets
+    ///
+    /// Returns an error if `secret` is shorter than `Config::min_secret_len`, 32 bytes by default.
+    /// Apart from that, this behaves exactly like `middleware`.
+    pub fn try_middleware<K, S>(secret: K, hmac_header_key: S)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        HmacAuthentication::with_config(D::default(), secret, hmac_header_key, Config::default())
+    }
+
+    /// Start building HmacAuthentication BeforeMiddleware and AfterMiddleware with `secret`
+    ///
+    /// Unlike `middleware`, this can set any option of `Config` along the way.
+    pub fn builder<K: Into<SecretKey>>(secret: K) -> HmacAuthenticationBuilder<D> {
+        HmacAuthenticationBuilder::new(D::default(), secret)
+    }
+}
+
+impl<D> HmacAuthentication<D>
+    where D: HmacDigest + TryFrom<Backend>,
+          Error: From<D::Error>
+{
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware computing HMACs with `backend`
+    ///
+    /// Returns an error if `backend` does not provide the digest, as libsodium does not provide
+    /// SHA-384. Apart from the crypto implementation, this behaves exactly like `middleware`.
+    pub fn middleware_with_backend<K, S>(backend: Backend, secret: K, hmac_header_key: S)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        let digest = try!(D::try_from(backend));
+        Ok(HmacAuthentication::with_digest(digest, secret, hmac_header_key))
+    }
+}
+
+impl HmacAuthentication<Algorithm> {
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware using `algorithm`
+    ///
+    /// Apart from the hash algorithm being chosen at runtime, this behaves exactly like
+    /// `middleware`.
+    pub fn with_algorithm<K, S>(algorithm: Algorithm, secret: K, hmac_header_key: S)
+        -> (HmacAuthentication<Algorithm>, HmacAuthentication<Algorithm>)
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        HmacAuthentication::with_digest(algorithm, secret, hmac_header_key)
+    }
+}
+
+impl<D: HmacDigest> HmacAuthentication<D> {
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware using `digest`
+    ///
+    /// Like `middleware`, this does not check the length of `secret`; `with_config` does.
+    pub fn with_digest<K, S>(digest: D, secret: K, hmac_header_key: S)
+        -> (HmacAuthentication<D>, HmacAuthentication<D>)
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        let auth = HmacAuthentication::from_parts_unchecked(digest, Some(secret.into()),
+                                                            hmac_header_key.into(),
+                                                            Config::default());
 
         (auth.clone(), auth)
     }
 
-    fn compute_request_hmac(&self, req: &mut iron::Request) -> Result<Vec<u8>> {
-        let body = match try!(req.get::<bodyparser::Raw>()) {
-            Some(body) => body,
-            None => "".to_string()
+    /// Build one half, returning an error if `config` is not valid for `digest`
+    ///
+    /// `secret` may only be `None` when `config` has a secret provider.
+    pub(crate) fn from_parts(digest: D, secret: Option<SecretKey>, hmac_header_key: String,
+                             config: Config)
+        -> Result<HmacAuthentication<D>>
+    {
+        try!(config.validate(&digest, secret.as_ref()));
+
+        Ok(HmacAuthentication::from_parts_unchecked(digest, secret, hmac_header_key, config))
+    }
+
+    /// Build one half without validating `config`
+    pub(crate) fn from_parts_unchecked(digest: D, secret: Option<SecretKey>,
+                                       hmac_header_key: String, config: Config)
+        -> HmacAuthentication<D>
+    {
+        HmacAuthentication {
+            inner: Arc::new(Inner {
+                digest: digest,
+                secret: secret.map(Arc::new),
+                hmac_header_key: hmac_header_key,
+                config: Arc::new(config),
+                verifier: ConstantTimeVerifier,
+                canonicalizer: DefaultCanonicalizer,
+                nonces: Arc::new(MemoryReplayCache::default()),
+                audit: None
+            })
+        }
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware with a subkey of `hkdf`
+    ///
+    /// The secret is derived for `info`, e.g. `"request-signing"`, and is as long as the digest
+    /// output. Apart from that, this behaves exactly like `with_digest`.
+    pub fn with_derived_key<S>(hkdf: &Hkdf<D>, info: &str, hmac_header_key: S)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where S: Into<String>
+    {
+        let digest = hkdf.digest().clone();
+        let len = digest.output_len();
+        let secret = try!(hkdf.derive(info.as_bytes(), len));
+
+        Ok(HmacAuthentication::with_digest(digest, secret, hmac_header_key))
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware with non-default behavior
+    ///
+    /// Returns an error if `config` is not valid for `digest`.
+    pub fn with_config<K, S>(digest: D, secret: K, hmac_header_key: S, config: Config)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        let auth = try!(HmacAuthentication::from_parts(digest, Some(secret.into()),
+                                                       hmac_header_key.into(), config));
+
+        Ok((auth.clone(), auth))
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware with the secrets of a file
+    ///
+    /// The file is read by a `FileSecretProvider`, which checks it for changes every five seconds
+    /// and swaps in the new secrets, so they can be rotated without restarting the server. The
+    /// provider replaces `config.secret_provider`. Returns an error if the file cannot be read
+    /// or parsed, or if `config` is not valid for `digest`.
+    pub fn with_secret_file<P, S>(digest: D, path: P, hmac_header_key: S, mut config: Config)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where P: AsRef<std::path::Path>,
+              S: Into<String>
+    {
+        let provider = try!(FileSecretProvider::watch(path, secret_file::DEFAULT_POLL_INTERVAL));
+        config.secret_provider = Some(Arc::new(provider));
+
+        let auth = try!(HmacAuthentication::from_parts(digest, None, hmac_header_key.into(),
+                                                       config));
+
+        Ok((auth.clone(), auth))
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware with the keys of a `Keystore`
+    /// file
+    ///
+    /// The keystore's keys are added to `config.keys`, and its secret verifies requests that name
+    /// no key; set `config.key_id_header` for clients to name one. The file is read once. Returns
+    /// an error if it cannot be read or parsed, has no secret, or if `config` is not valid for
+    /// `digest`.
+    #[cfg(feature = "keystore")]
+    pub fn with_keystore<P, S>(digest: D, path: P, hmac_header_key: S, mut config: Config)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>)>
+        where P: AsRef<std::path::Path>,
+              S: Into<String>
+    {
+        let keystore = try!(Keystore::load(path));
+        let secret = match keystore.secret {
+            Some(secret) => secret,
+            None => return Err(Error::InvalidKey("keystore has no secret".to_owned()))
         };
+        config.keys.extend(keystore.keys);
 
-        let method = req.method.as_ref();
+        HmacAuthentication::with_config(digest, secret, hmac_header_key, config)
+    }
+
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware along with a handle to
+    /// rotate their secret
+    ///
+    /// `secret` becomes the primary secret of a `SecretRotation`, and `config.previous_secrets`
+    /// its secondary secrets. The rotation replaces `config.secret_rotation`. Returns an error if
+    /// `config` is not valid for `digest`.
+    pub fn with_secret_rotation<K, S>(digest: D, secret: K, hmac_header_key: S,
+                                      mut config: Config)
+        -> Result<(HmacAuthentication<D>, HmacAuthentication<D>, Arc<SecretRotation>)>
+        where K: Into<SecretKey>,
+              S: Into<String>
+    {
+        // The secrets are checked before moving into the rotation, which then holds the only copy
+        let secret = secret.into();
+        try!(config.validate(&digest, Some(&secret)));
 
-        let method_hmac = hmac256(&self.secret, method.as_bytes());
-        let url: url::Url = req.url.clone().into();
-        let path_hmac = hmac256(&self.secret, url.path().as_bytes());
-        let body_hmac = hmac256(&self.secret, body.as_bytes());
+        let rotation = Arc::new(SecretRotation::new(secret));
+        for previous in config.previous_secrets.drain(..) {
+            rotation.add_secondary_secret(previous);
+        }
+        config.secret_rotation = Some(rotation.clone());
+
+        let auth = try!(HmacAuthentication::from_parts(digest, None, hmac_header_key.into(),
+                                                       config));
+        Ok((auth.clone(), auth, rotation))
+    }
+}
 
-        let mut merged_hmac = Hmac256::new(&self.secret);
+impl<D: HmacDigest, V: Verifier, C: Canonicalizer> HmacAuthentication<D, V, C> {
+    /// Compare MACs with `verifier` instead of the default `ConstantTimeVerifier`
+    pub fn with_verifier<W: Verifier>(self, verifier: W) -> HmacAuthentication<D, W, C> {
+        let inner = &self.inner;
+        self.with_options(verifier, inner.canonicalizer.clone(), inner.nonces.clone(),
+                          inner.audit.clone())
+    }
 
-        merged_hmac.input(&method_hmac[..])
-                   .input(&path_hmac[..])
-                   .input(&body_hmac[..]);
+    /// Remember nonces in `cache` instead of the default `MemoryReplayCache`
+    ///
+    /// Use a shared cache when several instances serve the same clients.
+    pub fn with_replay_cache<R: ReplayCache>(self, cache: R) -> HmacAuthentication<D, V, C> {
+        let inner = &self.inner;
+        self.with_options(inner.verifier.clone(), inner.canonicalizer.clone(), Arc::new(cache),
+                          inner.audit.clone())
+    }
 
-        Ok(merged_hmac.finalize())
+    /// Report every verification attempt to `audit`
+    ///
+    /// Set this on the BeforeMiddleware; the AfterMiddleware only signs responses.
+    pub fn with_audit<A: KeyAudit>(self, audit: A) -> HmacAuthentication<D, V, C> {
+        let inner = &self.inner;
+        self.with_options(inner.verifier.clone(), inner.canonicalizer.clone(),
+                          inner.nonces.clone(), Some(Arc::new(audit)))
     }
 
-    fn compute_response_hmac(&self, res: &mut iron::Response) -> Result<Vec<u8>> {
-        let body: Vec<u8> = match res.body {
-            Some(ref mut body) => {
-                let mut buf = util::Buffer::new();
-                try!(body.write_body(&mut buf));
-                buf.to_inner()
+    /// Compute request HMACs with `canonicalizer` instead of the default `DefaultCanonicalizer`
+    ///
+    /// Both halves of the middleware should use the same canonicalizer.
+    pub fn with_canonicalizer<K>(self, canonicalizer: K) -> HmacAuthentication<D, V, K>
+        where K: Canonicalizer
+    {
+        let inner = &self.inner;
+        self.with_options(inner.verifier.clone(), canonicalizer, inner.nonces.clone(),
+                          inner.audit.clone())
+    }
+
+    /// This half with the given verifier, canonicalizer, replay cache, and audit hook
+    ///
+    /// Every option is applied through here, giving the half an `Inner` of its own that shares
+    /// the secret and `Config` with the other half.
+    pub(crate) fn with_options<W, K>(&self, verifier: W, canonicalizer: K,
+                                     nonces: Arc<dyn ReplayCache>,
+                                     audit: Option<Arc<dyn KeyAudit>>)
+        -> HmacAuthentication<D, W, K>
+        where W: Verifier,
+              K: Canonicalizer
+    {
+        let inner = &*self.inner;
+        HmacAuthentication {
+            inner: Arc::new(Inner {
+                digest: inner.digest.clone(),
+                secret: inner.secret.clone(),
+                hmac_header_key: inner.hmac_header_key.clone(),
+                config: inner.config.clone(),
+                verifier: verifier,
+                canonicalizer: canonicalizer,
+                nonces: nonces,
+                audit: audit
+            })
+        }
+    }
+
+    /// HMAC function for the key id and algorithm name carried by a signature scheme's parameters
+    ///
+    /// A key id selects a configured key, whose algorithm a given algorithm name must match.
+    /// Otherwise the secret of `req` is used with the middleware's digest or, if named, one of the
+    /// allowed algorithms.
+    fn keyed_mac<'a>(&'a self, req: &iron::Request, key_id: Option<&str>,
+                     algorithm: Option<&str>)
+        -> Result<Box<dyn Fn(&[u8]) -> Result<Vec<u8>> + 'a>>
+    {
+        if let Some(id) = key_id {
+            let key = match try!(self.key(id)) {
+                Some(key) => key,
+                None if self.inner.config.secret_provider.is_some() => {
+                    return self.provided_mac(req, key_id, algorithm);
+                },
+                None => return Err(Error::UnknownKeyId(id.to_owned()))
+            };
+
+            if let Some(name) = algorithm {
+                if !key.algorithm.name().eq_ignore_ascii_case(name) {
+                    return Err(Error::UnsupportedAlgorithm(name.to_owned()));
+                }
+            }
+
+            return Ok(Box::new(move |data| key.algorithm.try_hmac(&key.secret, data)));
+        }
+
+        self.provided_mac(req, None, algorithm)
+    }
+
+    /// HMAC function with the secret of `req` and the middleware's digest or a named algorithm
+    fn provided_mac<'a>(&'a self, req: &iron::Request, key_id: Option<&str>,
+                        algorithm: Option<&str>)
+        -> Result<Box<dyn Fn(&[u8]) -> Result<Vec<u8>> + 'a>>
+    {
+        let secret = try!(self.secret_for(req, key_id));
+        match algorithm {
+            Some(name) => {
+                let algorithm: Algorithm = try!(name.parse());
+                if !self.inner.config.allowed_algorithms.contains(&algorithm) {
+                    return Err(Error::UnsupportedAlgorithm(name.to_owned()));
+                }
+                Ok(Box::new(move |data| algorithm.try_hmac(&secret, data)))
             },
-            None => Vec::new()
+            None => Ok(Box::new(move |data| self.inner.digest.try_hmac(&secret, data)))
+        }
+    }
+
+    /// The secret of `req`, from `Config::secret_provider` or else the middleware's own
+    fn secret_for<'a>(&'a self, req: &iron::Request, key_id: Option<&str>)
+        -> Result<Cow<'a, SecretKey>>
+    {
+        let provider = match self.inner.config.secret_provider {
+            Some(ref provider) => provider,
+            None => return self.own_secret()
         };
 
-        let response_hmac = hmac256(&self.secret, &body[..]);
+        match (provider.secret_for(req, key_id), key_id) {
+            (Some(secret), _) => Ok(Cow::Owned(secret)),
+            (None, Some(id)) => Err(Error::UnknownKeyId(id.to_owned())),
+            (None, None) => Err(Error::NoSecret)
+        }
+    }
 
-        // Need to reset body now that we've written it
-        res.body = Some(Box::new(body));
+    /// The primary secret of `Config::secret_rotation`, or else the middleware's secret
+    fn own_secret<'a>(&'a self) -> Result<Cow<'a, SecretKey>> {
+        match (&self.inner.config.secret_rotation, &self.inner.secret) {
+            (&Some(ref rotation), _) => Ok(Cow::Owned(rotation.primary())),
+            (&None, &Some(ref secret)) => Ok(Cow::Borrowed(secret)),
+            (&None, &None) => Err(Error::NoSecret)
+        }
+    }
 
-        Ok(response_hmac)
+    /// The HMAC-SHA256 secret of the access key `id` of a cloud provider compatible scheme
+    ///
+    /// `id` names a key using `Algorithm::Sha256`, or is passed to `Config::secret_provider`.
+    /// When none of `Config::keys`, `Config::secret_store`, and `Config::secret_provider` is
+    /// configured the middleware's secret is used for any id.
+    fn sha256_key<'a>(&'a self, req: &iron::Request, id: &str) -> Result<Cow<'a, SecretKey>> {
+        match try!(self.key(id)) {
+            Some(key) if key.algorithm == Algorithm::Sha256 => Ok(key_secret(key)),
+            Some(key) => Err(Error::UnsupportedAlgorithm(key.algorithm.name().to_owned())),
+            None if self.inner.config.secret_provider.is_some() => self.secret_for(req, Some(id)),
+            None if self.inner.config.keys.is_empty() &&
+                    self.inner.config.secret_store.is_none() => {
+                self.own_secret()
+            },
+            None => Err(Error::UnknownKeyId(id.to_owned()))
+        }
     }
-}
 
-impl BeforeMiddleware for Hmac256Authentication {
-    fn before(&self, req: &mut iron::Request) -> IronResult<()> {
-        let computed = try!(self.compute_request_hmac(req));
-        let supplied = match req.headers.get_raw(&self.hmac_header_key[..]) {
-            Some(hmac) => try!(util::from_hex(&hmac[0][..])),
+    /// The key `id` of `Config::keys` or, failing that, `Config::secret_store`
+    ///
+    /// Fails with `Error::KeyNotValid` if the key is outside its validity window.
+    fn key<'a>(&'a self, id: &str) -> Result<Option<Cow<'a, Key>>> {
+        let key = match self.inner.config.keys.get(id) {
+            Some(key) => Some(Cow::Borrowed(key)),
+            None => {
+                let store = self.inner.config.secret_store.as_ref();
+                store.and_then(|store| store.key(id)).map(Cow::Owned)
+            }
+        };
+
+        if let Some(ref key) = key {
+            try!(key.check_validity(id));
+        }
+
+        Ok(key)
+    }
+
+    /// The trimmed key id named in the request's key id header, if any
+    fn requested_key_id<'r>(&self, req: &'r iron::Request) -> Result<Option<&'r str>> {
+        let header = match self.inner.config.key_id_header {
+            Some(ref header) => header,
+            None => return Ok(None)
+        };
+
+        util::single_header(req, &header[..], self.inner.config.duplicate_headers)
+    }
+
+    /// The scopes and metadata of the key the request named, if it is a key of `Config::keys` or
+    /// `Config::secret_store`
+    fn verified_key(&self, req: &iron::Request) -> Result<Option<VerifiedKey>> {
+        let id = match try!(self.requested_key_id(req)) {
+            Some(id) => id,
+            None => return Ok(None)
+        };
+
+        Ok(try!(self.key(id)).map(|key| VerifiedKey {
+            id: id.to_owned(),
+            scopes: key.scopes.clone(),
+            metadata: key.metadata.clone()
+        }))
+    }
+
+    /// The secret and, if not the middleware's digest, the algorithm the request selects
+    fn requested_signing<'a>(&'a self, req: &iron::Request)
+        -> Result<(Cow<'a, SecretKey>, Option<Algorithm>)>
+    {
+        let key_id = try!(self.requested_key_id(req));
+        if let Some(id) = key_id {
+            match try!(self.key(id)) {
+                Some(key) => {
+                    let algorithm = key.algorithm;
+                    return Ok((key_secret(key), Some(algorithm)));
+                },
+                None if self.inner.config.secret_provider.is_some() => (),
+                None => return Err(Error::UnknownKeyId(id.to_owned()))
+            }
+        }
+
+        let secret = try!(self.secret_for(req, key_id));
+        match try!(self.requested_version(req)).and_then(|version| version.algorithm) {
+            Some(algorithm) => Ok((secret, Some(algorithm))),
+            None => Ok((secret, try!(self.requested_algorithm(req))))
+        }
+    }
+
+    /// `secret` followed by `Config::previous_secrets` if it is the middleware's own secret, or
+    /// by the secondary secrets of `Config::secret_rotation` if it is the primary secret
+    ///
+    /// With `Config::date_scoped_service`, each is replaced by its keys for the dates within
+    /// `Config::date_max_skew`.
+    fn accepted_secrets<'s>(&'s self, secret: &'s SecretKey, algorithm: Option<Algorithm>)
+        -> Vec<Cow<'s, SecretKey>>
+    {
+        let mut secrets = vec![Cow::Borrowed(secret)];
+        if self.inner.secret.as_ref().map_or(false, |own| std::ptr::eq(secret, &**own)) {
+            secrets.extend(self.inner.config.previous_secrets.iter().map(Cow::Borrowed));
+        } else if let Some(ref rotation) = self.inner.config.secret_rotation {
+            secrets.extend(rotation.secondaries(secret).into_iter().map(Cow::Owned));
+        }
+
+        if self.inner.config.date_scoped_service.is_none() {
+            return secrets;
+        }
+
+        // One time per distinct date, so each key is derived once
+        let now = SystemTime::now();
+        let skew = self.inner.config.date_max_skew
+                              .unwrap_or(Duration::from_secs(replay::DEFAULT_TIMESTAMP_MAX_AGE));
+        let date = |time: SystemTime| {
+            util::format_date(time.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()))
+        };
+        let mut times = vec![now];
+        for &time in &[now - skew, now + skew] {
+            if times.iter().all(|&other| date(other) != date(time)) {
+                times.push(time);
+            }
+        }
+
+        secrets.iter().flat_map(|secret| {
+            times.iter().map(move |&time| self.date_scoped(secret.clone(), algorithm, time))
+        }).collect()
+    }
+
+    /// `secret` derived for the date of `time` and `Config::date_scoped_service`, if set
+    fn date_scoped<'s>(&self, secret: Cow<'s, SecretKey>, algorithm: Option<Algorithm>,
+                       time: SystemTime)
+        -> Cow<'s, SecretKey>
+    {
+        let service = match self.inner.config.date_scoped_service {
+            Some(ref service) => service,
+            None => return secret
+        };
+
+        Cow::Owned(match algorithm {
+            Some(algorithm) => secret.date_scoped(&algorithm, time, service),
+            None => secret.date_scoped(&self.inner.digest, time, service)
+        })
+    }
+
+    /// The configured version declared in the version header or HMAC header prefix, if any
+    fn requested_version(&self, req: &iron::Request) -> Result<Option<&Version>> {
+        if self.inner.config.versions.is_empty() {
+            return Ok(None);
+        }
+
+        let name = match self.inner.config.version_header {
+            Some(ref header) => {
+                let duplicates = self.inner.config.duplicate_headers;
+                match try!(util::single_header(req, &header[..], duplicates)) {
+                    Some(name) => name,
+                    None => return Ok(None)
+                }
+            },
             None => {
-                let err = Error::MissingHmacHeader(self.hmac_header_key.clone());
-                return Err(::iron::IronError::new(err, ::iron::status::Forbidden));
+                let values = try!(self.hmac_header_values(req));
+                match values.first().and_then(|value| split_version(value).0) {
+                    Some(name) => name,
+                    None => return Ok(None)
+                }
             }
         };
 
-        if computed.len() != supplied.len() {
+        match self.inner.config.versions.get(name) {
+            Some(version) => Ok(Some(version)),
+            None => Err(Error::UnsupportedVersion(name.to_owned()))
+        }
+    }
+
+    /// The trimmed values of the request's HMAC header without the signature prefix
+    fn hmac_header_values<'r>(&self, req: &'r iron::Request) -> Result<Vec<&'r str>> {
+        let values = try!(self.accepted_hmac_header(req));
+
+        Ok(values.into_iter().map(|value| {
+            match self.inner.config.signature_prefix {
+                Some(ref prefix) if value.starts_with(&prefix[..]) => &value[prefix.len()..],
+                _ => value
+            }
+        }).collect())
+    }
+
+    /// The trimmed values of the HMAC header or a fallback header, per `Config::header_conflict`
+    /// and `Config::duplicate_headers`
+    fn accepted_hmac_header<'r>(&self, req: &'r iron::Request) -> Result<Vec<&'r str>> {
+        let fallbacks = &self.inner.config.fallback_hmac_headers;
+        let names = Some(&self.inner.hmac_header_key).into_iter().chain(fallbacks);
+
+        let mut accepted: Option<(&str, Vec<&'r str>)> = None;
+        for name in names {
+            let values = match req.headers.get_raw(&name[..]) {
+                Some(values) => values,
+                None => continue
+            };
+
+            let mut trimmed = Vec::with_capacity(values.len());
+            for value in values.iter() {
+                trimmed.push(try!(std::str::from_utf8(&value[..])).trim());
+            }
+            let reject = self.inner.config.duplicate_headers == DuplicateHeaders::Reject;
+            if trimmed.len() > 1 && reject {
+                return Err(Error::MalformedSignature(format!("multiple {} headers", name)));
+            }
+
+            match accepted {
+                None => accepted = Some((&name[..], trimmed)),
+                Some((first, ref first_values)) => match self.inner.config.header_conflict {
+                    HeaderConflict::PreferFirst => break,
+                    HeaderConflict::RequireEqual if trimmed == *first_values => (),
+                    _ => {
+                        let reason = format!("conflicting {} and {} headers", first, name);
+                        return Err(Error::MalformedSignature(reason));
+                    }
+                }
+            }
+        }
+
+        Ok(accepted.map(|(_, values)| values).unwrap_or_default())
+    }
+
+    /// Decode the MACs the client supplied, skipping any version prefix
+    fn supplied_macs(&self, req: &iron::Request) -> IronResult<Vec<Vec<u8>>> {
+        let values = try!(self.hmac_header_values(req));
+        if values.is_empty() {
+            let err = Error::MissingHmacHeader(self.inner.hmac_header_key.clone());
+            return Err(::iron::IronError::new(err, ::iron::status::Forbidden));
+        }
+
+        let mut macs = Vec::with_capacity(values.len());
+        for value in values {
+            let encoded = if self.inner.config.versions.is_empty() {
+                value
+            } else {
+                split_version(value).1
+            };
+            let encoding = if self.inner.config.lenient_decoding {
+                SignatureEncoding::detect(encoded)
+            } else {
+                self.inner.config.encoding
+            };
+
+            macs.push(try!(encoding.decode(encoded)));
+        }
+
+        Ok(macs)
+    }
+
+    /// The allowed algorithm declared in the request's algorithm header, if any
+    fn requested_algorithm(&self, req: &iron::Request) -> Result<Option<Algorithm>> {
+        let header = match self.inner.config.algorithm_header {
+            Some(ref header) => header,
+            None => return Ok(None)
+        };
+
+        let duplicates = self.inner.config.duplicate_headers;
+        let name = match try!(util::single_header(req, &header[..], duplicates)) {
+            Some(name) => name,
+            None => return Ok(None)
+        };
+
+        let algorithm: Algorithm = try!(name.parse());
+        if !self.inner.config.allowed_algorithms.contains(&algorithm) {
+            return Err(Error::UnsupportedAlgorithm(name.to_owned()));
+        }
+
+        Ok(Some(algorithm))
+    }
+
+    /// Compute the request HMAC with the canonicalization options of `config`
+    fn compute_request_hmac(&self, config: &Config, secret: &SecretKey,
+                            algorithm: Option<Algorithm>, req: &mut iron::Request)
+        -> Result<Vec<u8>>
+    {
+        // Canonicalizers take an infallible MAC, so the first failure is kept and returned after
+        let failure = RefCell::new(None);
+        let mac = |result: Result<Vec<u8>>| match result {
+            Ok(hmac) => hmac,
+            Err(err) => {
+                failure.borrow_mut().get_or_insert(err);
+                Vec::new()
+            }
+        };
+        let computed = match algorithm {
+            Some(algorithm) => {
+                try!(self.inner.canonicalizer.request_mac(req, config, |data| {
+                    mac(algorithm.try_hmac(secret, data))
+                }))
+            },
+            None => {
+                try!(self.inner.canonicalizer.request_mac(req, config, |data| {
+                    mac(self.inner.digest.try_hmac(secret, data))
+                }))
+            }
+        };
+
+        if let Some(err) = failure.into_inner() {
+            return Err(err);
+        }
+
+        Ok(self.inner.config.truncate(computed))
+    }
+
+    /// The canonicalization under which `supplied` matches the request HMAC, if any
+    fn matched_canonicalization(&self, secret: &SecretKey, algorithm: Option<Algorithm>,
+                                supplied: &[u8], req: &mut iron::Request)
+        -> Result<Option<Canonicalization>>
+    {
+        if try!(self.matches(&self.inner.config, secret, algorithm, supplied, req)) {
+            return Ok(Some(Canonicalization::Primary));
+        }
+
+        if let Some(ref legacy) = self.inner.config.legacy {
+            if try!(self.matches(legacy, secret, algorithm, supplied, req)) {
+                return Ok(Some(Canonicalization::Legacy));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Whether `supplied` matches the request HMAC computed with the options of `config`
+    fn matches(&self, config: &Config, secret: &SecretKey, algorithm: Option<Algorithm>,
+               supplied: &[u8], req: &mut iron::Request)
+        -> Result<bool>
+    {
+        let computed = try!(self.compute_request_hmac(config, secret, algorithm, req));
+        Ok(computed.len() == supplied.len() && self.inner.verifier.verify(&computed[..], supplied))
+    }
+
+    /// The response HMAC with the secret and algorithm `req` was verified with
+    fn response_hmac(&self, req: &iron::Request, res: &mut iron::Response) -> Result<Vec<u8>> {
+        let (secret, algorithm) = try!(self.requested_signing(req));
+        let secret = match self.inner.config.response_secret {
+            Some(ref response_secret) => Cow::Borrowed(response_secret),
+            None => secret
+        };
+        let secret = self.date_scoped(secret, algorithm, SystemTime::now());
+        let hmac = match algorithm {
+            Some(algorithm) => {
+                let hmac = try!(self.compute_response_hmac(&algorithm, &secret, res));
+                if let Some(ref header) = self.inner.config.algorithm_header {
+                    let name = algorithm.name().as_bytes().to_vec();
+                    res.headers.set_raw(header.clone(), vec![name]);
+                }
+                hmac
+            },
+            None => try!(self.compute_response_hmac(&self.inner.digest, &secret, res))
+        };
+
+        Ok(self.inner.config.truncate(hmac))
+    }
+
+    fn compute_response_hmac<H>(&self, digest: &H, secret: &SecretKey, res: &mut iron::Response)
+        -> Result<Vec<u8>>
+        where H: HmacDigest
+    {
+        try!(util::with_response_body(res, |body| digest.try_hmac(secret, body)))
+    }
+}
+
+/// The secret of a key, borrowed if the key is
+fn key_secret<'a>(key: Cow<'a, Key>) -> Cow<'a, SecretKey> {
+    match key {
+        Cow::Borrowed(key) => Cow::Borrowed(&key.secret),
+        Cow::Owned(key) => Cow::Owned(key.secret)
+    }
+}
+
+/// Split a version prefix such as `v2=` from a signature header value
+///
+/// Padding of a base64 encoded MAC is not mistaken for a prefix.
+fn split_version(value: &str) -> (Option<&str>, &str) {
+    match value.find('=') {
+        Some(index) if !value[index + 1..].trim_matches('=').is_empty() => {
+            (Some(&value[..index]), &value[index + 1..])
+        },
+        _ => (None, value)
+    }
+}
+
+impl<D, V, C> BeforeMiddleware for HmacAuthentication<D, V, C>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    fn before(&self, req: &mut iron::Request) -> IronResult<()> {
+        let result = self.verify(req);
+
+        if let Some(ref audit) = self.inner.audit {
+            let outcome = match result {
+                Ok(()) => AuditOutcome::Verified,
+                Err(ref err) => AuditOutcome::Rejected(err.error.to_string())
+            };
+            let key_id = self.requested_key_id(req).unwrap_or(None);
+            audit.record(&AuditEvent::new(req, key_id, outcome));
+        }
+
+        result
+    }
+}
+
+impl<D, V, C> HmacAuthentication<D, V, C>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    /// Verify `req` with the configured signing scheme
+    fn verify(&self, req: &mut iron::Request) -> IronResult<()> {
+        match self.inner.config.scheme {
+            Scheme::Hmac => (),
+            Scheme::OAuth1 => return oauth1::verify(self, req),
+            Scheme::HttpMessageSignatures => return http_signatures::verify(self, req),
+            Scheme::DraftCavage => return cavage::verify(self, req),
+            #[cfg(feature = "aws-sigv4")]
+            Scheme::AwsSigV4(ref scope) => return sigv4::verify(self, scope, req),
+            Scheme::AzureSharedKey => return azure::verify(self, req),
+            Scheme::Hawk => return hawk::verify(self, req),
+            Scheme::Stripe => return stripe::verify(self, req),
+            Scheme::Mailgun => return mailgun::verify(self, req),
+            Scheme::GitLabToken => return gitlab::verify(self, req),
+            Scheme::GitLabSigningToken => return gitlab::verify_signed(self, req)
+        }
+
+        if let Some(ref scheme) = self.inner.config.authorization_scheme {
+            try!(authorization::apply(req, &self.inner.config, scheme,
+                                      &self.inner.hmac_header_key[..]));
+        }
+
+        if self.inner.config.structured_signature {
+            try!(structured::apply(req, &self.inner.config, &self.inner.hmac_header_key[..]));
+        }
+
+        if let Some(ref public_key) = self.inner.config.public_key {
+            try!(signature::verify(self, &**public_key, req));
+            return self.check_replay(req);
+        }
+
+        let (secret, algorithm) = try!(self.requested_signing(req));
+        let supplied = try!(self.supplied_macs(req));
+        let version = try!(self.requested_version(req));
+        let require_all = self.inner.config.duplicate_headers == DuplicateHeaders::RequireAll;
+
+        let mut verified = false;
+        let mut matched = None;
+        for mac in &supplied {
+            let mut matches = false;
+            for secret in self.accepted_secrets(&secret, algorithm) {
+                matches = match version {
+                    Some(version) => {
+                        try!(self.matches(&version.config, &secret, algorithm, mac, req))
+                    },
+                    None => match try!(self.matched_canonicalization(&secret, algorithm, mac,
+                                                                     req)) {
+                        Some(canonicalization) => {
+                            matched = matched.or(Some(canonicalization));
+                            true
+                        },
+                        None => false
+                    }
+                };
+                if matches {
+                    break;
+                }
+            }
+
+            if !matches && require_all {
+                forbidden!();
+            }
+            verified = verified || matches;
+            if verified && !require_all {
+                break;
+            }
+        }
+        if !verified {
             forbidden!();
         }
+        if let Some(matched) = matched {
+            req.extensions.insert::<MatchedCanonicalization>(matched);
+        }
 
-        if util::contant_time_equals(&computed[..], &supplied[..]) {
-            Ok(())
-        } else {
-            forbidden!()
+        try!(self.check_replay(req));
+        let fingerprint = secret.fingerprint(try!(self.requested_key_id(req)));
+        req.extensions.insert::<KeyFingerprint>(fingerprint);
+        if let Some(key) = try!(self.verified_key(req)) {
+            req.extensions.insert::<VerifiedKey>(key);
         }
+
+        Ok(())
+    }
+
+    /// Reject stale, expired, and replayed requests as configured
+    fn check_replay(&self, req: &mut iron::Request) -> IronResult<()> {
+        try!(replay::check_timestamp(req, &self.inner.config));
+        try!(replay::check_date(req, &self.inner.config));
+        try!(replay::check_expires(req, &self.inner.config));
+        let key_id = try!(self.requested_key_id(req));
+        try!(replay::check_nonce(req, &self.inner.config, key_id, &*self.inner.nonces));
+        try!(idempotency::check_idempotency_key(req, &self.inner.config));
+
+        Ok(())
     }
 }
 
-impl AfterMiddleware for Hmac256Authentication {
-    fn after(&self, _: &mut iron::Request, mut res: iron::Response) -> IronResult<Response> {
-        let hmac = try!(self.compute_response_hmac(&mut res));
-        let hmac_hex_encoded = util::to_hex(&hmac[..]).as_bytes().to_vec();
-        res.headers.set_raw(self.hmac_header_key.clone(), vec![hmac_hex_encoded]);
+impl<D, V, C> AfterMiddleware for HmacAuthentication<D, V, C>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    fn after(&self, req: &mut iron::Request, mut res: iron::Response) -> IronResult<Response> {
+        if self.inner.config.scheme == Scheme::OAuth1 || self.inner.config.unsigned_responses {
+            return Ok(res);
+        }
+
+        let hmac = match self.inner.config.public_key {
+            Some(ref public_key) => match try!(signature::sign_response(&**public_key, &mut res)) {
+                Some(signature) => signature,
+                None => return Ok(res)
+            },
+            None => try!(self.response_hmac(req, &mut res))
+        };
+        let mut hmac_encoded = self.inner.config.signature_prefix.clone().unwrap_or_default();
+        hmac_encoded.push_str(&self.inner.config.encoding.encode(&hmac[..]));
+        let header = self.inner.config.response_hmac_header.as_ref()
+                                                           .unwrap_or(&self.inner.hmac_header_key);
+        res.headers.set_raw(header.clone(), vec![hmac_encoded.into_bytes()]);
+        if let Some(ref header) = self.inner.config.fingerprint_header {
+            if let Some(fingerprint) = req.extensions.get::<KeyFingerprint>() {
+                res.headers.set_raw(header.clone(), vec![fingerprint.0.clone().into_bytes()]);
+            }
+        }
         Ok(res)
     }
 }
@@ -0,0 +1,94 @@
+//! Mailgun webhook signatures
+//!
+//! The signature is carried in the body, either as the `signature` object of a JSON body with
+//! `timestamp`, `token`, and `signature` members, or as form parameters of the same names. The
+//! signature is the hex HMAC-SHA256 of the timestamp followed by the token.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use rustc_serialize::json::Json;
+use url;
+
+use canonical::CanonicalRequest;
+use error::{Error, Result};
+use replay;
+use util;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// The signature fields of a delivery
+struct Signature {
+    timestamp: String,
+    token: String,
+    signature: String,
+}
+
+/// Verify the Mailgun signature in the body of `req` with the secret of `auth`
+///
+/// The timestamp must be within `Config::timestamp_max_age`, and a token may not be reused.
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let body = try!(CanonicalRequest::from_request(req)).body;
+    let fields = try!(signature(&body));
+    let supplied = try!(util::from_hex(fields.signature.as_bytes()));
+
+    let mac = try!(auth.keyed_mac(req, None, None));
+    let computed = try!(mac(format!("{}{}", fields.timestamp, fields.token).as_bytes()));
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    let seconds: u64 = match fields.timestamp.parse() {
+        Ok(seconds) => seconds,
+        Err(_) => {
+            let err = Error::StaleRequest(format!("invalid timestamp {}", fields.timestamp));
+            return Err(err.into());
+        }
+    };
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), max_age));
+
+    if !try!(auth.inner.nonces.insert_if_absent(&fields.token, max_age * 2)) {
+        let err = Error::ReplayedRequest(format!("token {} was already used", fields.token));
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// The signature fields of a JSON or form encoded body
+fn signature(body: &str) -> Result<Signature> {
+    let fields = match Json::from_str(body) {
+        Ok(json) => {
+            let field = |name: &str| {
+                json.find_path(&["signature", name])
+                    .and_then(|value| value.as_string())
+                    .map(|value| value.to_owned())
+            };
+            (field("timestamp"), field("token"), field("signature"))
+        },
+        Err(_) => {
+            let params: Vec<(String, String)> =
+                url::form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+            let field = |name: &str| {
+                params.iter()
+                      .find(|&&(ref param, _)| param == name)
+                      .map(|&(_, ref value)| value.clone())
+            };
+            (field("timestamp"), field("token"), field("signature"))
+        }
+    };
+
+    match fields {
+        (Some(timestamp), Some(token), Some(signature)) => {
+            Ok(Signature { timestamp: timestamp, token: token, signature: signature })
+        },
+        _ => Err(Error::MalformedSignature("missing timestamp, token, or signature".to_owned()))
+    }
+}
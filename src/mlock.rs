@@ -0,0 +1,97 @@
+//! Keeping secret memory out of swap
+//!
+//! With the `mlock` feature, the pages holding the bytes of each `SecretKey` are locked into RAM
+//! with `mlock(2)` until the last key on them is dropped. Pages are reference counted, since
+//! unlocking a page shared by two keys when the first is dropped would expose the second.
+
+#[cfg(all(feature = "mlock", unix))]
+mod imp {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use libc;
+
+    /// Number of keys on each page, and whether the page is locked
+    static PAGES: Mutex<Option<HashMap<usize, (usize, bool)>>> = Mutex::new(None);
+
+    /// Whether locking any page failed
+    static FAILED: AtomicBool = AtomicBool::new(false);
+
+    /// Lock the pages of the `capacity` bytes at `ptr`
+    pub fn lock(ptr: *const u8, capacity: usize) {
+        let mut pages = PAGES.lock().unwrap();
+        let pages = pages.get_or_insert_with(HashMap::new);
+        for page in page_range(ptr, capacity) {
+            let entry = pages.entry(page).or_insert((0, false));
+            if !entry.1 {
+                entry.1 = unsafe { libc::mlock(page as *const libc::c_void, page_size()) } == 0;
+                if !entry.1 {
+                    FAILED.store(true, Ordering::SeqCst);
+                }
+            }
+            entry.0 += 1;
+        }
+    }
+
+    /// Unlock the pages of the `capacity` bytes at `ptr` no other key is on
+    pub fn unlock(ptr: *const u8, capacity: usize) {
+        let mut pages = PAGES.lock().unwrap();
+        let pages = pages.get_or_insert_with(HashMap::new);
+        for page in page_range(ptr, capacity) {
+            let unused = match pages.get_mut(&page) {
+                Some(entry) => {
+                    entry.0 -= 1;
+                    entry.0 == 0
+                },
+                None => false
+            };
+            if unused {
+                if let Some((_, true)) = pages.remove(&page) {
+                    unsafe { libc::munlock(page as *const libc::c_void, page_size()) };
+                }
+            }
+        }
+    }
+
+    /// Whether the memory of every `SecretKey` so far was locked
+    ///
+    /// Locking fails when the process may not lock as much memory as its keys need, e.g. because
+    /// of a low `RLIMIT_MEMLOCK`. Deployments that must keep keys out of swap should check this
+    /// once their keys are loaded.
+    pub fn secrets_locked() -> bool {
+        !FAILED.load(Ordering::SeqCst)
+    }
+
+    /// Start addresses of the pages spanned by the `capacity` bytes at `ptr`
+    fn page_range(ptr: *const u8, capacity: usize) -> Vec<usize> {
+        if capacity == 0 {
+            return Vec::new();
+        }
+
+        let size = page_size();
+        let first = ptr as usize / size * size;
+        let last = (ptr as usize + capacity - 1) / size * size;
+        (0..(last - first) / size + 1).map(|index| first + index * size).collect()
+    }
+
+    fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+}
+
+#[cfg(all(feature = "mlock", unix))]
+pub use self::imp::{lock, secrets_locked, unlock};
+
+/// Without the `mlock` feature or on platforms other than Unix, memory is not locked
+#[cfg(not(all(feature = "mlock", unix)))]
+pub fn lock(_ptr: *const u8, _capacity: usize) {}
+
+#[cfg(not(all(feature = "mlock", unix)))]
+pub fn unlock(_ptr: *const u8, _capacity: usize) {}
+
+/// Whether the memory of every `SecretKey` so far was locked; never on platforms other than Unix
+#[cfg(all(feature = "mlock", not(unix)))]
+pub fn secrets_locked() -> bool {
+    false
+}
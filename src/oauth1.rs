@@ -0,0 +1,147 @@
+//! OAuth 1.0 (RFC 5849) signature verification
+//!
+//! Legacy clients such as LTI tool consumers sign an OAuth signature base string with
+//! HMAC-SHA1 and send the base64 encoded result as the `oauth_signature` parameter. Only the
+//! consumer secret is used; there is no token secret.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use bodyparser;
+use url;
+
+use iron::prelude::*;
+use rustc_serialize::base64::FromBase64;
+
+use error::{Error, Result};
+use replay;
+use util;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, SecretKey, Verifier};
+
+/// Name of the parameter carrying the signature
+const SIGNATURE_PARAM: &'static str = "oauth_signature";
+
+/// Verify the OAuth 1.0 signature of `req` with the middleware's secret as consumer secret
+///
+/// `oauth_timestamp` must be within `Config::timestamp_max_age`, and an `oauth_nonce` may not be
+/// reused by the same consumer.
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let params = try!(request_params(req, auth));
+    let param = |name: &str| {
+        params.iter()
+              .find(|&&(ref param, _)| param == name)
+              .map(|&(_, ref value)| &value[..])
+    };
+
+    let supplied = match param(SIGNATURE_PARAM).map(|signature| signature.from_base64()) {
+        Some(Ok(signature)) => signature,
+        _ => forbidden!()
+    };
+
+    let consumer_secret = try!(auth.secret_for(req, None));
+    let key = SecretKey::new(format!("{}&", util::percent_encode(&consumer_secret)).as_bytes());
+    let computed = try!(auth.inner.digest.try_hmac(&key, base_string(req, &params).as_bytes()));
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    let max_age = replay::timestamp_max_age(&auth.inner.config);
+    let seconds: u64 = match param("oauth_timestamp").map(str::parse) {
+        Some(Ok(seconds)) => seconds,
+        _ => return Err(Error::StaleRequest("missing or invalid oauth_timestamp".to_owned()).into())
+    };
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), max_age));
+
+    let nonce = match param("oauth_nonce") {
+        Some(nonce) if !nonce.is_empty() => nonce,
+        _ => return Err(Error::ReplayedRequest("missing oauth_nonce".to_owned()).into())
+    };
+    let consumer_key = param("oauth_consumer_key").unwrap_or("");
+    if !try!(auth.inner.nonces.insert_if_absent(&format!("{}:{}", consumer_key, nonce),
+                                                 max_age * 2)) {
+        return Err(Error::ReplayedRequest(format!("nonce {} was already used", nonce)).into());
+    }
+
+    Ok(())
+}
+
+/// Collect the decoded parameters from the query, Authorization header, and form body
+fn request_params<D, V, C>(req: &mut iron::Request, auth: &HmacAuthentication<D, V, C>)
+    -> Result<Vec<(String, String)>>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let url: url::Url = req.url.clone().into();
+    let mut params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+    let duplicates = auth.inner.config.duplicate_headers;
+    if let Some(value) = try!(util::single_header(req, "authorization", duplicates)) {
+        let is_oauth = value.get(..6).map_or(false, |scheme| scheme.eq_ignore_ascii_case("oauth "));
+        if is_oauth {
+            for param in value[6..].split(',') {
+                let mut parts = param.trim().splitn(2, '=');
+                let name = parts.next().unwrap_or("");
+                let value = parts.next().unwrap_or("").trim_matches('"');
+                if name == "realm" {
+                    continue;
+                }
+
+                let decode = |s: &str| {
+                    url::percent_encoding::percent_decode(s.as_bytes())
+                        .decode_utf8_lossy()
+                        .into_owned()
+                };
+                params.push((decode(name), decode(value)));
+            }
+        }
+    }
+
+    let is_form = match req.headers.get_raw("content-type") {
+        Some(value) => value[0].starts_with(b"application/x-www-form-urlencoded"),
+        None => false
+    };
+
+    if is_form {
+        if let Some(body) = try!(req.get::<bodyparser::Raw>()) {
+            params.extend(url::form_urlencoded::parse(body.as_bytes()).into_owned());
+        }
+    }
+
+    Ok(params)
+}
+
+/// Build the signature base string of RFC 5849 section 3.4.1
+fn base_string(req: &iron::Request, params: &[(String, String)]) -> String {
+    let url: url::Url = req.url.clone().into();
+    let mut base_uri = format!("{}://{}", url.scheme(), url.host_str().unwrap_or(""));
+    if let Some(port) = url.port() {
+        base_uri.push_str(&format!(":{}", port));
+    }
+    base_uri.push_str(url.path());
+
+    let mut encoded: Vec<(String, String)> =
+        params.iter()
+              .filter(|&&(ref name, _)| name != SIGNATURE_PARAM)
+              .map(|&(ref name, ref value)| {
+                  (util::percent_encode(name.as_bytes()), util::percent_encode(value.as_bytes()))
+              })
+              .collect();
+    encoded.sort();
+
+    let normalized = encoded.iter()
+                            .map(|&(ref name, ref value)| format!("{}={}", name, value))
+                            .collect::<Vec<_>>()
+                            .join("&");
+
+    format!("{}&{}&{}",
+            req.method.as_ref().to_uppercase(),
+            util::percent_encode(base_uri.as_bytes()),
+            util::percent_encode(normalized.as_bytes()))
+}
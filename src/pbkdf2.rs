@@ -0,0 +1,79 @@
+//! PBKDF2 key stretching (RFC 8018)
+
+use zeroize::Zeroize;
+
+use error::{Error, Result};
+use hmac::HmacDigest;
+use ::SecretKey;
+
+/// Iterations used by `Pbkdf2::new`, following current OWASP guidance for PBKDF2-HMAC-SHA256
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+/// PBKDF2 parameters for hardening low-entropy passphrases before use as HMAC keys
+///
+/// All services sharing a passphrase must use the same parameters.
+#[derive(Debug, Clone)]
+pub struct Pbkdf2<D: HmacDigest> {
+    /// Digest used as the PBKDF2 pseudorandom function
+    pub digest: D,
+    /// Salt; should be unique per deployment
+    pub salt: Vec<u8>,
+    /// Iteration count
+    pub iterations: u32,
+    /// Length of the derived key in bytes
+    pub len: usize,
+}
+
+impl<D: HmacDigest> Pbkdf2<D> {
+    /// Parameters with `DEFAULT_ITERATIONS` deriving a key as long as the digest output
+    pub fn new(digest: D, salt: &[u8]) -> Pbkdf2<D> {
+        let len = digest.output_len();
+
+        Pbkdf2 {
+            digest: digest,
+            salt: salt.to_vec(),
+            iterations: DEFAULT_ITERATIONS,
+            len: len
+        }
+    }
+
+    /// Derive a key from `passphrase`
+    ///
+    /// Returns an error if `iterations` or `len` is zero.
+    pub fn stretch(&self, passphrase: &[u8]) -> Result<SecretKey> {
+        if self.iterations == 0 || self.len == 0 {
+            let reason = "PBKDF2 needs a nonzero iteration count and length";
+            return Err(Error::InvalidKey(reason.to_owned()));
+        }
+
+        let password = SecretKey::new(passphrase);
+        let mut key = Vec::with_capacity(self.len);
+        let mut index = 1u32;
+
+        while key.len() < self.len {
+            let mut salt = self.salt.clone();
+            salt.extend_from_slice(&[(index >> 24) as u8, (index >> 16) as u8,
+                                     (index >> 8) as u8, index as u8]);
+
+            let mut u = try!(self.digest.try_hmac(&password, &salt[..]));
+            let mut block = u.clone();
+            for _ in 1..self.iterations {
+                let next = try!(self.digest.try_hmac(&password, &u[..]));
+                u.zeroize();
+                u = next;
+                for (b, x) in block.iter_mut().zip(u.iter()) {
+                    *b ^= *x;
+                }
+            }
+
+            key.extend_from_slice(&block[..]);
+            u.zeroize();
+            block.zeroize();
+            index += 1;
+        }
+
+        key[self.len..].zeroize();
+        key.truncate(self.len);
+        Ok(SecretKey::from_vec(key))
+    }
+}
@@ -0,0 +1,443 @@
+//! Ready-made middleware for webhook providers
+//!
+//! Each provider is a `Preset`, built by `HmacAuthentication::from_preset` or by a provider
+//! specific constructor with concrete types. Adding a provider adds a variant and its
+//! configuration here. Webhook senders ignore responses, so responses are not signed.
+
+use std::time::Duration;
+
+use iron;
+use url;
+
+use canonical::{BodyCanonicalizer, CanonicalRequest, Canonicalizer, DefaultCanonicalizer};
+use config::{Config, Scheme};
+use encoding::SignatureEncoding;
+use error::{Error, Result};
+use gitlab;
+use ::{ConstantTimeVerifier, HmacAuthentication, HmacDigest, SecretKey, Sha256};
+#[cfg(feature = "hmac-sha1")]
+use ::Sha1;
+
+/// Minimum length of provider secrets
+///
+/// Providers issue the secret or let users choose it, so only an empty one, usually a missing
+/// setting, is rejected.
+const PROVIDER_MIN_SECRET_LEN: usize = 1;
+
+/// Tolerance in seconds of Stripe webhook timestamps
+const STRIPE_TOLERANCE: u64 = 300;
+
+/// Version of Slack's signing scheme
+const SLACK_VERSION: &'static str = "v0";
+
+/// Maximum age in seconds of Slack request timestamps
+const SLACK_MAX_AGE: u64 = 300;
+
+/// Middleware verifying the raw body of webhook deliveries
+pub type BodyAuthentication<D> = HmacAuthentication<D, ConstantTimeVerifier, BodyCanonicalizer>;
+
+/// Middleware built by `from_preset`
+pub type PresetAuthentication =
+    HmacAuthentication<PresetDigest, ConstantTimeVerifier, PresetCanonicalizer>;
+
+/// A webhook provider whose signing scheme is supported
+///
+/// Pass one to `HmacAuthentication::from_preset` with the provider's secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// GitHub `X-Hub-Signature-256`, see `Hmac256Authentication::github_webhook`
+    GitHub,
+    /// Meta `X-Hub-Signature-256`, see `Hmac256Authentication::meta_webhook`
+    Meta,
+    /// Meta `X-Hub-Signature`, see `HmacAuthentication::<Sha1>::meta_webhook_sha1`
+    #[cfg(feature = "hmac-sha1")]
+    MetaSha1,
+    /// Shopify `X-Shopify-Hmac-Sha256`, see `Hmac256Authentication::shopify_webhook`
+    Shopify,
+    /// Stripe `Stripe-Signature`, see `Hmac256Authentication::stripe_webhook`
+    Stripe,
+    /// GitLab `X-Gitlab-Token`, see `Hmac256Authentication::gitlab_webhook`
+    GitLab,
+    /// GitLab signing tokens, see `Hmac256Authentication::gitlab_signed_webhook`
+    ///
+    /// The secret is the `whsec_` prefixed token as text.
+    GitLabSigned,
+    /// Mailgun, see `Hmac256Authentication::mailgun_webhook`
+    Mailgun,
+    /// Slack `X-Slack-Signature`, see `Hmac256Authentication::slack`
+    Slack,
+    /// Twilio `X-Twilio-Signature`, see `HmacAuthentication::<Sha1>::twilio`
+    #[cfg(feature = "hmac-sha1")]
+    Twilio,
+}
+
+impl Preset {
+    /// Header the provider sends the signature in
+    pub fn hmac_header_key(&self) -> &'static str {
+        match *self {
+            Preset::GitHub | Preset::Meta => "x-hub-signature-256",
+            #[cfg(feature = "hmac-sha1")]
+            Preset::MetaSha1 => "x-hub-signature",
+            Preset::Shopify => "x-shopify-hmac-sha256",
+            Preset::Stripe => "stripe-signature",
+            Preset::GitLab => "x-gitlab-signature",
+            Preset::GitLabSigned => "webhook-signature",
+            Preset::Mailgun => "x-mailgun-signature",
+            Preset::Slack => "x-slack-signature",
+            #[cfg(feature = "hmac-sha1")]
+            Preset::Twilio => "x-twilio-signature",
+        }
+    }
+
+    /// Configuration of the middleware for the provider
+    pub fn config(&self) -> Config {
+        let config = match *self {
+            Preset::GitHub | Preset::Meta => Config {
+                signature_prefix: Some("sha256=".to_owned()),
+                ..Config::default()
+            },
+            #[cfg(feature = "hmac-sha1")]
+            Preset::MetaSha1 => Config {
+                signature_prefix: Some("sha1=".to_owned()),
+                ..Config::default()
+            },
+            Preset::Shopify => Config {
+                encoding: SignatureEncoding::Base64,
+                ..Config::default()
+            },
+            Preset::Stripe => Config {
+                scheme: Scheme::Stripe,
+                timestamp_max_age: Some(Duration::from_secs(STRIPE_TOLERANCE)),
+                ..Config::default()
+            },
+            Preset::GitLab => Config {
+                scheme: Scheme::GitLabToken,
+                ..Config::default()
+            },
+            Preset::GitLabSigned => Config {
+                scheme: Scheme::GitLabSigningToken,
+                ..Config::default()
+            },
+            Preset::Mailgun => Config {
+                scheme: Scheme::Mailgun,
+                ..Config::default()
+            },
+            Preset::Slack => Config {
+                signature_prefix: Some(format!("{}=", SLACK_VERSION)),
+                timestamp_header: Some("x-slack-request-timestamp".to_owned()),
+                timestamp_max_age: Some(Duration::from_secs(SLACK_MAX_AGE)),
+                ..Config::default()
+            },
+            #[cfg(feature = "hmac-sha1")]
+            Preset::Twilio => Config {
+                encoding: SignatureEncoding::Base64,
+                ..Config::default()
+            },
+        };
+
+        Config {
+            min_secret_len: Some(PROVIDER_MIN_SECRET_LEN),
+            unsigned_responses: true,
+            ..config
+        }
+    }
+
+    /// Digest of the provider's signatures
+    pub fn digest(&self) -> PresetDigest {
+        match *self {
+            Preset::GitHub | Preset::Meta | Preset::Shopify | Preset::Stripe | Preset::GitLab |
+            Preset::GitLabSigned | Preset::Mailgun | Preset::Slack => PresetDigest::Sha256,
+            #[cfg(feature = "hmac-sha1")]
+            Preset::MetaSha1 | Preset::Twilio => PresetDigest::Sha1,
+        }
+    }
+
+    /// Canonicalization of the provider's signatures
+    pub fn canonicalizer(&self) -> PresetCanonicalizer {
+        match *self {
+            Preset::GitHub | Preset::Meta | Preset::Shopify => PresetCanonicalizer::Body,
+            #[cfg(feature = "hmac-sha1")]
+            Preset::MetaSha1 => PresetCanonicalizer::Body,
+            Preset::Slack => PresetCanonicalizer::Slack,
+            #[cfg(feature = "hmac-sha1")]
+            Preset::Twilio => PresetCanonicalizer::Twilio,
+            Preset::Stripe | Preset::GitLab | Preset::GitLabSigned | Preset::Mailgun => {
+                PresetCanonicalizer::Default
+            },
+        }
+    }
+}
+
+/// Digest of a `Preset`, selected at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetDigest {
+    /// HMAC-SHA256
+    Sha256,
+    /// HMAC-SHA1; requires the `hmac-sha1` feature
+    #[cfg(feature = "hmac-sha1")]
+    Sha1,
+}
+
+impl HmacDigest for PresetDigest {
+    fn hmac(&self, secret: &SecretKey, data: &[u8]) -> Vec<u8> {
+        match *self {
+            PresetDigest::Sha256 => Sha256::default().hmac(secret, data),
+            #[cfg(feature = "hmac-sha1")]
+            PresetDigest::Sha1 => Sha1.hmac(secret, data),
+        }
+    }
+
+    fn try_hmac(&self, secret: &SecretKey, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            PresetDigest::Sha256 => Sha256::default().try_hmac(secret, data),
+            #[cfg(feature = "hmac-sha1")]
+            PresetDigest::Sha1 => Sha1.try_hmac(secret, data),
+        }
+    }
+
+    fn output_len(&self) -> usize {
+        match *self {
+            PresetDigest::Sha256 => Sha256::default().output_len(),
+            #[cfg(feature = "hmac-sha1")]
+            PresetDigest::Sha1 => Sha1.output_len(),
+        }
+    }
+}
+
+/// Canonicalization of a `Preset`, selected at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetCanonicalizer {
+    /// `DefaultCanonicalizer`, for presets verified by their own mode in `Config`
+    Default,
+    /// `BodyCanonicalizer`
+    Body,
+    /// `SlackCanonicalizer`
+    Slack,
+    /// `TwilioCanonicalizer`
+    Twilio,
+}
+
+impl Canonicalizer for PresetCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        match *self {
+            PresetCanonicalizer::Default => DefaultCanonicalizer.request_mac(req, config, mac),
+            PresetCanonicalizer::Body => BodyCanonicalizer.request_mac(req, config, mac),
+            PresetCanonicalizer::Slack => SlackCanonicalizer.request_mac(req, config, mac),
+            PresetCanonicalizer::Twilio => TwilioCanonicalizer.request_mac(req, config, mac),
+        }
+    }
+}
+
+impl PresetAuthentication {
+    /// Build BeforeMiddleware and AfterMiddleware verifying the webhooks of `preset`
+    ///
+    /// This is the single entry point for all providers; the provider specific constructors
+    /// build the same middleware with a concrete digest and canonicalizer. Returns an error if
+    /// the secret is not valid for the provider, e.g. a malformed GitLab signing token.
+    pub fn from_preset<K: Into<SecretKey>>(preset: Preset, secret: K)
+        -> Result<(PresetAuthentication, PresetAuthentication)>
+    {
+        let secret = secret.into();
+        let secret = match preset {
+            Preset::GitLabSigned => {
+                try!(gitlab::decode_signing_token(try!(::std::str::from_utf8(&secret[..]))))
+            },
+            _ => secret
+        };
+
+        Ok(build(preset.digest(), secret, preset, preset.canonicalizer()))
+    }
+}
+
+impl HmacAuthentication<Sha256> {
+    /// Build BeforeMiddleware and AfterMiddleware verifying GitHub webhook deliveries
+    ///
+    /// Deliveries carry `X-Hub-Signature-256: sha256=<hex>`, the HMAC-SHA256 of the raw body with
+    /// the webhook secret.
+    pub fn github_webhook<K: Into<SecretKey>>(secret: K)
+        -> (BodyAuthentication<Sha256>, BodyAuthentication<Sha256>)
+    {
+        build(Sha256::default(), secret, Preset::GitHub, BodyCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Meta (Facebook) Graph API webhooks
+    ///
+    /// Deliveries carry `X-Hub-Signature-256: sha256=<hex>`, the HMAC-SHA256 of the raw body with
+    /// the app secret. See `meta_webhook_sha1` for the legacy `X-Hub-Signature` header.
+    pub fn meta_webhook<K: Into<SecretKey>>(app_secret: K)
+        -> (BodyAuthentication<Sha256>, BodyAuthentication<Sha256>)
+    {
+        build(Sha256::default(), app_secret, Preset::Meta, BodyCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Shopify webhook deliveries
+    ///
+    /// Deliveries carry `X-Shopify-Hmac-Sha256`, the base64 HMAC-SHA256 of the raw body with the
+    /// app's client secret.
+    pub fn shopify_webhook<K: Into<SecretKey>>(secret: K)
+        -> (BodyAuthentication<Sha256>, BodyAuthentication<Sha256>)
+    {
+        build(Sha256::default(), secret, Preset::Shopify, BodyCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Stripe webhook deliveries
+    ///
+    /// Deliveries carry `Stripe-Signature: t=<timestamp>,v1=<hex>`, see `Scheme::Stripe`.
+    /// Timestamps more than five minutes old are rejected, as by Stripe's libraries.
+    pub fn stripe_webhook<K: Into<SecretKey>>(secret: K)
+        -> (HmacAuthentication<Sha256>, HmacAuthentication<Sha256>)
+    {
+        build(Sha256::default(), secret, Preset::Stripe, DefaultCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying GitLab webhooks by secret token
+    ///
+    /// Deliveries carry the token verbatim in `X-Gitlab-Token`, see `Scheme::GitLabToken`. Use
+    /// `gitlab_signed_webhook` for webhooks with a signing token, which also protects the body.
+    pub fn gitlab_webhook<K: Into<SecretKey>>(secret_token: K)
+        -> (HmacAuthentication<Sha256>, HmacAuthentication<Sha256>)
+    {
+        build(Sha256::default(), secret_token, Preset::GitLab, DefaultCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying GitLab webhooks by signing token
+    ///
+    /// Signed deliveries are verified as described by `Scheme::GitLabSigningToken`.
+    /// `signing_token` is the `whsec_` prefixed base64 token shown by GitLab. Returns an error if
+    /// it is not valid base64.
+    pub fn gitlab_signed_webhook(signing_token: &str)
+        -> Result<(HmacAuthentication<Sha256>, HmacAuthentication<Sha256>)>
+    {
+        let secret = try!(gitlab::decode_signing_token(signing_token));
+
+        Ok(build(Sha256::default(), secret, Preset::GitLabSigned, DefaultCanonicalizer))
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Mailgun webhook deliveries
+    ///
+    /// Use the HTTP webhook signing key, see `Scheme::Mailgun`. Timestamps must be within
+    /// five minutes and tokens are remembered to reject replays.
+    pub fn mailgun_webhook<K: Into<SecretKey>>(signing_key: K)
+        -> (HmacAuthentication<Sha256>, HmacAuthentication<Sha256>)
+    {
+        build(Sha256::default(), signing_key, Preset::Mailgun, DefaultCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Slack requests
+    ///
+    /// Requests carry `X-Slack-Signature: v0=<hex>`, see `SlackCanonicalizer`, and
+    /// `X-Slack-Request-Timestamp`, which must be within five minutes. Use the app's signing
+    /// secret.
+    pub fn slack<K: Into<SecretKey>>(secret: K)
+        -> (HmacAuthentication<Sha256, ConstantTimeVerifier, SlackCanonicalizer>,
+            HmacAuthentication<Sha256, ConstantTimeVerifier, SlackCanonicalizer>)
+    {
+        build(Sha256::default(), secret, Preset::Slack, SlackCanonicalizer)
+    }
+}
+
+/// Canonicalization of Slack's v0 request signing
+///
+/// The request MAC is `hmac("v0:" + timestamp + ":" + body)`, where the timestamp is the value of
+/// `Config::timestamp_header`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlackCanonicalizer;
+
+impl Canonicalizer for SlackCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let timestamp = match config.timestamp_header {
+            Some(ref header) => match req.headers.get_raw(&header[..]) {
+                Some(value) => try!(::std::str::from_utf8(&value[0][..])).trim().to_owned(),
+                None => return Err(Error::StaleRequest(format!("missing {} header", header)))
+            },
+            None => String::new()
+        };
+        let body = try!(CanonicalRequest::from_request(req)).body;
+
+        Ok(mac(format!("{}:{}:{}", SLACK_VERSION, timestamp, body).as_bytes()))
+    }
+}
+
+/// Canonicalization of Twilio's request validation
+///
+/// The request MAC is the HMAC of the full request URL followed by each form parameter of the
+/// body, sorted by name, as the name immediately followed by the value. The URL is as seen by
+/// Iron, so services behind a proxy that rewrites it cannot use this.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TwilioCanonicalizer;
+
+impl Canonicalizer for TwilioCanonicalizer {
+    fn request_mac<F>(&self, req: &mut iron::Request, _config: &Config, mac: F) -> Result<Vec<u8>>
+        where F: Fn(&[u8]) -> Vec<u8>
+    {
+        let url: url::Url = req.url.clone().into();
+        let body = try!(CanonicalRequest::from_request(req)).body;
+
+        let mut params: Vec<(String, String)> =
+            url::form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+        params.sort();
+
+        let mut data = url.as_str().to_owned();
+        for (name, value) in params {
+            data.push_str(&name);
+            data.push_str(&value);
+        }
+
+        Ok(mac(data.as_bytes()))
+    }
+}
+
+#[cfg(feature = "hmac-sha1")]
+impl HmacAuthentication<Sha1> {
+    /// Build BeforeMiddleware and AfterMiddleware verifying Twilio webhook requests
+    ///
+    /// Requests carry `X-Twilio-Signature`, the base64 HMAC-SHA1 with the account's auth token,
+    /// see `TwilioCanonicalizer`.
+    pub fn twilio<K: Into<SecretKey>>(auth_token: K)
+        -> (HmacAuthentication<Sha1, ConstantTimeVerifier, TwilioCanonicalizer>,
+            HmacAuthentication<Sha1, ConstantTimeVerifier, TwilioCanonicalizer>)
+    {
+        build(Sha1, auth_token, Preset::Twilio, TwilioCanonicalizer)
+    }
+
+    /// Build BeforeMiddleware and AfterMiddleware verifying Meta webhooks by `X-Hub-Signature`
+    ///
+    /// The legacy header is `sha1=<hex>`, the HMAC-SHA1 of the raw body with the app secret.
+    /// Prefer `Hmac256Authentication::meta_webhook` where possible.
+    pub fn meta_webhook_sha1<K: Into<SecretKey>>(app_secret: K)
+        -> (BodyAuthentication<Sha1>, BodyAuthentication<Sha1>)
+    {
+        build(Sha1, app_secret, Preset::MetaSha1, BodyCanonicalizer)
+    }
+}
+
+/// Build both halves of the middleware of `preset`
+fn build<D, K, C>(digest: D, secret: K, preset: Preset, canonicalizer: C)
+    -> (HmacAuthentication<D, ConstantTimeVerifier, C>,
+        HmacAuthentication<D, ConstantTimeVerifier, C>)
+    where D: HmacDigest,
+          K: Into<SecretKey>,
+          C: Canonicalizer
+{
+    preset_with(digest, secret, preset.hmac_header_key(), preset.config(), canonicalizer)
+}
+
+/// Build both halves of a preset without validating `config`, which is known to be valid
+pub fn preset_with<D, K, C>(digest: D, secret: K, hmac_header_key: &str, config: Config,
+                            canonicalizer: C)
+    -> (HmacAuthentication<D, ConstantTimeVerifier, C>,
+        HmacAuthentication<D, ConstantTimeVerifier, C>)
+    where D: HmacDigest,
+          K: Into<SecretKey>,
+          C: Canonicalizer
+{
+    let auth = HmacAuthentication::from_parts_unchecked(digest, Some(secret.into()),
+                                                        hmac_header_key.to_owned(), config)
+        .with_canonicalizer(canonicalizer);
+
+    (auth.clone(), auth)
+}
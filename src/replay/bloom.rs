@@ -0,0 +1,117 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use error::Result;
+use super::ReplayCache;
+
+/// Approximate replay cache with bounded memory
+///
+/// Keys are recorded in time-bucketed Bloom filters: the current filter takes new keys for one
+/// `window` and is then retired, remaining consulted for one more window before it is discarded.
+/// Keys are therefore remembered for between one and two windows, regardless of the TTL asked
+/// for, so `window` should be at least the TTL (twice `Config::timestamp_max_age`).
+///
+/// Memory use is fixed by the expected number of keys per window and the false positive rate. A
+/// false positive rejects a legitimate request as replayed, so choose a rate the service can
+/// tolerate; more keys than expected raise the rate.
+#[derive(Debug)]
+pub struct BloomReplayCache {
+    window: Duration,
+    bits: usize,
+    hashes: u32,
+    hasher: RandomState,
+    filters: Mutex<Filters>
+}
+
+#[derive(Debug)]
+struct Filters {
+    started: Instant,
+    current: Vec<u64>,
+    previous: Vec<u64>
+}
+
+impl BloomReplayCache {
+    /// Create a cache for about `expected` keys per `window` with the given false positive rate
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not strictly between 0 and 1.
+    pub fn new(expected: usize, false_positive_rate: f64, window: Duration) -> BloomReplayCache {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0,
+                "false positive rate must be between 0 and 1");
+
+        let ln2 = ::std::f64::consts::LN_2;
+        let expected = expected.max(1) as f64;
+        let bits = (-expected * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / expected) * ln2).round().max(1.0) as u32;
+        let words = (bits + 63) / 64;
+
+        BloomReplayCache {
+            window: window,
+            bits: words * 64,
+            hashes: hashes,
+            hasher: RandomState::new(),
+            filters: Mutex::new(Filters {
+                started: Instant::now(),
+                current: vec![0; words],
+                previous: vec![0; words]
+            })
+        }
+    }
+
+    /// Bit positions of `key`, by double hashing
+    fn positions(&self, key: &str) -> Vec<usize> {
+        let hash = |seed: u8| {
+            let mut hasher = self.hasher.build_hasher();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (h1, h2) = (hash(0), hash(1) | 1);
+
+        (0..self.hashes as u64)
+            .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % self.bits as u64) as usize)
+            .collect()
+    }
+}
+
+impl ReplayCache for BloomReplayCache {
+    fn insert_if_absent(&self, key: &str, _ttl: Duration) -> Result<bool> {
+        let positions = self.positions(key);
+        let now = Instant::now();
+        let mut filters = self.filters.lock().unwrap();
+        let filters = &mut *filters;
+
+        let elapsed = now.duration_since(filters.started);
+        if elapsed >= self.window * 2 {
+            clear(&mut filters.current);
+            clear(&mut filters.previous);
+            filters.started = now;
+        } else if elapsed >= self.window {
+            ::std::mem::swap(&mut filters.current, &mut filters.previous);
+            clear(&mut filters.current);
+            filters.started += self.window;
+        }
+
+        let contains = |filter: &[u64]| {
+            positions.iter().all(|&bit| filter[bit / 64] & (1 << (bit % 64)) != 0)
+        };
+        if contains(&filters.current[..]) || contains(&filters.previous[..]) {
+            return Ok(false);
+        }
+
+        for &bit in &positions {
+            filters.current[bit / 64] |= 1 << (bit % 64);
+        }
+
+        Ok(true)
+    }
+}
+
+fn clear(filter: &mut Vec<u64>) {
+    for word in filter.iter_mut() {
+        *word = 0;
+    }
+}
@@ -0,0 +1,242 @@
+//! Replay protection
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use iron;
+use iron::headers::HttpDate;
+
+use config::Config;
+use error::{Error, Result};
+use util;
+
+mod bloom;
+
+#[cfg(feature = "replay-redis")]
+mod redis_cache;
+
+pub use self::bloom::BloomReplayCache;
+
+#[cfg(feature = "replay-redis")]
+pub use self::redis_cache::RedisReplayCache;
+
+/// Maximum timestamp age used when `Config::timestamp_max_age` is not set
+pub const DEFAULT_TIMESTAMP_MAX_AGE: u64 = 300;
+
+/// Check the request's timestamp header, if configured, against the freshness window
+///
+/// The timestamp is in seconds since the Unix epoch. Timestamps too far in the future are
+/// rejected as well to bound clock skew.
+pub fn check_timestamp(req: &iron::Request, config: &Config) -> Result<()> {
+    let header = match config.timestamp_header {
+        Some(ref header) => header,
+        None => return Ok(())
+    };
+
+    let timestamp = match try!(util::single_header(req, &header[..], config.duplicate_headers)) {
+        Some(value) => value,
+        None => return Err(Error::StaleRequest(format!("missing {} header", header)))
+    };
+
+    let seconds: u64 = match timestamp.parse() {
+        Ok(seconds) => seconds,
+        Err(_) => return Err(Error::StaleRequest(format!("invalid timestamp {}", timestamp)))
+    };
+
+    check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), timestamp_max_age(config))
+}
+
+/// Check the request's `Date` header, if required, against the allowed clock skew
+pub fn check_date(req: &iron::Request, config: &Config) -> Result<()> {
+    if !config.require_date {
+        return Ok(());
+    }
+
+    let date = match try!(util::single_header(req, "date", config.duplicate_headers)) {
+        Some(value) => value,
+        None => return Err(Error::StaleRequest("missing Date header".to_owned()))
+    };
+
+    let seconds = try!(parse_http_date(&date));
+    let max_skew = config.date_max_skew
+                         .unwrap_or_else(|| Duration::from_secs(DEFAULT_TIMESTAMP_MAX_AGE));
+
+    check_freshness(UNIX_EPOCH + Duration::from_secs(seconds), max_skew)
+}
+
+/// Parse an HTTP date such as `Sun, 06 Nov 1994 08:49:37 GMT` into seconds since the epoch
+pub fn parse_http_date(date: &str) -> Result<u64> {
+    match date.parse::<HttpDate>() {
+        Ok(HttpDate(tm)) if tm.to_timespec().sec >= 0 => Ok(tm.to_timespec().sec as u64),
+        _ => Err(Error::StaleRequest(format!("invalid date {}", date)))
+    }
+}
+
+/// Check the request's expiry header, if configured, against the current time
+pub fn check_expires(req: &iron::Request, config: &Config) -> Result<()> {
+    let header = match config.expires_header {
+        Some(ref header) => header,
+        None => return Ok(())
+    };
+
+    let expires = match try!(util::single_header(req, &header[..], config.duplicate_headers)) {
+        Some(value) => value,
+        None => return Err(Error::StaleRequest(format!("missing {} header", header)))
+    };
+
+    match expires.parse() {
+        Ok(seconds) => check_expiry(seconds),
+        Err(_) => Err(Error::StaleRequest(format!("invalid expiry {}", expires)))
+    }
+}
+
+/// Check that `expires`, in seconds since the Unix epoch, has not passed
+pub fn check_expiry(expires: u64) -> Result<()> {
+    if SystemTime::now() > UNIX_EPOCH + Duration::from_secs(expires) {
+        return Err(Error::StaleRequest(format!("request expired at {}", expires)));
+    }
+
+    Ok(())
+}
+
+/// The configured or default freshness window for timestamps
+pub fn timestamp_max_age(config: &Config) -> Duration {
+    config.timestamp_max_age.unwrap_or_else(|| Duration::from_secs(DEFAULT_TIMESTAMP_MAX_AGE))
+}
+
+/// Check the request's nonce header, if configured, and remember the nonce
+///
+/// Nonces are remembered for twice the timestamp freshness window, which covers the clock skew
+/// accepted in either direction. They are remembered per `key_id`, the key the request was
+/// verified with, so clients of different keys cannot use up each other's nonces.
+pub fn check_nonce(req: &iron::Request, config: &Config, key_id: Option<&str>,
+                   nonces: &dyn ReplayCache)
+    -> Result<()>
+{
+    let header = match config.nonce_header {
+        Some(ref header) => header,
+        None => return Ok(())
+    };
+
+    let nonce = match try!(util::single_header(req, &header[..], config.duplicate_headers)) {
+        Some(value) => value,
+        None => return Err(Error::ReplayedRequest(format!("missing {} header", header)))
+    };
+
+    // The length of the key id keeps ids containing `:` apart
+    let key_id = key_id.unwrap_or("");
+    let key = format!("{}:{}:{}", key_id.len(), key_id, nonce);
+    if nonce.is_empty() || !try!(nonces.insert_if_absent(&key, timestamp_max_age(config) * 2)) {
+        return Err(Error::ReplayedRequest(format!("nonce {} was already used", nonce)));
+    }
+
+    Ok(())
+}
+
+/// Store of recently seen nonces
+///
+/// Implement this to share replay state between instances, e.g. in a distributed cache.
+pub trait ReplayCache: Send + Sync + 'static {
+    /// Remember `key` for `ttl` unless it is already remembered
+    ///
+    /// Returns whether the key was new. This must be atomic: of two concurrent calls with the same
+    /// key, only one may return true.
+    fn insert_if_absent(&self, key: &str, ttl: Duration) -> Result<bool>;
+}
+
+impl fmt::Debug for dyn ReplayCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReplayCache")
+    }
+}
+
+/// Number of keys `MemoryReplayCache::default` remembers
+const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Bounded in-process replay cache
+///
+/// Once `capacity` unexpired keys are remembered, the oldest is forgotten to make room, which
+/// would let it be replayed. Size the capacity for the peak request rate over the TTL. Replay state
+/// is not shared between processes.
+#[derive(Debug)]
+pub struct MemoryReplayCache {
+    capacity: usize,
+    entries: Mutex<Entries>
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    expiry: HashMap<String, Instant>,
+    order: VecDeque<String>
+}
+
+impl MemoryReplayCache {
+    /// Create a cache remembering at most `capacity` keys
+    pub fn new(capacity: usize) -> MemoryReplayCache {
+        MemoryReplayCache {
+            capacity: capacity,
+            entries: Mutex::new(Entries::default())
+        }
+    }
+}
+
+impl Default for MemoryReplayCache {
+    fn default() -> MemoryReplayCache {
+        MemoryReplayCache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ReplayCache for MemoryReplayCache {
+    fn insert_if_absent(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let entries = &mut *entries;
+
+        // Forget expired keys from the front; keys are ordered by insertion
+        while let Some(oldest) = entries.order.pop_front() {
+            if entries.expiry[&oldest] > now {
+                entries.order.push_front(oldest);
+                break;
+            }
+            entries.expiry.remove(&oldest);
+        }
+
+        if let Some(expires) = entries.expiry.remove(key) {
+            if expires > now {
+                entries.expiry.insert(key.to_owned(), expires);
+                return Ok(false);
+            }
+            entries.order.retain(|seen| seen != key);
+        }
+
+        while entries.order.len() >= self.capacity.max(1) {
+            match entries.order.pop_front() {
+                Some(oldest) => { entries.expiry.remove(&oldest); },
+                None => break
+            }
+        }
+
+        entries.expiry.insert(key.to_owned(), now + ttl);
+        entries.order.push_back(key.to_owned());
+
+        Ok(true)
+    }
+}
+
+/// Reject `time` if it is more than `max_age` away from now in either direction
+pub fn check_freshness(time: SystemTime, max_age: Duration) -> Result<()> {
+    let now = SystemTime::now();
+    let skew = match now.duration_since(time) {
+        Ok(age) => age,
+        Err(err) => err.duration()
+    };
+
+    if skew > max_age {
+        return Err(Error::StaleRequest(format!("request is {}s outside the freshness window",
+                                               skew.as_secs() - max_age.as_secs())));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use redis::{self, Client, Connection};
+
+use error::{Error, Result};
+use super::ReplayCache;
+
+/// Most idle connections kept open for reuse
+const MAX_IDLE_CONNECTIONS: usize = 16;
+
+/// Replay cache shared through Redis
+///
+/// Keys are stored with `SET key 1 NX PX ttl`, the atomic form of `SETNX` followed by `EXPIRE`,
+/// so every instance pointed at the same Redis sees the same replay state. Each request takes a
+/// connection of its own, so a slow round trip does not hold up others; idle connections are kept
+/// open for reuse and dropped after errors. Requires the `replay-redis` feature.
+pub struct RedisReplayCache {
+    client: Client,
+    idle: Mutex<Vec<Connection>>,
+    prefix: String
+}
+
+impl RedisReplayCache {
+    /// Create a cache for the Redis server at `url`, e.g. `redis://127.0.0.1/`
+    ///
+    /// Keys are prefixed with `iron-hmac:nonce:`. No connection is made until first use.
+    pub fn new(url: &str) -> Result<RedisReplayCache> {
+        let client = try!(Client::open(url).map_err(cache_error));
+
+        Ok(RedisReplayCache {
+            client: client,
+            idle: Mutex::new(Vec::new()),
+            prefix: "iron-hmac:nonce:".to_owned()
+        })
+    }
+
+    /// Prefix keys with `prefix` instead, e.g. to separate services sharing one Redis
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> RedisReplayCache {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+impl ReplayCache for RedisReplayCache {
+    fn insert_if_absent(&self, key: &str, ttl: Duration) -> Result<bool> {
+        // The lock is only held to take an idle connection, never during a round trip
+        let idle = self.idle.lock().unwrap().pop();
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => try!(self.client.get_connection().map_err(cache_error))
+        };
+
+        let millis = ttl.as_secs() * 1000 + u64::from(ttl.subsec_nanos() / 1_000_000);
+        let reply = redis::cmd("SET").arg(format!("{}{}", self.prefix, key))
+                                     .arg(1)
+                                     .arg("NX")
+                                     .arg("PX")
+                                     .arg(millis.max(1))
+                                     .query::<Option<String>>(&mut connection);
+
+        match reply {
+            Ok(reply) => {
+                let mut idle = self.idle.lock().unwrap();
+                if idle.len() < MAX_IDLE_CONNECTIONS {
+                    idle.push(connection);
+                }
+                Ok(reply.is_some())
+            },
+            // The connection is dropped, so a later call connects anew
+            Err(err) => Err(cache_error(err))
+        }
+    }
+}
+
+fn cache_error(err: redis::RedisError) -> Error {
+    Error::ReplayCacheUnavailable(err.to_string())
+}
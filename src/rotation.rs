@@ -0,0 +1,91 @@
+//! Secrets rotated while the server runs
+
+use std::sync::RwLock;
+
+use util;
+use ::SecretKey;
+
+/// The current secrets of a `SecretRotation`
+#[derive(Debug)]
+struct Secrets {
+    primary: SecretKey,
+    secondaries: Vec<SecretKey>,
+}
+
+/// Control handle rotating the middleware's secret without rebuilding the `Chain`
+///
+/// Requests are verified with the primary secret and, failing that, each secondary secret, and
+/// responses are signed with the primary secret, as with `Config::previous_secrets`. Keep the
+/// handle returned by `HmacAuthentication::with_secret_rotation`, e.g. in an admin endpoint or a
+/// SIGHUP handler, to roll out a new secret:
+///
+/// ```
+/// use iron_hmac::{Config, Hmac256Authentication, Sha256};
+///
+/// let old_secret = "9f2c4e1a7b3d8f6e0c5a2b9d4e7f1a3c";
+/// let (hmac_before, hmac_after, rotation) =
+///     Hmac256Authentication::with_secret_rotation(Sha256::default(), old_secret, "x-hmac",
+///                                                 Config::default()).unwrap();
+///
+/// // Sign with the new secret, still accepting the old one until every client has moved
+/// rotation.set_primary_secret("4b8e2d6f0a3c7e1b5d9f2a6c0e4b8d3f");
+/// rotation.add_secondary_secret(old_secret);
+///
+/// // Later
+/// rotation.revoke(old_secret);
+/// ```
+#[derive(Debug)]
+pub struct SecretRotation {
+    secrets: RwLock<Secrets>,
+}
+
+impl SecretRotation {
+    pub fn new<K: Into<SecretKey>>(primary: K) -> SecretRotation {
+        SecretRotation {
+            secrets: RwLock::new(Secrets {
+                primary: primary.into(),
+                secondaries: Vec::new()
+            })
+        }
+    }
+
+    /// Verify and sign with `secret` from now on
+    ///
+    /// The previous primary secret is no longer accepted unless it is added as a secondary secret.
+    pub fn set_primary_secret<K: Into<SecretKey>>(&self, secret: K) {
+        self.secrets.write().unwrap().primary = secret.into();
+    }
+
+    /// Also accept requests signed with `secret`
+    pub fn add_secondary_secret<K: Into<SecretKey>>(&self, secret: K) {
+        self.secrets.write().unwrap().secondaries.push(secret.into());
+    }
+
+    /// Stop accepting requests signed with the secondary secret `secret`, returning whether it was
+    /// accepted
+    ///
+    /// The primary secret cannot be revoked; replace it with `set_primary_secret` instead.
+    pub fn revoke<K: Into<SecretKey>>(&self, secret: K) -> bool {
+        let secret = secret.into();
+        let mut secrets = self.secrets.write().unwrap();
+        let count = secrets.secondaries.len();
+        secrets.secondaries.retain(|secondary| !util::contant_time_equals(secondary, &secret));
+
+        secrets.secondaries.len() != count
+    }
+
+    /// The secret requests are signed with
+    pub(crate) fn primary(&self) -> SecretKey {
+        self.secrets.read().unwrap().primary.clone()
+    }
+
+    /// The secondary secrets if `secret` is the primary secret
+    pub(crate) fn secondaries(&self, secret: &SecretKey) -> Vec<SecretKey> {
+        let secrets = self.secrets.read().unwrap();
+        if util::contant_time_equals(&secrets.primary, secret) {
+            secrets.secondaries.clone()
+        } else {
+            Vec::new()
+        }
+    }
+}
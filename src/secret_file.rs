@@ -0,0 +1,141 @@
+//! Secrets loaded from a file and reloaded when it changes
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use iron;
+
+use error::{Error, Result};
+use secret_store::SecretProvider;
+use ::SecretKey;
+
+/// How often `HmacAuthentication::with_secret_file` checks the file for changes
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The parsed contents of a secret file
+#[derive(Debug, Default)]
+struct Secrets {
+    default: Option<SecretKey>,
+    keys: HashMap<String, SecretKey>,
+    modified: Option<SystemTime>,
+}
+
+/// Secrets read from a file, swapped atomically whenever the file changes
+///
+/// Each line of the file is a hex encoded secret, used for requests that do not name a key, or a
+/// key id and a hex encoded secret separated by whitespace. Blank lines and lines starting with
+/// `#` are ignored.
+///
+/// ```text
+/// # used when the request names no key
+/// 3f2a0c9d5e7b...
+/// partner 9c1d4be0a672...
+/// ```
+///
+/// Set the provider as `Config::secret_provider`, or build the middleware with
+/// `HmacAuthentication::with_secret_file`. A file that fails to parse while being watched leaves
+/// the previous secrets in place.
+#[derive(Debug, Clone)]
+pub struct FileSecretProvider {
+    path: PathBuf,
+    secrets: Arc<RwLock<Secrets>>,
+}
+
+impl FileSecretProvider {
+    /// Load the secrets of the file at `path` once
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FileSecretProvider> {
+        let provider = FileSecretProvider {
+            path: path.as_ref().to_owned(),
+            secrets: Arc::new(RwLock::new(Secrets::default()))
+        };
+        try!(provider.reload());
+
+        Ok(provider)
+    }
+
+    /// Load the secrets of the file at `path` and reload them whenever the file changes
+    ///
+    /// The file's modification time is checked every `interval` on a background thread, which
+    /// exits once every clone of the provider is dropped.
+    pub fn watch<P: AsRef<Path>>(path: P, interval: Duration) -> Result<FileSecretProvider> {
+        let provider = try!(FileSecretProvider::load(path));
+
+        let path = provider.path.clone();
+        let secrets = Arc::downgrade(&provider.secrets);
+        thread::spawn(move || poll(path, secrets, interval));
+
+        Ok(provider)
+    }
+
+    /// Read the file again, replacing the secrets if it parses
+    pub fn reload(&self) -> Result<()> {
+        let secrets = try!(read_secrets(&self.path));
+        *self.secrets.write().unwrap() = secrets;
+
+        Ok(())
+    }
+}
+
+impl SecretProvider for FileSecretProvider {
+    fn secret_for(&self, _req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        let secrets = self.secrets.read().unwrap();
+        match key_id {
+            Some(id) => secrets.keys.get(id).cloned(),
+            None => secrets.default.clone()
+        }
+    }
+}
+
+/// Reload the secrets whenever the file's modification time changes, until they are dropped
+fn poll(path: PathBuf, secrets: Weak<RwLock<Secrets>>, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+
+        let secrets = match secrets.upgrade() {
+            Some(secrets) => secrets,
+            None => return
+        };
+
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == secrets.read().unwrap().modified {
+            continue;
+        }
+
+        if let Ok(reloaded) = read_secrets(&path) {
+            *secrets.write().unwrap() = reloaded;
+        }
+    }
+}
+
+/// Read and parse the secret file at `path`
+fn read_secrets(path: &Path) -> Result<Secrets> {
+    let mut contents = String::new();
+    let mut file = try!(File::open(path));
+    let modified = try!(file.metadata()).modified().ok();
+    try!(file.read_to_string(&mut contents));
+
+    let mut secrets = Secrets { modified: modified, ..Secrets::default() };
+    for (number, line) in contents.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(secret), None, None) => {
+                secrets.default = Some(try!(SecretKey::from_hex(secret)));
+            },
+            (Some(id), Some(secret), None) => {
+                secrets.keys.insert(id.to_owned(), try!(SecretKey::from_hex(secret)));
+            },
+            _ => return Err(Error::InvalidKey(format!("invalid secret file line {}", number + 1)))
+        }
+    }
+
+    Ok(secrets)
+}
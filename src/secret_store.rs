@@ -0,0 +1,256 @@
+//! Keys looked up by id while serving requests
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use iron;
+
+use config::Key;
+use ::SecretKey;
+
+/// Source of the keys clients select by key id
+///
+/// Implement this for keys held outside the process, e.g. in a database.
+pub trait SecretStore: Send + Sync + 'static {
+    /// The key with id `id`, or `None` if there is no such key or it was revoked
+    fn key(&self, id: &str) -> Option<Key>;
+}
+
+impl fmt::Debug for dyn SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretStore")
+    }
+}
+
+impl SecretStore for HashMap<String, Key> {
+    fn key(&self, id: &str) -> Option<Key> {
+        self.get(id).cloned()
+    }
+}
+
+/// Supplies the secret of each request in place of the middleware's own secret
+///
+/// Implement this for secrets that depend on the request, e.g. per-tenant secrets chosen by host,
+/// or that are looked up in a database so they can be changed without restarting the server.
+pub trait SecretProvider: Send + Sync + 'static {
+    /// The secret for `req`, signed with the key `key_id` if the request named one
+    ///
+    /// Returning `None` rejects the request.
+    fn secret_for(&self, req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey>;
+}
+
+impl fmt::Debug for dyn SecretProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretProvider")
+    }
+}
+
+/// Secrets chosen by path prefix, so one middleware can guard routes with distinct secrets
+///
+/// The route with the longest prefix matching the request path whole segments at a time is used,
+/// so `/api/v1` matches `/api/v1/users` but not `/api/v10`. Requests matching no route use the
+/// default secret if one is set, and are rejected otherwise, as are requests naming a key id that
+/// is not in `Config::keys` or `Config::secret_store`.
+///
+/// ```
+/// use std::sync::Arc;
+/// use iron_hmac::{Config, RouteSecretProvider};
+///
+/// let routes = RouteSecretProvider::new()
+///     .route("/webhooks/github", "github secret")
+///     .route("/api/v1", "api secret");
+/// let config = Config { secret_provider: Some(Arc::new(routes)), ..Config::default() };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RouteSecretProvider {
+    routes: Vec<(Vec<String>, SecretKey)>,
+    default: Option<SecretKey>,
+}
+
+impl RouteSecretProvider {
+    pub fn new() -> RouteSecretProvider {
+        RouteSecretProvider::default()
+    }
+
+    /// Use `secret` for requests whose path starts with `prefix`
+    pub fn route<K: Into<SecretKey>>(mut self, prefix: &str, secret: K) -> RouteSecretProvider {
+        let segments = prefix.split('/').filter(|segment| !segment.is_empty());
+        self.routes.push((segments.map(str::to_owned).collect(), secret.into()));
+        self
+    }
+
+    /// Use `secret` for requests matching no route
+    pub fn default_secret<K: Into<SecretKey>>(mut self, secret: K) -> RouteSecretProvider {
+        self.default = Some(secret.into());
+        self
+    }
+}
+
+impl SecretProvider for RouteSecretProvider {
+    fn secret_for(&self, req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        if key_id.is_some() {
+            return None;
+        }
+
+        let path: Vec<&str> = req.url.path().into_iter().filter(|segment| !segment.is_empty())
+                                 .collect();
+        self.routes.iter()
+                   .filter(|&&(ref prefix, _)| {
+                       prefix.len() <= path.len() &&
+                       prefix.iter().zip(&path).all(|(expected, segment)| expected == segment)
+                   })
+                   .max_by_key(|&&(ref prefix, _)| prefix.len())
+                   .map(|&(_, ref secret)| secret)
+                   .or(self.default.as_ref())
+                   .cloned()
+    }
+}
+
+/// In-process keys that can be added and revoked while the server runs
+///
+/// Share one store between both halves of the middleware, and keep a handle to revoke a single
+/// client without rotating the keys of the others.
+#[derive(Debug, Default)]
+pub struct MemorySecretStore {
+    keys: RwLock<HashMap<String, Key>>
+}
+
+impl MemorySecretStore {
+    pub fn new() -> MemorySecretStore {
+        MemorySecretStore::default()
+    }
+
+    /// Add the key `id`, replacing any key with the same id
+    pub fn insert<S: Into<String>>(&self, id: S, key: Key) {
+        self.keys.write().unwrap().insert(id.into(), key);
+    }
+
+    /// Revoke the key `id`, returning whether it existed
+    pub fn revoke(&self, id: &str) -> bool {
+        self.keys.write().unwrap().remove(id).is_some()
+    }
+}
+
+impl SecretStore for MemorySecretStore {
+    fn key(&self, id: &str) -> Option<Key> {
+        self.keys.read().unwrap().get(id).cloned()
+    }
+}
+
+/// The cache key of a request, by default the key id it named
+type CacheKey = Box<dyn Fn(&iron::Request, Option<&str>) -> Option<String> + Send + Sync>;
+
+/// A cached answer of the wrapped provider
+struct CacheEntry {
+    secret: Option<SecretKey>,
+    expires: Instant,
+}
+
+/// Caches the secrets of another `SecretProvider`, so that it is not asked on every request
+///
+/// Secrets are cached by the key id the request named for `ttl`, one minute by default, and the
+/// absence of a secret for `negative_ttl`, ten seconds by default, so that requests naming an
+/// unknown key do not reach the backing store either. Once `max_entries` secrets are cached, the
+/// one expiring soonest is dropped to make room. If the secret depends on more than the key id,
+/// e.g. the host of the request, set `cache_key` accordingly.
+pub struct CachingSecretProvider<P> {
+    provider: P,
+    ttl: Duration,
+    negative_ttl: Duration,
+    max_entries: usize,
+    cache_key: CacheKey,
+    entries: Mutex<HashMap<Option<String>, CacheEntry>>,
+}
+
+impl<P: fmt::Debug> fmt::Debug for CachingSecretProvider<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CachingSecretProvider")
+         .field("provider", &self.provider)
+         .field("ttl", &self.ttl)
+         .field("negative_ttl", &self.negative_ttl)
+         .field("max_entries", &self.max_entries)
+         .finish()
+    }
+}
+
+impl<P: SecretProvider> CachingSecretProvider<P> {
+    pub fn new(provider: P) -> CachingSecretProvider<P> {
+        CachingSecretProvider {
+            provider: provider,
+            ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(10),
+            max_entries: 1024,
+            cache_key: Box::new(|_, key_id| key_id.map(str::to_owned)),
+            entries: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// How long a secret is cached
+    pub fn ttl(mut self, ttl: Duration) -> CachingSecretProvider<P> {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long the absence of a secret is cached; zero disables negative caching
+    pub fn negative_ttl(mut self, ttl: Duration) -> CachingSecretProvider<P> {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// How many answers are cached at most
+    pub fn max_entries(mut self, max_entries: usize) -> CachingSecretProvider<P> {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Cache by the value of `cache_key` for the request and key id, so that requests mapped to
+    /// the same value share a secret
+    pub fn cache_key<F>(mut self, cache_key: F) -> CachingSecretProvider<P>
+        where F: Fn(&iron::Request, Option<&str>) -> Option<String> + Send + Sync + 'static
+    {
+        self.cache_key = Box::new(cache_key);
+        self
+    }
+
+    /// Drop every cached answer, e.g. after a secret was rotated
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl<P: SecretProvider> SecretProvider for CachingSecretProvider<P> {
+    fn secret_for(&self, req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        let cache_key = (self.cache_key)(req, key_id);
+        let now = Instant::now();
+        if let Some(entry) = self.entries.lock().unwrap().get(&cache_key) {
+            if now < entry.expires {
+                return entry.secret.clone();
+            }
+        }
+
+        // The lock is not held while the wrapped provider looks the secret up
+        let secret = self.provider.secret_for(req, key_id);
+        let ttl = if secret.is_some() { self.ttl } else { self.negative_ttl };
+        if ttl == Duration::from_secs(0) || self.max_entries == 0 {
+            return secret;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&cache_key) {
+            entries.retain(|_, entry| now < entry.expires);
+        }
+        if entries.len() >= self.max_entries && !entries.contains_key(&cache_key) {
+            let soonest = entries.iter()
+                                 .min_by_key(|&(_, entry)| entry.expires)
+                                 .map(|(key, _)| key.clone());
+            if let Some(soonest) = soonest {
+                entries.remove(&soonest);
+            }
+        }
+        entries.insert(cache_key, CacheEntry { secret: secret.clone(), expires: now + ttl });
+
+        secret
+    }
+}
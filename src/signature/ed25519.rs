@@ -0,0 +1,56 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use error::{Error, Result};
+use super::SignatureScheme;
+
+/// Ed25519 public key for verifying requests and optional private key for signing responses
+pub struct Ed25519 {
+    verifying_key: VerifyingKey,
+    signing_key: Option<SigningKey>
+}
+
+impl Ed25519 {
+    /// Create from the 32 byte public key of the client
+    pub fn new(public_key: &[u8]) -> Result<Ed25519> {
+        let mut bytes = [0u8; 32];
+        if public_key.len() != bytes.len() {
+            return Err(Error::InvalidKey("Ed25519 public keys are 32 bytes".to_owned()));
+        }
+        bytes.copy_from_slice(public_key);
+
+        let verifying_key = match VerifyingKey::from_bytes(&bytes) {
+            Ok(key) => key,
+            Err(_) => return Err(Error::InvalidKey("invalid Ed25519 public key".to_owned()))
+        };
+
+        Ok(Ed25519 {
+            verifying_key: verifying_key,
+            signing_key: None
+        })
+    }
+
+    /// Also sign responses with the given 32 byte private key (seed)
+    pub fn with_signing_key(mut self, private_key: &[u8]) -> Result<Ed25519> {
+        let mut bytes = [0u8; 32];
+        if private_key.len() != bytes.len() {
+            return Err(Error::InvalidKey("Ed25519 private keys are 32 bytes".to_owned()));
+        }
+        bytes.copy_from_slice(private_key);
+
+        self.signing_key = Some(SigningKey::from_bytes(&bytes));
+        Ok(self)
+    }
+}
+
+impl SignatureScheme for Ed25519 {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match Signature::from_slice(signature) {
+            Ok(signature) => self.verifying_key.verify_strict(message, &signature).is_ok(),
+            Err(_) => false
+        }
+    }
+
+    fn sign(&self, message: &[u8]) -> Option<Vec<u8>> {
+        self.signing_key.as_ref().map(|key| key.sign(message).to_bytes().to_vec())
+    }
+}
@@ -0,0 +1,200 @@
+//! Asymmetric signature authentication
+//!
+//! With `Config::public_key` set, `HmacAuthentication` verifies a detached signature over the
+//! canonical request instead of an HMAC. The signed message is built by the middleware's
+//! `Canonicalizer` from its `Config`, with each input that would be HMACed prefixed by its length
+//! as a 64 bit big-endian integer instead. With `DefaultCanonicalizer`, the message is thus
+//! `len(len(method) + len(path) + len(body))`, extended by the query, timestamp, nonce, and signed
+//! headers as configured, and the same replay checks apply. Responses are signed over the raw body
+//! when the scheme holds a private key.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use iron;
+use iron::prelude::*;
+use iron::{BeforeMiddleware, AfterMiddleware};
+
+use canonical::Canonicalizer;
+use config::{Config, DuplicateHeaders};
+use error::Result;
+use hmac::HmacDigest;
+use util;
+use verifier::Verifier;
+use ::{HmacAuthentication, Sha256};
+
+#[cfg(feature = "ed25519")]
+mod ed25519;
+
+#[cfg(feature = "ed25519")]
+pub use self::ed25519::Ed25519;
+
+#[cfg(feature = "ecdsa-p256")]
+mod p256;
+
+#[cfg(feature = "ecdsa-p256")]
+pub use self::p256::EcdsaP256;
+
+#[cfg(feature = "rsa-sha256")]
+mod rsa;
+
+#[cfg(feature = "rsa-sha256")]
+pub use self::rsa::RsaSha256;
+
+/// An asymmetric signature algorithm together with its keys
+pub trait SignatureScheme: Send + Sync + 'static {
+    /// Check `signature` over `message` against the public key
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+
+    /// Sign `message`, or return None if no private key is configured
+    fn sign(&self, message: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl fmt::Debug for dyn SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SignatureScheme")
+    }
+}
+
+/// Iron middleware for verifying request signatures and signing responses.
+///
+/// This is `HmacAuthentication` with `Config::public_key` set to a scheme of type `S`.
+/// Signatures are hex encoded in the configured header unless `Config::encoding` says otherwise.
+pub struct SignatureAuthentication<S: SignatureScheme> {
+    inner: HmacAuthentication<Sha256>,
+    scheme: PhantomData<S>
+}
+
+/// SignatureAuthentication using Ed25519
+#[cfg(feature = "ed25519")]
+pub type Ed25519Authentication = SignatureAuthentication<Ed25519>;
+
+/// SignatureAuthentication using ECDSA P-256 with SHA-256
+#[cfg(feature = "ecdsa-p256")]
+pub type EcdsaP256Authentication = SignatureAuthentication<EcdsaP256>;
+
+impl<S: SignatureScheme> Clone for SignatureAuthentication<S> {
+    fn clone(&self) -> SignatureAuthentication<S> {
+        SignatureAuthentication {
+            inner: self.inner.clone(),
+            scheme: PhantomData
+        }
+    }
+}
+
+impl<S: SignatureScheme> SignatureAuthentication<S> {
+    /// Build SignatureAuthentication BeforeMiddleware and AfterMiddleware
+    ///
+    /// The `signature_header_key` is used to lookup the request's signature and to label the
+    /// response's signature.
+    pub fn middleware<H: Into<String>>(scheme: S, signature_header_key: H)
+        -> (SignatureAuthentication<S>, SignatureAuthentication<S>) {
+
+        let config = Config { public_key: Some(Arc::new(scheme)), ..Config::default() };
+        let auth = SignatureAuthentication {
+            inner: HmacAuthentication::from_parts_unchecked(Sha256::default(), None,
+                                                            signature_header_key.into(), config),
+            scheme: PhantomData
+        };
+
+        (auth.clone(), auth)
+    }
+
+    /// Build SignatureAuthentication BeforeMiddleware and AfterMiddleware with non-default
+    /// behavior, such as replay protection
+    ///
+    /// Returns an error if `config` is not valid.
+    pub fn with_config<H: Into<String>>(scheme: S, signature_header_key: H, config: Config)
+        -> Result<(SignatureAuthentication<S>, SignatureAuthentication<S>)>
+    {
+        let (auth, _) = try!(HmacAuthentication::with_public_key(scheme, signature_header_key,
+                                                                 config));
+        let auth = SignatureAuthentication { inner: auth, scheme: PhantomData };
+
+        Ok((auth.clone(), auth))
+    }
+}
+
+impl HmacAuthentication<Sha256> {
+    /// Build HmacAuthentication BeforeMiddleware and AfterMiddleware verifying signatures made
+    /// with the private key of `public_key`
+    ///
+    /// Sets `Config::public_key`. Returns an error if `config` is not valid.
+    pub fn with_public_key<P, H>(public_key: P, hmac_header_key: H, config: Config)
+        -> Result<(HmacAuthentication<Sha256>, HmacAuthentication<Sha256>)>
+        where P: SignatureScheme,
+              H: Into<String>
+    {
+        let config = Config { public_key: Some(Arc::new(public_key)), ..config };
+        let auth = try!(HmacAuthentication::from_parts(Sha256::default(), None,
+                                                       hmac_header_key.into(), config));
+
+        Ok((auth.clone(), auth))
+    }
+}
+
+impl<S: SignatureScheme> BeforeMiddleware for SignatureAuthentication<S> {
+    fn before(&self, req: &mut iron::Request) -> IronResult<()> {
+        self.inner.before(req)
+    }
+}
+
+impl<S: SignatureScheme> AfterMiddleware for SignatureAuthentication<S> {
+    fn after(&self, req: &mut iron::Request, res: iron::Response) -> IronResult<Response> {
+        self.inner.after(req, res)
+    }
+}
+
+/// Verify the signatures of `req` against `public_key`
+///
+/// Duplicate signature headers are handled as configured by `Config::duplicate_headers`.
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, public_key: &dyn SignatureScheme,
+                       req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let supplied = try!(auth.supplied_macs(req));
+    let message = try!(message(&auth.inner.canonicalizer, req, &auth.inner.config));
+
+    let require_all = auth.inner.config.duplicate_headers == DuplicateHeaders::RequireAll;
+    let mut verified = supplied.iter().map(|signature| public_key.verify(&message[..], signature));
+    let verified = if require_all {
+        verified.all(|matches| matches)
+    } else {
+        verified.any(|matches| matches)
+    };
+
+    if verified {
+        Ok(())
+    } else {
+        forbidden!()
+    }
+}
+
+/// Sign the body of `res` with `public_key`, if it holds a private key
+pub fn sign_response(public_key: &dyn SignatureScheme, res: &mut iron::Response)
+    -> Result<Option<Vec<u8>>>
+{
+    util::with_response_body(res, |body| public_key.sign(body))
+}
+
+/// The message signed for `req`
+fn message<C: Canonicalizer>(canonicalizer: &C, req: &mut iron::Request, config: &Config)
+    -> Result<Vec<u8>>
+{
+    canonicalizer.request_mac(req, config, length_prefixed)
+}
+
+/// `data` prefixed by its length as a 64 bit big-endian integer
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    let len = data.len() as u64;
+    let mut prefixed = Vec::with_capacity(data.len() + 8);
+    for shift in (0..8).rev() {
+        prefixed.push((len >> (shift * 8)) as u8);
+    }
+    prefixed.extend_from_slice(data);
+    prefixed
+}
@@ -0,0 +1,61 @@
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::ecdsa::signature::{Signer, Verifier};
+
+use error::{Error, Result};
+use super::SignatureScheme;
+
+/// ECDSA P-256 with SHA-256 public key for verifying requests and optional private key for
+/// signing responses
+///
+/// Request signatures may be fixed size (`r || s`, 64 bytes) or ASN.1 DER encoded. Response
+/// signatures are fixed size.
+pub struct EcdsaP256 {
+    verifying_key: VerifyingKey,
+    signing_key: Option<SigningKey>
+}
+
+impl EcdsaP256 {
+    /// Create from the client's SEC1 encoded (compressed or uncompressed) public key
+    pub fn new(public_key: &[u8]) -> Result<EcdsaP256> {
+        let verifying_key = match VerifyingKey::from_sec1_bytes(public_key) {
+            Ok(key) => key,
+            Err(_) => return Err(Error::InvalidKey("invalid P-256 public key".to_owned()))
+        };
+
+        Ok(EcdsaP256 {
+            verifying_key: verifying_key,
+            signing_key: None
+        })
+    }
+
+    /// Also sign responses with the given 32 byte private scalar
+    pub fn with_signing_key(mut self, private_key: &[u8]) -> Result<EcdsaP256> {
+        match SigningKey::from_slice(private_key) {
+            Ok(key) => self.signing_key = Some(key),
+            Err(_) => return Err(Error::InvalidKey("invalid P-256 private key".to_owned()))
+        }
+
+        Ok(self)
+    }
+}
+
+impl SignatureScheme for EcdsaP256 {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        let signature = match Signature::from_slice(signature) {
+            Ok(signature) => signature,
+            Err(_) => match Signature::from_der(signature) {
+                Ok(signature) => signature,
+                Err(_) => return false
+            }
+        };
+
+        self.verifying_key.verify(message, &signature).is_ok()
+    }
+
+    fn sign(&self, message: &[u8]) -> Option<Vec<u8>> {
+        self.signing_key.as_ref().map(|key| {
+            let signature: Signature = key.sign(message);
+            signature.to_bytes().to_vec()
+        })
+    }
+}
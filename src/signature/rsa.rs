@@ -0,0 +1,52 @@
+use std::convert::TryFrom;
+
+use rsa::RsaPublicKey;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::sha2::Sha256;
+use rsa::signature::Verifier;
+
+use error::{Error, Result};
+use super::SignatureScheme;
+
+/// RSASSA-PKCS1-v1_5 with SHA-256 public key for verifying requests
+///
+/// Set it as `Config::public_key`, e.g. through `HmacAuthentication::with_public_key`. This scheme
+/// only verifies; responses are passed through unsigned.
+pub struct RsaSha256 {
+    verifying_key: VerifyingKey<Sha256>
+}
+
+impl RsaSha256 {
+    /// Create from the sender's PEM encoded public key
+    ///
+    /// Both SubjectPublicKeyInfo (`BEGIN PUBLIC KEY`) and PKCS#1 (`BEGIN RSA PUBLIC KEY`) encodings
+    /// are accepted.
+    pub fn from_pem(pem: &str) -> Result<RsaSha256> {
+        let public_key = match RsaPublicKey::from_public_key_pem(pem) {
+            Ok(key) => key,
+            Err(_) => match RsaPublicKey::from_pkcs1_pem(pem) {
+                Ok(key) => key,
+                Err(_) => return Err(Error::InvalidKey("invalid RSA public key".to_owned()))
+            }
+        };
+
+        Ok(RsaSha256 {
+            verifying_key: VerifyingKey::new(public_key)
+        })
+    }
+}
+
+impl SignatureScheme for RsaSha256 {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match Signature::try_from(signature) {
+            Ok(signature) => self.verifying_key.verify(message, &signature).is_ok(),
+            Err(_) => false
+        }
+    }
+
+    fn sign(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
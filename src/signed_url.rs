@@ -0,0 +1,118 @@
+//! Short-lived URLs carrying their own HMAC
+//!
+//! A signed URL has an expiry parameter and an HMAC parameter appended to its query, e.g.
+//! `/report.pdf?user=7&exp=1700000000&sig=5f1c...`. The HMAC covers the path and the raw query
+//! string without the HMAC parameter, so the link can be handed to a browser without any custom
+//! headers. The method and body are not covered.
+
+use std::sync::Arc;
+
+use iron;
+use iron::prelude::*;
+use iron::BeforeMiddleware;
+use url;
+
+use error::{Error, Result};
+use hmac::HmacDigest;
+use replay;
+use util;
+use ::SecretKey;
+
+/// Default name of the query parameter carrying the HMAC
+const DEFAULT_SIGNATURE_PARAM: &'static str = "sig";
+
+/// Default name of the query parameter carrying the expiry
+const DEFAULT_EXPIRES_PARAM: &'static str = "exp";
+
+/// Generates signed URLs and, as BeforeMiddleware, verifies them
+#[derive(Debug, Clone)]
+pub struct SignedUrls<D: HmacDigest> {
+    digest: D,
+    secret: Arc<SecretKey>,
+    signature_param: String,
+    expires_param: String,
+}
+
+impl<D: HmacDigest> SignedUrls<D> {
+    /// Sign and verify URLs with `secret` using the `sig` and `exp` parameters
+    pub fn new<K: Into<SecretKey>>(digest: D, secret: K) -> SignedUrls<D> {
+        SignedUrls {
+            digest: digest,
+            secret: Arc::new(secret.into()),
+            signature_param: DEFAULT_SIGNATURE_PARAM.to_owned(),
+            expires_param: DEFAULT_EXPIRES_PARAM.to_owned()
+        }
+    }
+
+    /// Use other names for the HMAC and expiry parameters
+    pub fn with_params<S, E>(mut self, signature_param: S, expires_param: E) -> SignedUrls<D>
+        where S: Into<String>,
+              E: Into<String>
+    {
+        self.signature_param = signature_param.into();
+        self.expires_param = expires_param.into();
+        self
+    }
+
+    /// Sign the absolute `url` so it is valid until `expires`, in seconds since the Unix epoch
+    ///
+    /// Returns `Error::InvalidUrl` if `url` cannot be parsed.
+    pub fn sign(&self, url: &str, expires: u64) -> Result<String> {
+        let mut url = match url::Url::parse(url) {
+            Ok(url) => url,
+            Err(err) => return Err(Error::InvalidUrl(format!("{}", err)))
+        };
+
+        url.query_pairs_mut().append_pair(&self.expires_param[..], &expires.to_string()[..]);
+        let mac = self.mac(url.path(), url.query().unwrap_or(""));
+        url.query_pairs_mut().append_pair(&self.signature_param[..], &util::to_hex(&mac[..])[..]);
+
+        Ok(url.into_string())
+    }
+
+    /// HMAC of the path and query as signed
+    fn mac(&self, path: &str, query: &str) -> Vec<u8> {
+        self.digest.hmac(&self.secret, format!("{}?{}", path, query).as_bytes())
+    }
+
+    /// Check the HMAC and expiry carried by the query of `url`
+    fn verify(&self, url: &url::Url) -> Result<()> {
+        let mut supplied = None;
+        let mut expires = None;
+        let mut signed = Vec::new();
+
+        for param in url.query().unwrap_or("").split('&') {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("");
+            if name == self.signature_param {
+                supplied = Some(try!(util::from_hex(value.as_bytes())));
+                continue;
+            }
+            if name == self.expires_param {
+                expires = value.parse::<u64>().ok();
+            }
+            signed.push(param);
+        }
+
+        let (supplied, expires) = match (supplied, expires) {
+            (Some(supplied), Some(expires)) => (supplied, expires),
+            _ => return Err(Error::InvalidHmac)
+        };
+
+        let computed = self.mac(url.path(), &signed.join("&")[..]);
+        if computed.len() != supplied.len() || !util::contant_time_equals(&computed, &supplied) {
+            return Err(Error::InvalidHmac);
+        }
+
+        replay::check_expiry(expires)
+    }
+}
+
+impl<D: HmacDigest> BeforeMiddleware for SignedUrls<D> {
+    fn before(&self, req: &mut iron::Request) -> IronResult<()> {
+        let url: url::Url = req.url.clone().into();
+        try!(self.verify(&url));
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! AWS Signature Version 4 compatible verification
+//!
+//! Requests signed by the AWS SDKs carry
+//! `Authorization: AWS4-HMAC-SHA256 Credential=<access key>/<date>/<region>/<service>/aws4_request,
+//! SignedHeaders=host;x-amz-date, Signature=<hex>` and an `X-Amz-Date` timestamp. The signature is
+//! recomputed over the canonical request, whose payload hash is the `X-Amz-Content-Sha256` header
+//! when sent and is checked against the body. Presigned URLs are not supported.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+use sha2::{Digest, Sha256};
+use url;
+
+use canonical::{self, BodySigning};
+use error::{Error, Result};
+use hmac::Algorithm;
+use replay;
+use util;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, SecretKey, Verifier};
+
+/// Algorithm of the `Authorization` header and string to sign
+pub const ALGORITHM: &'static str = "AWS4-HMAC-SHA256";
+
+/// Last element of the credential scope
+pub const TERMINATOR: &'static str = "aws4_request";
+
+/// Payload hash of requests whose body is not signed
+const UNSIGNED_PAYLOAD: &'static str = "UNSIGNED-PAYLOAD";
+
+/// The region and service requests must be scoped to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsSigV4 {
+    pub region: String,
+    pub service: String,
+}
+
+impl AwsSigV4 {
+    pub fn new<R: Into<String>, S: Into<String>>(region: R, service: S) -> AwsSigV4 {
+        AwsSigV4 {
+            region: region.into(),
+            service: service.into()
+        }
+    }
+}
+
+/// The parts of a SigV4 `Authorization` header
+struct Credentials {
+    access_key: String,
+    date: String,
+    region: String,
+    service: String,
+    terminator: String,
+    signed_headers: Vec<String>,
+    signature: Vec<u8>,
+}
+
+/// Verify the SigV4 signature of `req` with the keys of `auth`
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, scope: &AwsSigV4,
+                       req: &mut iron::Request) -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let credentials = try!(credentials(req));
+    if credentials.region != scope.region || credentials.service != scope.service ||
+       credentials.terminator != TERMINATOR {
+        forbidden!();
+    }
+    if !credentials.signed_headers.iter().any(|name| name == "host") {
+        return Err(malformed("the host header must be signed").into());
+    }
+
+    let timestamp = try!(header(req, "x-amz-date"));
+    let seconds = try!(parse_timestamp(&timestamp));
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds),
+                                 replay::timestamp_max_age(&auth.inner.config)));
+    if !timestamp.starts_with(&credentials.date[..]) {
+        forbidden!();
+    }
+
+    let secret = try!(auth.sha256_key(req, &credentials.access_key));
+
+    let payload_hash = try!(payload_hash(req, &auth.inner.config.body));
+    let canonical_request = try!(canonical_request(req, &credentials, &scope.service,
+                                                   &payload_hash));
+    let credential_scope = format!("{}/{}/{}/{}", credentials.date, credentials.region,
+                                   credentials.service, credentials.terminator);
+    let string_to_sign = format!("{}\n{}\n{}\n{}", ALGORITHM, timestamp, credential_scope,
+                                 util::to_hex(&Sha256::digest(canonical_request.as_bytes())));
+
+    let signing_key = signing_key(&secret, &credentials.date, &credentials.region,
+                                  &credentials.service, &credentials.terminator);
+    let computed = try!(Algorithm::Sha256.try_hmac(&signing_key, string_to_sign.as_bytes()));
+
+    let supplied = &credentials.signature;
+    if computed.len() != supplied.len() ||
+       !auth.inner.verifier.verify(&computed[..], &supplied[..]) {
+        forbidden!();
+    }
+
+    Ok(())
+}
+
+/// The key signing strings to sign of the credential scope `date/region/service/terminator`
+pub fn signing_key(secret: &SecretKey, date: &str, region: &str, service: &str, terminator: &str)
+    -> SecretKey
+{
+    let mut signing_key = SecretKey::new(&[&b"AWS4"[..], &secret[..]].concat()[..]);
+    for part in &[date, region, service, terminator] {
+        signing_key = SecretKey::new(&Algorithm::Sha256.hmac(&signing_key, part.as_bytes()));
+    }
+
+    signing_key
+}
+
+/// Parse the SigV4 `Authorization` header
+fn credentials(req: &iron::Request) -> Result<Credentials> {
+    let value = try!(header(req, "authorization"));
+    if !value.starts_with(ALGORITHM) {
+        return Err(malformed("not a AWS4-HMAC-SHA256 authorization"));
+    }
+
+    let mut credential = None;
+    let mut signed_headers = None;
+    let mut signature = None;
+    for param in value[ALGORITHM.len()..].split(',') {
+        let mut parts = param.trim().splitn(2, '=');
+        match (parts.next().unwrap_or(""), parts.next()) {
+            ("Credential", Some(value)) => credential = Some(value),
+            ("SignedHeaders", Some(value)) => signed_headers = Some(value),
+            ("Signature", Some(value)) => signature = Some(value),
+            _ => ()
+        }
+    }
+
+    let credential: Vec<&str> = match credential {
+        Some(credential) => credential.split('/').collect(),
+        None => return Err(malformed("missing Credential"))
+    };
+    if credential.len() != 5 {
+        return Err(malformed("invalid Credential"));
+    }
+    let signed_headers = match signed_headers {
+        Some(signed_headers) => signed_headers.split(';').map(|name| name.to_lowercase()).collect(),
+        None => return Err(malformed("missing SignedHeaders"))
+    };
+    let signature = match signature {
+        Some(signature) => try!(util::from_hex(signature.as_bytes())),
+        None => return Err(malformed("missing Signature"))
+    };
+
+    Ok(Credentials {
+        access_key: credential[0].to_owned(),
+        date: credential[1].to_owned(),
+        region: credential[2].to_owned(),
+        service: credential[3].to_owned(),
+        terminator: credential[4].to_owned(),
+        signed_headers: signed_headers,
+        signature: signature
+    })
+}
+
+/// The hex SHA-256 of the body, or the `X-Amz-Content-Sha256` header after checking it
+///
+/// `UNSIGNED-PAYLOAD` is only accepted with `BodySigning::Omit`.
+fn payload_hash(req: &mut iron::Request, body: &BodySigning) -> Result<String> {
+    let supplied = match req.headers.get_raw("x-amz-content-sha256") {
+        Some(value) => Some(try!(::std::str::from_utf8(&value[0][..])).trim().to_lowercase()),
+        None => None
+    };
+
+    if let Some(ref supplied) = supplied {
+        if supplied.eq_ignore_ascii_case(UNSIGNED_PAYLOAD) && *body == BodySigning::Omit {
+            return Ok(UNSIGNED_PAYLOAD.to_owned());
+        }
+    }
+
+    let body = try!(canonical::CanonicalRequest::from_request(req)).body;
+    let computed = util::to_hex(&Sha256::digest(body.as_bytes()));
+    match supplied {
+        Some(ref supplied) if *supplied != computed => {
+            Err(malformed("X-Amz-Content-Sha256 does not match the body"))
+        },
+        _ => Ok(computed)
+    }
+}
+
+/// The canonical request of SigV4
+///
+/// Path segments are encoded once more, except for S3, as the AWS SDKs do.
+fn canonical_request(req: &iron::Request, credentials: &Credentials, service: &str,
+                     payload_hash: &str) -> Result<String> {
+    let url: url::Url = req.url.clone().into();
+    let path = if service == "s3" {
+        url.path().to_owned()
+    } else {
+        url.path()
+           .split('/')
+           .map(|segment| util::percent_encode(segment.as_bytes()))
+           .collect::<Vec<_>>()
+           .join("/")
+    };
+
+    let mut headers = String::new();
+    for name in &credentials.signed_headers {
+        let values = match req.headers.get_raw(name) {
+            Some(values) => values,
+            None => return Err(malformed(&format!("missing {} header", name)))
+        };
+
+        let mut trimmed = Vec::with_capacity(values.len());
+        for value in values.iter() {
+            let value = try!(::std::str::from_utf8(&value[..]));
+            trimmed.push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        headers.push_str(&format!("{}:{}\n", name, trimmed.join(",")));
+    }
+
+    Ok(format!("{}\n{}\n{}\n{}\n{}\n{}",
+               req.method.as_ref(),
+               if path.is_empty() { "/" } else { &path[..] },
+               canonical::sorted_query(url.query().unwrap_or("")),
+               headers,
+               credentials.signed_headers.join(";"),
+               payload_hash))
+}
+
+/// Parse an ISO 8601 basic timestamp such as `20150830T123600Z` into seconds since the epoch
+fn parse_timestamp(timestamp: &str) -> Result<u64> {
+    let invalid = || Error::StaleRequest(format!("invalid X-Amz-Date {}", timestamp));
+    // Checked byte by byte, as the value is not yet authenticated and may hold any characters
+    let well_formed = timestamp.len() == 16 && timestamp.bytes().enumerate().all(|(i, byte)| {
+        match i {
+            8 => byte == b'T',
+            15 => byte == b'Z',
+            _ => byte.is_ascii_digit()
+        }
+    });
+    if !well_formed {
+        return Err(invalid());
+    }
+
+    let field = |range: ::std::ops::Range<usize>| timestamp[range].parse::<u64>();
+    let (year, month, day, hour, minute, second) =
+        match (field(0..4), field(4..6), field(6..8), field(9..11), field(11..13), field(13..15)) {
+            (Ok(year), Ok(month), Ok(day), Ok(hour), Ok(minute), Ok(second)) => {
+                (year, month, day, hour, minute, second)
+            },
+            _ => return Err(invalid())
+        };
+    if year < 1970 || month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || minute > 59 ||
+       second > 59 {
+        return Err(invalid());
+    }
+
+    // Days since the epoch of the proleptic Gregorian date, counting years from March
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let days = 365 * year + year / 4 - year / 100 + year / 400 + (153 * month + 2) / 5 + day - 1
+        - 719468;
+
+    Ok(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Format seconds since the epoch as an ISO 8601 basic timestamp such as `20150830T123600Z`
+#[cfg(feature = "secrets-aws")]
+pub fn format_timestamp(seconds: u64) -> String {
+    format!("{}T{:02}{:02}{:02}Z", util::format_date(seconds), seconds % 86400 / 3600,
+            seconds % 3600 / 60, seconds % 60)
+}
+
+/// The trimmed value of a required header
+fn header(req: &iron::Request, name: &str) -> Result<String> {
+    match req.headers.get_raw(name) {
+        Some(value) => Ok(try!(::std::str::from_utf8(&value[0][..])).trim().to_owned()),
+        None => Err(malformed(&format!("missing {} header", name)))
+    }
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::MalformedSignature(reason.to_owned())
+}
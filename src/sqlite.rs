@@ -0,0 +1,115 @@
+//! Secrets looked up in a SQLite table
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use iron;
+use rusqlite::{self, Connection, OptionalExtension};
+
+use error::{Error, Result};
+use secret_store::SecretProvider;
+use ::SecretKey;
+
+/// Looks up the secret of an enabled key that has not expired
+const QUERY: &'static str = "SELECT secret FROM hmac_keys \
+                             WHERE key_id = ?1 AND enabled <> 0 \
+                             AND (expires_at IS NULL OR expires_at > ?2)";
+
+/// Secrets of the keys in the `hmac_keys` table of a SQLite database
+///
+/// The table holds one row per key, which is found by the key id the request names:
+///
+/// ```sql
+/// CREATE TABLE hmac_keys (
+///     key_id TEXT PRIMARY KEY,
+///     secret BLOB NOT NULL,
+///     enabled INTEGER NOT NULL DEFAULT 1,
+///     -- seconds since the Unix epoch, or NULL if the key never expires
+///     expires_at INTEGER
+/// );
+/// ```
+///
+/// Disabled and expired keys are rejected, as are requests naming no key unless `default_key` is
+/// set. The lookup is a prepared statement, compiled once and reused. Every request queries the
+/// database, so wrap the provider in a `CachingSecretProvider`, keeping in mind that a disabled
+/// key stays usable for up to the cache's `ttl`:
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use iron_hmac::{CachingSecretProvider, Config, SqliteSecretProvider};
+///
+/// let keys = SqliteSecretProvider::open("/var/lib/api/keys.db").unwrap();
+/// let config = Config {
+///     key_id_header: Some("x-hmac-key-id".to_owned()),
+///     secret_provider: Some(Arc::new(CachingSecretProvider::new(keys))),
+///     ..Config::default()
+/// };
+/// ```
+///
+/// For other databases or schemas, this provider is a template: hold the connection, or a
+/// connection pool, and run one prepared query per lookup in `SecretProvider::secret_for`.
+#[derive(Debug)]
+pub struct SqliteSecretProvider {
+    connection: Mutex<Connection>,
+    default_key: Option<String>,
+}
+
+impl SqliteSecretProvider {
+    /// Look keys up in the database at `path`
+    ///
+    /// Returns an error if the database cannot be opened or has no `hmac_keys` table.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteSecretProvider> {
+        match Connection::open(path) {
+            Ok(connection) => SqliteSecretProvider::new(connection),
+            Err(err) => Err(unavailable(err))
+        }
+    }
+
+    /// Look keys up over `connection`
+    pub fn new(connection: Connection) -> Result<SqliteSecretProvider> {
+        if let Err(err) = connection.prepare_cached(QUERY) {
+            return Err(unavailable(err));
+        }
+
+        Ok(SqliteSecretProvider {
+            connection: Mutex::new(connection),
+            default_key: None
+        })
+    }
+
+    /// Verify requests naming no key with the key `key_id`
+    pub fn default_key<S: Into<String>>(mut self, key_id: S) -> SqliteSecretProvider {
+        self.default_key = Some(key_id.into());
+        self
+    }
+
+    /// The secret of the key `key_id`, if it is enabled and has not expired
+    fn lookup(&self, key_id: &str) -> rusqlite::Result<Option<Vec<u8>>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs())
+                                   .unwrap_or(0);
+        let connection = self.connection.lock().unwrap();
+        let mut statement = try!(connection.prepare_cached(QUERY));
+
+        statement.query_row((key_id, now as i64), |row| row.get(0)).optional()
+    }
+}
+
+impl SecretProvider for SqliteSecretProvider {
+    fn secret_for(&self, _req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        let key_id = match key_id.or(self.default_key.as_ref().map(|id| &id[..])) {
+            Some(key_id) => key_id,
+            None => return None
+        };
+
+        match self.lookup(key_id) {
+            Ok(Some(ref secret)) if secret.is_empty() => None,
+            Ok(Some(secret)) => Some(SecretKey::from_vec(secret)),
+            _ => None
+        }
+    }
+}
+
+fn unavailable(err: rusqlite::Error) -> Error {
+    Error::SecretBackendUnavailable(err.to_string())
+}
@@ -0,0 +1,74 @@
+//! Stripe webhook signatures
+//!
+//! Deliveries carry `Stripe-Signature: t=<timestamp>,v1=<hex>`, where the signature is the
+//! HMAC-SHA256 of `<timestamp>.<body>`. Several `v1` signatures are sent while the endpoint secret
+//! is being rolled, and other schemes such as `v0` are ignored.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use iron;
+use iron::prelude::*;
+
+use canonical::CanonicalRequest;
+use error::{Error, Result};
+use replay;
+use util;
+use ::{Canonicalizer, HmacAuthentication, HmacDigest, Verifier};
+
+/// Header carrying the timestamp and signatures
+const SIGNATURE_HEADER: &'static str = "stripe-signature";
+
+/// Verify the `Stripe-Signature` header of `req` with the secret of `auth`
+///
+/// The timestamp must be within `Config::timestamp_max_age` and any `v1` signature may match.
+pub fn verify<D, V, C>(auth: &HmacAuthentication<D, V, C>, req: &mut iron::Request)
+    -> IronResult<()>
+    where D: HmacDigest,
+          V: Verifier,
+          C: Canonicalizer
+{
+    let (timestamp, signatures) = try!(parse(req));
+    let seconds: u64 = match timestamp.parse() {
+        Ok(seconds) => seconds,
+        Err(_) => return Err(Error::StaleRequest(format!("invalid timestamp {}", timestamp)).into())
+    };
+    try!(replay::check_freshness(UNIX_EPOCH + Duration::from_secs(seconds),
+                                 replay::timestamp_max_age(&auth.inner.config)));
+
+    let body = try!(CanonicalRequest::from_request(req)).body;
+    let mac = try!(auth.keyed_mac(req, None, None));
+    let computed = try!(mac(format!("{}.{}", timestamp, body).as_bytes()));
+
+    let matched = signatures.iter().any(|supplied| {
+        computed.len() == supplied.len() && auth.inner.verifier.verify(&computed[..], &supplied[..])
+    });
+    if !matched {
+        forbidden!();
+    }
+
+    Ok(())
+}
+
+/// The timestamp and decoded `v1` signatures of the `Stripe-Signature` header
+fn parse(req: &iron::Request) -> Result<(String, Vec<Vec<u8>>)> {
+    let value = match req.headers.get_raw(SIGNATURE_HEADER) {
+        Some(value) => try!(::std::str::from_utf8(&value[0][..])).to_owned(),
+        None => return Err(Error::MissingHmacHeader(SIGNATURE_HEADER.to_owned()))
+    };
+
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for item in value.split(',') {
+        let mut parts = item.trim().splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("t"), Some(value)) => timestamp = Some(value.to_owned()),
+            (Some("v1"), Some(value)) => signatures.push(try!(util::from_hex(value.as_bytes()))),
+            _ => ()
+        }
+    }
+
+    match timestamp {
+        Some(timestamp) if !signatures.is_empty() => Ok((timestamp, signatures)),
+        _ => Err(Error::MalformedSignature("missing timestamp or v1 signature".to_owned()))
+    }
+}
@@ -0,0 +1,75 @@
+//! Structured HMAC headers carrying signature parameters
+//!
+//! A structured header value is a comma separated list of parameters, e.g.
+//! `keyId=abc,alg=hmac-sha256,ts=1699999999,nonce=f81d4fae,sig=7f2c...`. Values may be quoted.
+
+use iron;
+
+use config::Config;
+use error::Result;
+use util;
+
+/// Parameter carrying the key id
+const KEY_ID_PARAM: &'static str = "keyId";
+
+/// Parameter carrying the algorithm name
+const ALGORITHM_PARAM: &'static str = "alg";
+
+/// Parameter carrying the timestamp
+const TIMESTAMP_PARAM: &'static str = "ts";
+
+/// Parameter carrying the nonce
+const NONCE_PARAM: &'static str = "nonce";
+
+/// Parameter carrying the MAC
+const SIGNATURE_PARAM: &'static str = "sig";
+
+/// Split a structured header value into its parameters
+pub fn parse(value: &str) -> Vec<(String, String)> {
+    value.split(',')
+         .map(|param| {
+             let mut parts = param.trim().splitn(2, '=');
+             let name = parts.next().unwrap_or("").trim().to_owned();
+             let value = parts.next().unwrap_or("").trim().trim_matches('"').to_owned();
+             (name, value)
+         })
+         .filter(|&(ref name, _)| !name.is_empty())
+         .collect()
+}
+
+/// Move the parameters of the structured HMAC header into the headers they stand for
+///
+/// The `sig` parameter replaces the HMAC header value, and the key id, algorithm, timestamp, and
+/// nonce parameters are set as the headers configured for them, so they are looked up and covered
+/// by the request HMAC as if sent separately. Parameters whose header is not configured are
+/// ignored.
+pub fn apply(req: &mut iron::Request, config: &Config, hmac_header: &str) -> Result<()> {
+    let params = match try!(util::single_header(req, hmac_header, config.duplicate_headers)) {
+        Some(value) => parse(value),
+        None => return Ok(())
+    };
+
+    let targets = [
+        (KEY_ID_PARAM, config.key_id_header.as_ref()),
+        (ALGORITHM_PARAM, config.algorithm_header.as_ref()),
+        (TIMESTAMP_PARAM, config.timestamp_header.as_ref()),
+        (NONCE_PARAM, config.nonce_header.as_ref()),
+    ];
+
+    req.headers.remove_raw(hmac_header);
+    for (name, value) in params {
+        if name == SIGNATURE_PARAM {
+            req.headers.set_raw(hmac_header.to_owned(), vec![value.into_bytes()]);
+            continue;
+        }
+
+        let header = targets.iter()
+                            .find(|&&(param, _)| param == name)
+                            .and_then(|&(_, header)| header);
+        if let Some(header) = header {
+            req.headers.set_raw(header.clone(), vec![value.into_bytes()]);
+        }
+    }
+
+    Ok(())
+}
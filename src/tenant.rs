@@ -0,0 +1,122 @@
+//! Secrets of the tenant a request is addressed to
+
+use std::collections::HashMap;
+use std::fmt;
+
+use iron;
+
+use secret_store::SecretProvider;
+use ::SecretKey;
+
+/// Where the tenant of a request is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantSource {
+    /// The whole host, e.g. `api.acme.com`
+    Host,
+    /// The subdomain of the given domain, e.g. `acme` for `acme.example.com` with `example.com`
+    ///
+    /// Requests to the domain itself or to other domains have no tenant.
+    Subdomain(String),
+    /// The value of the given header, e.g. `x-tenant-id`
+    Header(String),
+}
+
+impl Default for TenantSource {
+    fn default() -> TenantSource {
+        TenantSource::Host
+    }
+}
+
+impl TenantSource {
+    /// The tenant of `req`, if it names one
+    ///
+    /// Hosts are lowercased; header values are only trimmed.
+    pub fn tenant(&self, req: &iron::Request) -> Option<String> {
+        match *self {
+            TenantSource::Host => Some(req.url.host().to_string().to_lowercase()),
+            TenantSource::Subdomain(ref domain) => {
+                let host = req.url.host().to_string().to_lowercase();
+                let domain = domain.trim_matches('.').to_lowercase();
+                if host.len() <= domain.len() + 1 || !host.ends_with(&domain[..]) {
+                    return None;
+                }
+
+                let subdomain = &host[..host.len() - domain.len()];
+                if subdomain.ends_with('.') {
+                    Some(subdomain[..subdomain.len() - 1].to_owned())
+                } else {
+                    None
+                }
+            },
+            TenantSource::Header(ref name) => {
+                req.headers.get_raw(name)
+                           .and_then(|value| ::std::str::from_utf8(&value[0][..]).ok())
+                           .map(|value| value.trim().to_owned())
+                           .filter(|value| !value.is_empty())
+            }
+        }
+    }
+}
+
+/// Lookup of the secrets of each tenant
+///
+/// Implement this for tenants kept in a database.
+pub trait TenantSecrets: Send + Sync + 'static {
+    /// The secret of `tenant`, signed with the key `key_id` if the request named one
+    fn secret(&self, tenant: &str, key_id: Option<&str>) -> Option<SecretKey>;
+}
+
+impl fmt::Debug for dyn TenantSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("TenantSecrets")
+    }
+}
+
+/// One secret per tenant; requests naming a key id have no secret
+impl TenantSecrets for HashMap<String, SecretKey> {
+    fn secret(&self, tenant: &str, key_id: Option<&str>) -> Option<SecretKey> {
+        match key_id {
+            Some(_) => None,
+            None => self.get(tenant).cloned()
+        }
+    }
+}
+
+/// Secrets of many tenants served by one deployment
+///
+/// The tenant of each request is read from its `TenantSource` and its secret looked up in the
+/// `TenantSecrets`. Requests without a tenant or to an unknown tenant are rejected. Wrap the
+/// provider in a `CachingSecretProvider`, with a `cache_key` combining the tenant and key id, if
+/// looking secrets up is expensive.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::sync::Arc;
+/// use iron_hmac::{Config, SecretKey, TenantSecretProvider, TenantSource};
+///
+/// let mut secrets = HashMap::new();
+/// secrets.insert("acme".to_owned(), SecretKey::new(b"acme secret"));
+/// let tenants = TenantSecretProvider::new(TenantSource::Subdomain("example.com".to_owned()),
+///                                         secrets);
+/// let config = Config { secret_provider: Some(Arc::new(tenants)), ..Config::default() };
+/// ```
+#[derive(Debug)]
+pub struct TenantSecretProvider<T> {
+    source: TenantSource,
+    secrets: T,
+}
+
+impl<T: TenantSecrets> TenantSecretProvider<T> {
+    pub fn new(source: TenantSource, secrets: T) -> TenantSecretProvider<T> {
+        TenantSecretProvider {
+            source: source,
+            secrets: secrets
+        }
+    }
+}
+
+impl<T: TenantSecrets> SecretProvider for TenantSecretProvider<T> {
+    fn secret_for(&self, req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        self.source.tenant(req).and_then(|tenant| self.secrets.secret(&tenant, key_id))
+    }
+}
@@ -6,7 +6,10 @@ use rustc_serialize::hex::ToHex;
 
 use constant_time_eq::constant_time_eq;
 
-use ::error::{Result};
+use iron;
+
+use ::config::DuplicateHeaders;
+use ::error::{Error, Result};
 
 /// Constant time equality comparison for byte lists
 #[inline]
@@ -49,8 +52,88 @@ pub fn to_hex(bytes: &[u8]) -> String {
     bytes.to_hex()
 }
 
-/// Interpret a slice of utf8 bytes as hex values
+/// Interpret a slice of utf8 bytes as hex values of either case
 pub fn from_hex(maybe_utf8_bytes: &[u8]) -> Result<Vec<u8>> {
     let s = try!(from_utf8(maybe_utf8_bytes));
     Ok(try!(s.from_hex()))
 }
+
+/// Format seconds since the Unix epoch as the UTC date `YYYYMMDD`
+pub fn format_date(seconds: u64) -> String {
+    // Days since the epoch to the proleptic Gregorian date, counting years from March
+    let days = seconds / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096)
+        / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let (year, month) = if month < 10 {
+        (era * 400 + year_of_era, month + 3)
+    } else {
+        (era * 400 + year_of_era + 1, month - 9)
+    };
+
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+/// Percent encode everything but unreserved characters (RFC 3986 section 2.3)
+///
+/// Hex digits are uppercase, so the result is canonical.
+pub fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        if (byte as char).is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+/// Read the full response body and pass it to `f`
+///
+/// The body is replaced with the buffered bytes afterwards so the response can still be sent.
+pub fn with_response_body<T, F>(res: &mut iron::Response, f: F) -> Result<T>
+    where F: FnOnce(&[u8]) -> T
+{
+    let body: Vec<u8> = match res.body {
+        Some(ref mut body) => {
+            let mut buf = Buffer::new();
+            try!(body.write_body(&mut buf));
+            buf.to_inner()
+        },
+        None => Vec::new()
+    };
+    let result = f(&body[..]);
+
+    // Need to reset body now that we've written it
+    res.body = Some(Box::new(body));
+
+    Ok(result)
+}
+
+/// The trimmed value of the request header `name`, if present
+///
+/// A header sent more than once is rejected under `DuplicateHeaders::Reject`, and otherwise
+/// accepted only when every value is the same, since only one of them can be used.
+pub fn single_header<'r>(req: &'r iron::Request, name: &str, duplicates: DuplicateHeaders)
+    -> Result<Option<&'r str>>
+{
+    let values = match req.headers.get_raw(name) {
+        Some(values) => values,
+        None => return Ok(None)
+    };
+
+    let value = try!(from_utf8(&values[0][..])).trim();
+    for other in &values[1..] {
+        if duplicates == DuplicateHeaders::Reject || try!(from_utf8(&other[..])).trim() != value {
+            return Err(Error::MalformedSignature(format!("multiple {} headers", name)));
+        }
+    }
+
+    Ok(Some(value))
+}
@@ -0,0 +1,187 @@
+//! Secrets fetched from the KV secrets engine of HashiCorp Vault
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use iron;
+use reqwest;
+use rustc_serialize::json::Json;
+
+use error::{Error, Result};
+use secret_store::SecretProvider;
+use ::SecretKey;
+
+/// How long secrets without a lease are cached by default
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How long to keep serving cached secrets after a failed refresh before trying again
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The secrets of the last successful fetch
+struct Cached {
+    secrets: HashMap<String, SecretKey>,
+    lease: Option<Duration>,
+    fetched: Instant,
+    retry_at: Option<Instant>,
+    refreshing: bool,
+}
+
+/// Secrets read from a secret of Vault's KV engine and cached until their lease runs out
+///
+/// Requests naming no key use one field of the secret, and requests naming key `id` the field
+/// `id`. Field values are decoded as by `SecretKey::from_env`, so `hex:` and `base64:` prefixes
+/// are understood. Secrets are refetched once their lease duration has passed or, for KV secrets
+/// which carry no lease, after the refresh interval. The request that triggers a refetch waits
+/// for Vault while other requests keep being served the cached secrets, which also stay in use
+/// if Vault cannot be reached.
+pub struct VaultSecretProvider {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+    field: String,
+    refresh_interval: Duration,
+    cache: Mutex<Cached>,
+}
+
+impl fmt::Debug for VaultSecretProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VaultSecretProvider")
+         .field("url", &self.url)
+         .field("field", &self.field)
+         .field("refresh_interval", &self.refresh_interval)
+         .finish()
+    }
+}
+
+impl VaultSecretProvider {
+    /// Read the secret at `path` of the version 2 KV engine mounted at `mount`, e.g. `secret`
+    ///
+    /// `address` is the Vault server, e.g. `https://vault.example.com:8200`, and `token` a Vault
+    /// token allowed to read the secret. Requests naming no key use the secret's `field`. The
+    /// secret is fetched here, so that a misconfiguration fails at startup with
+    /// `Error::SecretBackendUnavailable` or `Error::InvalidKey`.
+    pub fn new(address: &str, token: &str, mount: &str, path: &str, field: &str)
+        -> Result<VaultSecretProvider>
+    {
+        let provider = VaultSecretProvider {
+            client: reqwest::Client::new(),
+            url: format!("{}/v1/{}/data/{}", address.trim_end_matches('/'),
+                         mount.trim_matches('/'), path.trim_matches('/')),
+            token: token.to_owned(),
+            field: field.to_owned(),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            cache: Mutex::new(Cached {
+                secrets: HashMap::new(),
+                lease: None,
+                fetched: Instant::now(),
+                retry_at: None,
+                refreshing: false
+            })
+        };
+
+        let (secrets, lease) = try!(provider.fetch());
+        {
+            let mut cached = provider.cache.lock().unwrap();
+            cached.secrets = secrets;
+            cached.lease = lease;
+        }
+
+        Ok(provider)
+    }
+
+    /// Refetch secrets without a lease this often; every five minutes if not set
+    pub fn refresh_interval(mut self, interval: Duration) -> VaultSecretProvider {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Fetch the fields of the secret and its lease duration, if any
+    fn fetch(&self) -> Result<(HashMap<String, SecretKey>, Option<Duration>)> {
+        let mut headers = reqwest::header::Headers::new();
+        headers.set_raw("X-Vault-Token", self.token.clone());
+
+        let mut res = try!(self.client.get(&self.url[..])
+                                      .headers(headers)
+                                      .send()
+                                      .map_err(|err| unavailable(&err.to_string())));
+        if !res.status().is_success() {
+            return Err(unavailable(&format!("Vault responded with {}", res.status())));
+        }
+
+        let mut body = String::new();
+        try!(res.read_to_string(&mut body));
+        let json = match Json::from_str(&body) {
+            Ok(json) => json,
+            Err(_) => return Err(unavailable("Vault responded with invalid JSON"))
+        };
+
+        let data = match json.find_path(&["data", "data"]) {
+            Some(&Json::Object(ref data)) => data,
+            _ => return Err(unavailable("Vault responded without secret data"))
+        };
+
+        let mut secrets = HashMap::new();
+        for (name, value) in data {
+            if let Json::String(ref value) = *value {
+                let setting = format!("Vault field {}", name);
+                secrets.insert(name.clone(), try!(SecretKey::from_setting(&setting, value)));
+            }
+        }
+        if !secrets.contains_key(&self.field) {
+            return Err(Error::InvalidKey(format!("Vault secret has no {} field", self.field)));
+        }
+
+        let lease = json.find("lease_duration")
+                        .and_then(Json::as_u64)
+                        .filter(|&seconds| seconds > 0)
+                        .map(Duration::from_secs);
+
+        Ok((secrets, lease))
+    }
+}
+
+impl SecretProvider for VaultSecretProvider {
+    fn secret_for(&self, _req: &iron::Request, key_id: Option<&str>) -> Option<SecretKey> {
+        let now = Instant::now();
+        let refresh = {
+            let mut cached = self.cache.lock().unwrap();
+            let expired = now.duration_since(cached.fetched) >=
+                          cached.lease.unwrap_or(self.refresh_interval);
+            let backing_off = cached.retry_at.map_or(false, |retry_at| now < retry_at);
+            if expired && !backing_off && !cached.refreshing {
+                cached.refreshing = true;
+                true
+            } else {
+                false
+            }
+        };
+
+        // Vault is only asked once the lock is released, so concurrent requests are not held up
+        let fetched = if refresh { Some(self.fetch()) } else { None };
+
+        let mut cached = self.cache.lock().unwrap();
+        match fetched {
+            Some(Ok((secrets, lease))) => {
+                cached.secrets = secrets;
+                cached.lease = lease;
+                cached.fetched = now;
+                cached.retry_at = None;
+                cached.refreshing = false;
+            },
+            Some(Err(_)) => {
+                cached.retry_at = Some(now + RETRY_INTERVAL);
+                cached.refreshing = false;
+            },
+            None => ()
+        }
+
+        cached.secrets.get(key_id.unwrap_or(&self.field[..])).cloned()
+    }
+}
+
+fn unavailable(reason: &str) -> Error {
+    Error::SecretBackendUnavailable(reason.to_owned())
+}
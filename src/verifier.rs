@@ -0,0 +1,25 @@
+//! Comparison of computed and supplied MACs
+
+use util;
+
+/// Compares the MAC computed for a request with the one the client supplied
+///
+/// Implement this to use a vetted comparison primitive. Implementations must not leak timing
+/// information about where the inputs differ. The middleware only calls `verify` with inputs of
+/// equal length.
+pub trait Verifier: Clone + Send + Sync + 'static {
+    /// Whether `supplied` matches `computed`
+    fn verify(&self, computed: &[u8], supplied: &[u8]) -> bool;
+}
+
+/// Constant time comparison provided by the `constant_time_eq` crate
+///
+/// This is the default verifier.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstantTimeVerifier;
+
+impl Verifier for ConstantTimeVerifier {
+    fn verify(&self, computed: &[u8], supplied: &[u8]) -> bool {
+        util::contant_time_equals(computed, supplied)
+    }
+}
@@ -3,6 +3,11 @@ extern crate iron_hmac;
 extern crate iron;
 extern crate bodyparser;
 extern crate persistent;
+extern crate rustc_serialize;
+#[cfg(feature = "aws-sigv4")]
+extern crate sha2;
+#[cfg(feature = "secrets-sqlite")]
+extern crate rusqlite;
 
 #[macro_use]
 extern crate hyper;
@@ -10,7 +15,8 @@ extern crate reqwest;
 
 use reqwest::Client;
 use iron::prelude::*;
-use iron_hmac::Hmac256Authentication;
+use iron::{BeforeMiddleware, AfterMiddleware};
+use iron_hmac::{Algorithm, Backend, Config, Hmac256Authentication, HmacAuthentication, Key, Sha256};
 use std::io::Read;
 
 /// The header used for our tests
@@ -19,6 +25,12 @@ const HMAC_HEADER_NAME: &'static str = "x-hmac";
 /// Hyper wrapper for the hmac header
 header! { (XHmac, HMAC_HEADER_NAME) => [String] }
 
+/// Hyper wrapper for the algorithm header
+header! { (XHmacAlg, "x-hmac-alg") => [String] }
+
+/// Hyper wrapper for the key id header
+header! { (XHmacKeyId, "x-hmac-key-id") => [String] }
+
 /// Ensures that the iron server is closed (and the test thread ends) upon failure. The drop
 /// implementation simply calls close on the underlying hyper server.
 struct CloseGuard(::iron::Listening);
@@ -37,6 +49,19 @@ fn build_hmac_hello_world() -> (CloseGuard, String) {
     // Create the hmac middleware
     let (hmac_before, hmac_after) = Hmac256Authentication::middleware("rust :)", "x-hmac");
 
+    build_hello_world(hmac_before, hmac_after)
+}
+
+/// Configuration accepting the short secrets of the test vectors
+fn test_config() -> Config {
+    Config { min_secret_len: Some(0), ..Config::default() }
+}
+
+/// Build a server using the provided hmac middleware
+fn build_hello_world<B, A>(hmac_before: B, hmac_after: A) -> (CloseGuard, String)
+    where B: BeforeMiddleware,
+          A: AfterMiddleware
+{
     let mut chain = Chain::new(|_: &mut Request| {
         Ok(Response::with((iron::status::Ok, "Hello, world!")))
     });
@@ -124,3 +149,3441 @@ fn correct_hmac_is_ok() {
         assert_eq!(&actual_hmac[..], &expected_response_hmac[..]);
     }
 }
+
+#[test]
+fn every_backend_computes_the_same_hmac() {
+    for backend in Backend::available() {
+        let (hmac_before, hmac_after) =
+            Hmac256Authentication::middleware_with_backend(*backend, "rust :)", "x-hmac")
+                .unwrap();
+        let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok, "backend {:?}", backend);
+    }
+}
+
+#[cfg(all(feature = "hmac-sodium", feature = "hmac-rust-crypto"))]
+#[test]
+fn sodium_backend_is_rejected_for_sha384() {
+    use iron_hmac::Sha384;
+
+    let result = HmacAuthentication::<Sha384>::middleware_with_backend(Backend::Sodium, "rust :)",
+                                                                       "x-hmac");
+    assert!(result.is_err());
+}
+
+#[test]
+fn custom_builder_is_used() {
+    use iron_hmac::{BuilderDigest, HmacBuilder, HmacDigest, SecretKey};
+
+    /// Buffers input and delegates to the default SHA-256 digest
+    struct BufferedHmac(SecretKey, Vec<u8>);
+
+    impl HmacBuilder for BufferedHmac {
+        fn new(secret: &SecretKey) -> BufferedHmac {
+            BufferedHmac(secret.clone(), Vec::new())
+        }
+
+        fn output_len() -> usize {
+            32
+        }
+
+        fn input(&mut self, data: &[u8]) -> &mut BufferedHmac {
+            self.1.extend_from_slice(data);
+            self
+        }
+
+        fn finalize(self) -> Vec<u8> {
+            Sha256::default().hmac(&self.0, &self.1[..])
+        }
+    }
+
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::<BuilderDigest<BufferedHmac>>::middleware("rust :)", "x-hmac");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+}
+
+#[test]
+fn custom_verifier_is_used() {
+    use iron_hmac::Verifier;
+
+    #[derive(Clone)]
+    struct RejectAll;
+
+    impl Verifier for RejectAll {
+        fn verify(&self, _computed: &[u8], _supplied: &[u8]) -> bool {
+            false
+        }
+    }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::middleware("rust :)", "x-hmac");
+    let hmac_before = hmac_before.with_verifier(RejectAll);
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn hkdf_matches_rfc_5869() {
+    use iron_hmac::{Hkdf, SecretKey};
+
+    // RFC 5869 test case 1
+    let salt = (0..13).collect::<Vec<u8>>();
+    let info = (0xf0..0xfa).collect::<Vec<u8>>();
+    let hkdf = Hkdf::new(Sha256::default(), &salt[..], SecretKey::new(&[0x0b; 22]));
+    let okm = hkdf.derive(&info[..], 42).unwrap();
+
+    assert_eq!(okm.to_hex(),
+               "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+                34007208d5b887185865");
+
+    assert!(hkdf.derive(&info[..], 255 * 32 + 1).is_err());
+}
+
+#[test]
+fn pbkdf2_matches_known_vector() {
+    use iron_hmac::{Pbkdf2, SecretKey};
+
+    let params = Pbkdf2 { iterations: 2, ..Pbkdf2::new(Sha256::default(), b"salt") };
+    let key = SecretKey::from_passphrase("password", &params).unwrap();
+
+    assert_eq!(key.to_hex(), "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43");
+}
+
+#[test]
+fn date_scoped_keys_are_accepted() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let secret = SecretKey::new(b"rust :)");
+    let date_key = SecretKey::new(&Sha256::default().hmac(&secret, b"19700102")[..]);
+    let expected = Sha256::default().hmac(&date_key, b"billing");
+    let derived = secret.date_scoped(&Sha256::default(), UNIX_EPOCH + Duration::from_secs(86400),
+                                     "billing");
+    assert_eq!(&derived[..], &expected[..]);
+
+    let config = Config { date_scoped_service: Some("billing".to_owned()), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = |secret: &SecretKey| {
+            let hmac = |data: &[u8]| Sha256::default().hmac(secret, data);
+            let mut merged = Vec::new();
+            for part in &["GET", "/", ""] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+
+        let client = Client::new();
+        let status = |secret: &SecretKey| {
+            client.get(&url[..])
+                  .header(XHmac(request_hmac(secret)))
+                  .send().unwrap()
+                  .status()
+        };
+
+        let today = secret.date_scoped(&Sha256::default(), SystemTime::now(), "billing");
+        let last_week = secret.date_scoped(&Sha256::default(),
+                                           SystemTime::now() - Duration::from_secs(7 * 86400),
+                                           "billing");
+        let other_service = secret.date_scoped(&Sha256::default(), SystemTime::now(), "search");
+        assert_eq!(status(&today), hyper::StatusCode::Ok);
+        assert_eq!(status(&last_week), hyper::StatusCode::Forbidden);
+        assert_eq!(status(&other_service), hyper::StatusCode::Forbidden);
+        assert_eq!(status(&secret), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn keys_are_read_from_the_environment() {
+    use iron_hmac::SecretKey;
+
+    std::env::set_var("IRON_HMAC_TEST_SECRET", "hex:72757374203a29");
+    assert_eq!(&SecretKey::from_env("IRON_HMAC_TEST_SECRET").unwrap()[..], b"rust :)");
+    std::env::set_var("IRON_HMAC_TEST_SECRET", "base64:not base64!");
+    assert!(SecretKey::from_env("IRON_HMAC_TEST_SECRET").is_err());
+    assert!(SecretKey::from_env("IRON_HMAC_TEST_UNSET").is_err());
+
+    std::env::set_var("IRON_HMAC_TEST_KEY_PARTNER", "partner");
+    let keys = SecretKey::map_from_env("IRON_HMAC_TEST_KEY_").unwrap();
+    assert_eq!(&keys["partner"][..], b"partner");
+}
+
+#[test]
+fn generated_keys_are_random() {
+    use iron_hmac::SecretKey;
+
+    let key = SecretKey::generate(32).unwrap();
+    let other = SecretKey::generate(32).unwrap();
+
+    assert_eq!(key.len(), 32);
+    assert_eq!(key.to_hex().len(), 64);
+    assert_eq!(key.to_base64().len(), 44);
+    assert!(key.to_hex() != other.to_hex());
+}
+
+#[test]
+fn keys_are_decoded_from_hex_and_base64() {
+    use iron_hmac::{Error, SecretKey};
+
+    let key = SecretKey::from_hex("72757374203a29\n").unwrap();
+    assert_eq!(&key[..], b"rust :)");
+
+    let key = SecretKey::from_base64("cnVzdCA6KQ==").unwrap();
+    assert_eq!(&key[..], b"rust :)");
+
+    match SecretKey::from_hex("not hex") {
+        Err(Error::DecodingHex(_)) => (),
+        other => panic!("expected DecodingHex, got {:?}", other)
+    }
+
+    match SecretKey::from_base64("not base64!") {
+        Err(Error::DecodingBase64(_)) => (),
+        other => panic!("expected DecodingBase64, got {:?}", other)
+    }
+}
+
+#[test]
+fn keys_are_parsed_from_strings() {
+    use iron_hmac::{Error, SecretKey};
+
+    for encoded in &["hex:72757374203a29", "base64:cnVzdCA6KQ==", "raw:rust :)", "rust :)"] {
+        let key: SecretKey = encoded.parse().unwrap();
+        assert_eq!(&key[..], b"rust :)");
+    }
+
+    let key: SecretKey = "raw:hex:72".parse().unwrap();
+    assert_eq!(&key[..], b"hex:72");
+
+    for invalid in &["hex:not hex", "base64:not base64!", "raw:", ""] {
+        match invalid.parse::<SecretKey>() {
+            Err(Error::InvalidKey(_)) => (),
+            other => panic!("expected InvalidKey, got {:?}", other)
+        }
+    }
+}
+
+#[test]
+fn secrets_are_redacted_in_debug_output() {
+    use iron_hmac::SecretKey;
+
+    let key = SecretKey::new(b"rust :)");
+    let debug = format!("{:?}", key);
+    assert!(debug.starts_with("SecretKey(redacted, fingerprint "));
+    assert_eq!(debug, format!("{:?}", SecretKey::new(b"rust :)")));
+    assert!(debug != format!("{:?}", SecretKey::new(b"other")));
+    assert_eq!(key.expose_secret(), b"rust :)");
+
+    let (hmac_before, _) = Hmac256Authentication::middleware("rust :)", "x-hmac");
+    assert!(!format!("{:?}", hmac_before).contains("114, 117, 115, 116"));
+}
+
+#[test]
+fn short_secrets_are_rejected() {
+    use iron_hmac::Error;
+
+    let short = Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac",
+                                                   Config::default());
+    match short {
+        Err(Error::InvalidKey(_)) => (),
+        other => panic!("expected InvalidKey, got {:?}", other.map(|_| ()))
+    }
+
+    let secret = "0123456789abcdef0123456789abcdef";
+    assert!(Hmac256Authentication::with_config(Sha256::default(), secret, "x-hmac",
+                                               Config::default()).is_ok());
+
+    let mut config = Config::default();
+    config.keys.insert("partner".to_owned(), Key::new(secret, Algorithm::Sha512));
+    assert!(Hmac256Authentication::with_config(Sha256::default(), secret, "x-hmac", config)
+                .is_ok());
+
+    let mut config = Config::default();
+    config.keys.insert("partner".to_owned(), Key::new("rust :)", Algorithm::Sha512));
+    assert!(Hmac256Authentication::with_config(Sha256::default(), secret, "x-hmac", config)
+                .is_err());
+
+    assert!(Hmac256Authentication::try_middleware("rust :)", "x-hmac").is_err());
+    assert!(iron_hmac::Hmac512Authentication::try_middleware(secret, "x-hmac").is_ok());
+
+    let config = Config { min_secret_len: Some(8), ..Config::default() };
+    assert!(Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+                .is_err());
+}
+
+#[cfg(feature = "mlock")]
+#[test]
+fn secrets_are_locked_into_memory() {
+    use iron_hmac::SecretKey;
+
+    let keys: Vec<SecretKey> = (0..64).map(|_| SecretKey::generate(32).unwrap()).collect();
+    let copies = keys.clone();
+    drop(keys);
+
+    assert!(iron_hmac::secrets_locked());
+    assert!(copies.iter().all(|key| key.len() == 32));
+}
+
+#[test]
+fn custom_canonicalizer_is_used() {
+    use iron_hmac::{Canonicalizer, Error};
+
+    /// Signs `METHOD path` as one string
+    #[derive(Clone)]
+    struct RequestLine;
+
+    impl Canonicalizer for RequestLine {
+        fn request_mac<F>(&self, req: &mut Request, _config: &Config, mac: F)
+            -> Result<Vec<u8>, Error>
+            where F: Fn(&[u8]) -> Vec<u8>
+        {
+            let line = format!("{} /{}", req.method, req.url.path().join("/"));
+            Ok(mac(line.as_bytes()))
+        }
+    }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::middleware("rust :)", "x-hmac");
+    let hmac_before = hmac_before.with_canonicalizer(RequestLine);
+    let hmac_after = hmac_after.with_canonicalizer(RequestLine);
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "a31f9196b5338b88db4fc1a2cd50df4829ba7392a5bf8bc2a278fa102037e0a1";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+}
+
+#[test]
+fn correct_sha512_hmac_is_ok() {
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::with_algorithm(Algorithm::Sha512, "rust :)", "x-hmac");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let expected_response_hmac =
+            "4d32a230864a7cb25d9d1da022b3fdda175fc69c3ce4b49e8e93d31e9b3d8b47\
+             e04c2a9d015362602c73f3ebaa9e5d65767fb1d0eaaf921c090bf600d11c2f39";
+
+        let request_hmac =
+            "bd05b14dcc2aa6a9159fe687f16a01628ab368560f7c804a0f7c5cfb1c84480d\
+             0d3bad3de4ef21d12e6637583ea3c8bb404471cee5de31a18b97f92f7ed3dd4d";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        let actual_hmac = std::str::from_utf8(&actual_response_hmac[..]).unwrap();
+        assert_eq!(&actual_hmac[..], &expected_response_hmac[..]);
+    }
+}
+
+#[cfg(feature = "hmac-sha3")]
+#[test]
+fn correct_sha3_256_hmac_is_ok() {
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::with_algorithm(Algorithm::Sha3_256, "rust :)", "x-hmac");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let expected_response_hmac =
+            "bb089d7d49723c0a57e562b944091e06d1cbdcf816c30bb1c2c78ec08a8b0e97";
+
+        let request_hmac = "979a42e13818b8c769924758b562aa2bd3449a252f78638923ce636dba0bcfd5";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        let actual_hmac = std::str::from_utf8(&actual_response_hmac[..]).unwrap();
+        assert_eq!(&actual_hmac[..], &expected_response_hmac[..]);
+    }
+}
+
+#[cfg(feature = "hmac-blake2b")]
+#[test]
+fn correct_blake2b_keyed_hash_is_ok() {
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::with_algorithm(Algorithm::Blake2b, "rust :)", "x-hmac");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let expected_response_hmac =
+            "470873f0a524498e4e66f7e21492637fc42cdc942f6a91f08a37f7f18471e05f\
+             99bc3c19386086a5521a0d0676210f13e7c4388c59b74060f0d8647083e79598";
+
+        let request_hmac =
+            "c0ceeff60072860ed7931fe8c82b3ff59b65d210650e724aa2201fc8497b9f4d\
+             f59fc3ee3d061214dd2d675a5d66c26632bc9b24712b97fac1eb6bf876e18af0";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        let actual_hmac = std::str::from_utf8(&actual_response_hmac[..]).unwrap();
+        assert_eq!(&actual_hmac[..], &expected_response_hmac[..]);
+    }
+}
+
+#[cfg(feature = "hmac-blake3")]
+#[test]
+fn blake3_keyed_hash_matches_test_vectors() {
+    use iron_hmac::{Blake3, HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    // From the BLAKE3 test vectors, whose inputs repeat the bytes 0 to 250
+    let key = SecretKey::new(b"whats the Elvish word for friend");
+    let input: Vec<u8> = (0..1024).map(|i| (i % 251) as u8).collect();
+    let vectors = [
+        (0, "92b2b75604ed3c761f9d6f62392c8a9227ad0ea3f09573e783f1498a4ed60d26"),
+        (1, "6d7878dfff2f485635d39013278ae14f1454b8c0a3a2d34bc1ab38228a80c95b"),
+        (1024, "75c46f6f3d9eb4f55ecaaee480db732e6c2105546f1e675003687c31719c7ba4"),
+    ];
+    for &(len, expected) in &vectors {
+        assert_eq!(Blake3.hmac(&key, &input[..len]).to_hex(), expected);
+    }
+
+    // Keys of any other length are hashed to the 32 bytes of a BLAKE3 key first
+    let long_key = SecretKey::new(b"a secret longer than the 32 bytes of a BLAKE3 key");
+    assert_eq!(Blake3.hmac(&long_key, b"Hello, world!").to_hex(),
+               "8e1baa7ca5f1b15caba2b1f59a84a599f186c045f158dd3e32757ce9f8d8c167");
+}
+
+#[test]
+fn base64_encoding_is_used() {
+    use iron_hmac::SignatureEncoding;
+
+    let config = Config { encoding: SignatureEncoding::Base64, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "+mT+uU8dZJ1DWubc4An/B2f1fA8ghn3eX49nEv6jp74=";
+        let expected_response_hmac = "zMff4k3gN1zEkGdXa2m6TWi+VUyfhvs9rfwFPOhPcaA=";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn base64url_encoding_is_used() {
+    use iron_hmac::SignatureEncoding;
+
+    let config = Config { encoding: SignatureEncoding::Base64Url, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "-mT-uU8dZJ1DWubc4An_B2f1fA8ghn3eX49nEv6jp74";
+        let expected_response_hmac = "zMff4k3gN1zEkGdXa2m6TWi-VUyfhvs9rfwFPOhPcaA";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn upper_hex_encoding_is_used() {
+    use iron_hmac::SignatureEncoding;
+
+    let config = Config { encoding: SignatureEncoding::UpperHex, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmacs = [
+            "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be",
+            "FA64FEB94F1D649D435AE6DCE009FF0767F57C0F20867DDE5F8F6712FEA3A7BE",
+        ];
+        let expected_response_hmac =
+            "CCC7DFE24DE0375CC49067576B69BA4D68BE554C9F86FB3DADFC053CE84F71A0";
+
+        let client = Client::new();
+        for request_hmac in &request_hmacs {
+            let res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_string()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+            let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+            assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+        }
+    }
+}
+
+#[test]
+fn lenient_decoding_accepts_hex_and_base64() {
+    let config = Config { lenient_decoding: true, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmacs = [
+            "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be",
+            "+mT+uU8dZJ1DWubc4An/B2f1fA8ghn3eX49nEv6jp74=",
+            "-mT-uU8dZJ1DWubc4An_B2f1fA8ghn3eX49nEv6jp74",
+        ];
+
+        let client = Client::new();
+        for request_hmac in &request_hmacs {
+            let res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_string()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+    }
+}
+
+#[test]
+fn fallback_hmac_header_is_accepted() {
+    use iron_hmac::HeaderConflict;
+
+    header! { (XSignature, "x-signature") => [String] }
+
+    let config = Config {
+        fallback_hmac_headers: vec!["x-signature".to_owned()],
+        header_conflict: HeaderConflict::Reject,
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XSignature(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XSignature(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[test]
+fn duplicate_hmac_headers_follow_policy() {
+    use iron_hmac::DuplicateHeaders;
+
+    let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+    let bogus_hmac = "0000000000000000000000000000000000000000000000000000000000000000";
+    let duplicates = |first: &str, second: &str| {
+        let mut headers = hyper::Headers::new();
+        headers.append_raw("x-hmac", first.as_bytes().to_vec());
+        headers.append_raw("x-hmac", second.as_bytes().to_vec());
+        headers
+    };
+
+    let policies = [
+        (DuplicateHeaders::Reject, hyper::StatusCode::BadRequest),
+        (DuplicateHeaders::TryAny, hyper::StatusCode::Ok),
+        (DuplicateHeaders::RequireAll, hyper::StatusCode::Forbidden),
+    ];
+    for &(policy, expected) in &policies {
+        let config = Config { duplicate_headers: policy, ..test_config() };
+        let (hmac_before, hmac_after) =
+            Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+                .unwrap();
+        let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .headers(duplicates(bogus_hmac, request_hmac))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), expected);
+    }
+}
+
+#[test]
+fn duplicate_key_id_headers_follow_policy() {
+    use iron_hmac::{Algorithm, DuplicateHeaders, Key};
+
+    let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+    let key_ids = |first: &str, second: &str| {
+        let mut headers = hyper::Headers::new();
+        headers.set(XHmac(request_hmac.to_owned()));
+        headers.append_raw("x-hmac-key-id", first.as_bytes().to_vec());
+        headers.append_raw("x-hmac-key-id", second.as_bytes().to_vec());
+        headers
+    };
+
+    let cases = [
+        (DuplicateHeaders::Reject, "partner", hyper::StatusCode::BadRequest),
+        (DuplicateHeaders::TryAny, "other", hyper::StatusCode::BadRequest),
+        (DuplicateHeaders::TryAny, "partner", hyper::StatusCode::Ok),
+    ];
+    for &(policy, second, expected) in &cases {
+        let mut config = Config {
+            key_id_header: Some("x-hmac-key-id".to_owned()),
+            duplicate_headers: policy,
+            ..test_config()
+        };
+        config.keys.insert("partner".to_owned(), Key::new("rust :)", Algorithm::Sha256));
+        config.keys.insert("other".to_owned(), Key::new("other secret", Algorithm::Sha256));
+        let (hmac_before, hmac_after) =
+            Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+                .unwrap();
+        let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .headers(key_ids("partner", second))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), expected);
+    }
+}
+
+#[test]
+fn responses_are_signed_with_response_secret() {
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let config = Config {
+        response_secret: Some(SecretKey::new(b"partner issued")),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let expected_response_hmac =
+            Sha256::default().hmac(&SecretKey::new(b"partner issued"), b"Hello, world!").to_hex();
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn response_hmac_header_is_used() {
+    let config = Config {
+        response_hmac_header: Some("x-response-hmac".to_owned()),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let expected_response_hmac =
+            "ccc7dfe24de0375cc49067576b69ba4d68be554c9f86fb3dadfc053ce84f71a0";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        assert!(res.headers().get_raw("x-hmac").is_none());
+        let actual_response_hmac = &res.headers().get_raw("x-response-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn key_fingerprint_header_is_set() {
+    use iron_hmac::{Algorithm, Key, SecretKey};
+
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        fingerprint_header: Some("x-hmac-key-fingerprint".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("rust :)", Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let secret = SecretKey::new(b"rust :)");
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        let fingerprint = &res.headers().get_raw("x-hmac-key-fingerprint").unwrap()[0];
+        assert_eq!(&fingerprint[..], secret.fingerprint(Some("partner")).0.as_bytes());
+        assert_eq!(fingerprint.len(), 16);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        let fingerprint = &res.headers().get_raw("x-hmac-key-fingerprint").unwrap()[0];
+        assert_eq!(&fingerprint[..], secret.fingerprint(None).0.as_bytes());
+        assert!(secret.fingerprint(None) != secret.fingerprint(Some("partner")));
+    }
+}
+
+#[test]
+fn builder_options_are_used() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use iron_hmac::AuditEvent;
+
+    let audited = Arc::new(AtomicUsize::new(0));
+    let counter = audited.clone();
+    let (hmac_before, hmac_after) = Hmac256Authentication::builder("rust :)")
+        .config(test_config())
+        .response_hmac_header("x-response-hmac")
+        .audit(move |_: &AuditEvent| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .finish()
+        .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let expected_response_hmac =
+            "ccc7dfe24de0375cc49067576b69ba4d68be554c9f86fb3dadfc053ce84f71a0";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        let actual_response_hmac = &res.headers().get_raw("x-response-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+
+    assert_eq!(audited.load(Ordering::SeqCst), 1);
+    assert!(Hmac256Authentication::builder("rust :)").finish().is_err());
+}
+
+#[test]
+fn signature_prefix_is_used() {
+    let config = Config { signature_prefix: Some("sha256=".to_owned()), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac =
+            "sha256=fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let expected_response_hmac =
+            "sha256=ccc7dfe24de0375cc49067576b69ba4d68be554c9f86fb3dadfc053ce84f71a0";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert_eq!(&actual_response_hmac[..], expected_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn truncated_hmac_is_ok() {
+    let config = Config { truncate: Some(16), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac("fa64feb94f1d649d435ae6dce009ff07".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        let actual_hmac = std::str::from_utf8(&actual_response_hmac[..]).unwrap();
+        assert_eq!(actual_hmac, "ccc7dfe24de0375cc49067576b69ba4d");
+
+        // The full length MAC no longer matches
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn short_truncation_is_rejected() {
+    let config = Config { truncate: Some(8), ..test_config() };
+    let result =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn fips_requires_openssl_backend() {
+    let config = Config { fips: true, ..test_config() };
+    let result =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn declared_algorithm_is_used() {
+    let config = Config {
+        algorithm_header: Some("x-hmac-alg".to_owned()),
+        allowed_algorithms: vec![Algorithm::Sha256, Algorithm::Sha512],
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac =
+            "bd05b14dcc2aa6a9159fe687f16a01628ab368560f7c804a0f7c5cfb1c84480d\
+             0d3bad3de4ef21d12e6637583ea3c8bb404471cee5de31a18b97f92f7ed3dd4d";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacAlg("hmac-sha512".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_alg = &res.headers().get_raw("x-hmac-alg").unwrap()[0];
+        assert_eq!(&actual_alg[..], b"hmac-sha512");
+
+        // Requests without the header use the middleware's digest
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+}
+
+#[test]
+fn canonical_request_is_explained() {
+    use iron_hmac::{CanonicalRequest, HmacDigest, QuerySigning, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let secret: SecretKey = "rust :)".into();
+    let config = Config { query: QuerySigning::Raw, ..test_config() };
+    let explanation = CanonicalRequest::new("GET", "/?amount=10", "")
+        .explain(&config, |data| Sha256::default().hmac(&secret, data));
+
+    let names: Vec<_> = explanation.components.iter().map(|component| component.name).collect();
+    assert_eq!(names, ["method", "target", "body"]);
+    assert_eq!(explanation.components[1].value, "/?amount=10");
+    assert_eq!(explanation.mac.to_hex(),
+               "1335f9d958ad8364ca8da0e0fef66def6f23a5260595e76f6830ac29a5f3119c");
+}
+
+#[test]
+fn raw_query_is_signed() {
+    use iron_hmac::QuerySigning;
+
+    let config = Config { query: QuerySigning::Raw, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "1335f9d958ad8364ca8da0e0fef66def6f23a5260595e76f6830ac29a5f3119c";
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?amount=10", url)[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?amount=10000", url)[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn sorted_query_is_signed() {
+    use iron_hmac::QuerySigning;
+
+    let config = Config { query: QuerySigning::Sorted, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // Signed as /?a=1&b=x%20y&b=z
+        let request_hmac = "7f2cd2a1e6c5430e594724b5eb7cf83fcfd4d30c8915ccd532986325c085b6ea";
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?b=z&a=1&b=x+y", url)[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?b=x+y&a=1&b=y", url)[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn trailing_slash_is_normalized() {
+    use iron_hmac::{Normalization, TrailingSlash};
+
+    let config = Config {
+        normalization: Normalization {
+            trailing_slash: TrailingSlash::Strip,
+            ..Normalization::default()
+        },
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // Signed as /hello
+        let request_hmac = "01b4672b3a91d753088933e9bdb5eb8b12aa3b26f2121d452a673851e854444d";
+
+        let client = Client::new();
+        for path in &["/hello", "/hello/"] {
+            let res = client.get(&format!("{}{}", url, path)[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+    }
+}
+
+#[test]
+fn percent_encoding_is_normalized() {
+    use iron_hmac::Normalization;
+
+    let config = Config {
+        normalization: Normalization { percent_encoding: true, ..Normalization::default() },
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // Signed as /hello
+        let request_hmac = "01b4672b3a91d753088933e9bdb5eb8b12aa3b26f2121d452a673851e854444d";
+
+        let client = Client::new();
+        for path in &["/hello", "/h%65llo", "/%68%65%6c%6Co"] {
+            let res = client.get(&format!("{}{}", url, path)[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+    }
+}
+
+#[test]
+fn method_case_is_normalized() {
+    use iron_hmac::{MethodCase, Normalization};
+
+    let config = Config {
+        normalization: Normalization { method_case: MethodCase::Lower, ..Normalization::default() },
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // Signed as get /
+        let request_hmac = "3296c628d9eb1ddf808b2a3e000637a2bc98bf7bc750fd2ef93a0b5a95c58c52";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+}
+
+#[test]
+fn legacy_canonicalization_is_accepted() {
+    use iron_hmac::{MatchedCanonicalization, QuerySigning};
+
+    let config = Config {
+        query: QuerySigning::Raw,
+        legacy: Some(Box::new(test_config())),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+
+    let mut chain = Chain::new(|req: &mut Request| {
+        let matched = req.extensions.get::<MatchedCanonicalization>().unwrap();
+        Ok(Response::with((iron::status::Ok, format!("{:?}", matched))))
+    });
+    chain.link_before(persistent::Read::<bodyparser::MaxBodyLength>::one(1024 * 1024 * 10));
+    chain.link_before(hmac_before);
+    chain.link_after(hmac_after);
+    let server = Iron::new(chain).http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.socket);
+    let _close_guard = CloseGuard(server);
+    {
+        // Signed as /?amount=10 and as / respectively
+        let signatures = [
+            ("1335f9d958ad8364ca8da0e0fef66def6f23a5260595e76f6830ac29a5f3119c", "Primary"),
+            ("fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be", "Legacy"),
+        ];
+
+        let client = Client::new();
+        for &(request_hmac, expected) in &signatures {
+            let mut res = client.get(&format!("{}/?amount=10", url)[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+            let mut body = String::new();
+            res.read_to_string(&mut body).unwrap();
+            assert_eq!(body, expected);
+        }
+
+        let res = client.get(&format!("{}/?amount=10", url)[..])
+                            .header(XHmac("00".repeat(32)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn declared_version_is_used() {
+    use iron_hmac::{QuerySigning, Version};
+
+    let mut config = test_config();
+    let v2 = Config { query: QuerySigning::Raw, ..test_config() };
+    config.versions.insert("v2".to_owned(), Version::new(Some(Algorithm::Sha512), v2));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // HMAC-SHA512 of /?amount=10
+        let v2_hmac = "d50d33526e4abd87e13eff3f1439a3bb0045ef41c6d721e49e0b90c73657905518f2b0cf0b\
+                       07625cfc936130bac177b9c6eb6759fe9e49d56f7c56bab2afffae";
+        // HMAC-SHA256 of /
+        let v1_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        for request_hmac in &[format!("v2={}", v2_hmac), v1_hmac.to_owned()] {
+            let res = client.get(&format!("{}/?amount=10", url)[..])
+                                .header(XHmac(request_hmac.clone()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+
+        let res = client.get(&format!("{}/?amount=10", url)[..])
+                            .header(XHmac(format!("v3={}", v2_hmac)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[test]
+fn content_digest_header_replaces_body() {
+    use iron_hmac::BodySigning;
+
+    header! { (ContentDigest, "content-digest") => [String] }
+
+    let config = Config { body: BodySigning::DigestHeader, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let digest = "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:";
+        let request_hmac = "ce17e9fae7d442d90ff844000d4861366e79132673850edbca0c185707fa4247";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(ContentDigest(digest.to_owned()))
+                            .body("{\"hello\": \"world\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .body("{\"hello\": \"world\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+#[cfg(feature = "content-digest")]
+fn content_digest_is_verified() {
+    use iron_hmac::BodySigning;
+
+    header! { (ContentDigest, "content-digest") => [String] }
+
+    let config = Config { body: BodySigning::VerifiedDigest, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let digest = "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:";
+        let request_hmac = "ce17e9fae7d442d90ff844000d4861366e79132673850edbca0c185707fa4247";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(ContentDigest(digest.to_owned()))
+                            .body("{\"hello\": \"world\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(ContentDigest(digest.to_owned()))
+                            .body("{\"hello\": \"there\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn signed_headers_are_covered() {
+    header! { (XRequestId, "x-request-id") => [String] }
+
+    let config = Config {
+        signed_headers: vec!["Content-Type".to_owned(), "x-request-id".to_owned()],
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "95aa507b662f190472ee2db8188e61d303dbd3a92413a0b3c2c129027ea7c83e";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(hyper::header::ContentType::json())
+                            .header(XRequestId("abc123".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(hyper::header::ContentType::json())
+                            .header(XRequestId("def456".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn content_headers_are_signed() {
+    let config = Config { sign_content: true, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "5982528f10edd94104c5f89b7fba30fc4af575a330f6d2041caa9b40dae91388";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(hyper::header::ContentType::json())
+                            .body("{\"amount\":10}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(hyper::header::ContentType::plaintext())
+                            .body("{\"amount\":10}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn host_is_signed() {
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let config = Config { sign_host: true, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "rust :)".into();
+        let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+        let request_hmac = |authority: &str| {
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", authority] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(url.trim_start_matches("http://"))))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac("api.example.com")))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn scheme_and_port_are_signed() {
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let config = Config { sign_scheme_and_port: true, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "rust :)".into();
+        let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+        let request_hmac = |scheme_and_port: &str| {
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", scheme_and_port] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+        let port = url.rsplit(':').next().unwrap();
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&format!("http:{}", port))))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac("https:443")))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn client_addr_is_signed() {
+    use iron_hmac::ForwardedFor;
+    use std::sync::Arc;
+
+    header! { (XForwardedFor, "x-forwarded-for") => [String] }
+
+    let config = Config {
+        client_addr: Some(Arc::new(ForwardedFor::new(1))),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        // Signed for 10.0.0.1
+        let request_hmac = "b1034d48295c8dda7bbbbd7221ffa1e812e8375eac6867a5bac339f9e6231671";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XForwardedFor("10.0.0.9, 10.0.0.1".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XForwardedFor("10.0.0.1, 10.0.0.2".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn stale_timestamp_is_forbidden() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    header! { (XHmacTimestamp, "x-hmac-timestamp") => [String] }
+
+    let config = Config {
+        timestamp_header: Some("x-hmac-timestamp".to_owned()),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "rust :)".into();
+        let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+        let request_hmac = |timestamp: &str| {
+            let headers = format!("x-hmac-timestamp:{}\n", timestamp);
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", &headers[..]] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        let fresh = now.to_string();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&fresh)))
+                            .header(XHmacTimestamp(fresh.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let stale = (now - 600).to_string();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&stale)))
+                            .header(XHmacTimestamp(stale.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        // The timestamp is covered by the HMAC
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&stale)))
+                            .header(XHmacTimestamp(fresh.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        // Only one timestamp is accepted, even when all of them are signed
+        let mut headers = hyper::Headers::new();
+        headers.set(XHmac(request_hmac(&format!("{}, {}", fresh, stale))));
+        headers.append_raw("x-hmac-timestamp", fresh.into_bytes());
+        headers.append_raw("x-hmac-timestamp", stale.into_bytes());
+        let res = client.get(&url[..])
+                            .headers(headers)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[test]
+fn expired_request_is_forbidden() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    header! { (XHmacExpires, "x-hmac-expires") => [String] }
+
+    let config = Config {
+        expires_header: Some("x-hmac-expires".to_owned()),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "rust :)".into();
+        let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+        let request_hmac = |expires: &str| {
+            let headers = format!("x-hmac-expires:{}\n", expires);
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", &headers[..]] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        // Minted a day ahead of use
+        let future = (now + 86400).to_string();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&future)))
+                            .header(XHmacExpires(future.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let past = (now - 1).to_string();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&past)))
+                            .header(XHmacExpires(past))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn signed_url_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::SignedUrls;
+
+    let urls = SignedUrls::new(Sha256::default(), "rust :)");
+    let mut chain = Chain::new(|_: &mut Request| {
+        Ok(Response::with((iron::status::Ok, "Hello, world!")))
+    });
+    chain.link_before(urls.clone());
+    let server = Iron::new(chain).http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/report.pdf?user=7", server.socket);
+    let _close_guard = CloseGuard(server);
+    {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let client = Client::new();
+
+        let signed = urls.sign(&url[..], now + 60).unwrap();
+        let res = client.get(&signed[..]).send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let tampered = signed.replace("user=7", "user=8");
+        let res = client.get(&tampered[..]).send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let expired = urls.sign(&url[..], now - 1).unwrap();
+        let res = client.get(&expired[..]).send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let res = client.get(&url[..]).send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn stale_date_is_forbidden() {
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    header! { (XDate, "Date") => [String] }
+
+    let config = Config { require_date: true, ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "rust :)".into();
+        let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+        let request_hmac = |date: &str| {
+            let headers = format!("date:{}\n", date);
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", &headers[..]] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+
+        let client = Client::new();
+        let now = format!("{}", hyper::header::HttpDate::from(std::time::SystemTime::now()));
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(&now)))
+                            .header(XDate(now.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let stale = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac(stale)))
+                            .header(XDate(stale.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn reused_nonce_is_forbidden() {
+    header! { (XHmacNonce, "x-hmac-nonce") => [String] }
+
+    let mut config = Config {
+        nonce_header: Some("x-hmac-nonce".to_owned()),
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "3a0cb5ea9f649c6d650e2a60de7341e234a7d946f49875ede28f2d1f1ffc13a3";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        // Nonces are remembered per key
+        let request_hmac = "9be70dcb9e4e79213baf05a1db5a5979c1d840619287d5289efc3500404e4acf";
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn idempotency_key_is_signed_and_exposed() {
+    use iron_hmac::IdempotencyKey;
+
+    header! { (IdempotencyKeyHeader, "idempotency-key") => [String] }
+
+    let config = Config {
+        idempotency_key_header: Some("idempotency-key".to_owned()),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+
+    let mut chain = Chain::new(|req: &mut Request| {
+        let key = req.extensions.get::<IdempotencyKey>().unwrap().clone();
+        Ok(Response::with((iron::status::Ok, key)))
+    });
+    chain.link_before(persistent::Read::<bodyparser::MaxBodyLength>::one(1024 * 1024 * 10));
+    chain.link_before(hmac_before);
+    chain.link_after(hmac_after);
+    let server = Iron::new(chain).http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.socket);
+    let _close_guard = CloseGuard(server);
+    {
+        let request_hmac = "988cb585e53c99e81dd094a274ae307df94b0a3d11ebc6e1689e937bd6a7faca";
+
+        let client = Client::new();
+        let mut res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(IdempotencyKeyHeader("retry-1".to_owned()))
+                            .body("order=1")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "retry-1");
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(IdempotencyKeyHeader("retry-2".to_owned()))
+                            .body("order=1")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn memory_replay_cache_is_bounded() {
+    use std::time::Duration;
+    use iron_hmac::{MemoryReplayCache, ReplayCache};
+
+    let cache = MemoryReplayCache::new(2);
+    let ttl = Duration::from_secs(60);
+
+    assert!(cache.insert_if_absent("a", ttl).unwrap());
+    assert!(!cache.insert_if_absent("a", ttl).unwrap());
+    assert!(cache.insert_if_absent("b", ttl).unwrap());
+
+    // "a" is forgotten to make room
+    assert!(cache.insert_if_absent("c", ttl).unwrap());
+    assert!(cache.insert_if_absent("a", ttl).unwrap());
+    assert!(!cache.insert_if_absent("c", ttl).unwrap());
+
+    assert!(cache.insert_if_absent("d", Duration::from_secs(0)).unwrap());
+    assert!(cache.insert_if_absent("d", ttl).unwrap());
+}
+
+#[test]
+fn bloom_replay_cache_detects_duplicates() {
+    use std::time::Duration;
+    use iron_hmac::{BloomReplayCache, ReplayCache};
+
+    let cache = BloomReplayCache::new(1000, 0.001, Duration::from_secs(60));
+    let ttl = Duration::from_secs(60);
+
+    // A few false positives are expected
+    let new = (0..1000).filter(|i| cache.insert_if_absent(&format!("nonce-{}", i), ttl).unwrap())
+                       .count();
+    assert!(new > 990);
+
+    for i in 0..1000 {
+        assert!(!cache.insert_if_absent(&format!("nonce-{}", i), ttl).unwrap());
+    }
+}
+
+#[cfg(feature = "replay-redis")]
+#[test]
+fn redis_replay_cache_rejects_replays() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use iron_hmac::{RedisReplayCache, ReplayCache};
+
+    // Needs a Redis server, e.g. REDIS_URL=redis://127.0.0.1/
+    let url = match std::env::var("REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => return
+    };
+
+    // Keys of earlier runs may not have expired yet
+    let run = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let cache = RedisReplayCache::new(&url).unwrap()
+                                           .with_prefix(format!("iron-hmac-test:{}:", run));
+    let ttl = Duration::from_secs(60);
+
+    assert!(cache.insert_if_absent("nonce", ttl).unwrap());
+    assert!(!cache.insert_if_absent("nonce", ttl).unwrap());
+    assert!(cache.insert_if_absent("other nonce", ttl).unwrap());
+
+    // Keys are forgotten once their TTL has passed
+    assert!(cache.insert_if_absent("short-lived", Duration::from_millis(50)).unwrap());
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(cache.insert_if_absent("short-lived", ttl).unwrap());
+
+    // Concurrent calls each use a connection of their own, and only one inserts the key
+    let cache = std::sync::Arc::new(cache);
+    let threads: Vec<_> = (0..8).map(|_| {
+        let cache = cache.clone();
+        std::thread::spawn(move || cache.insert_if_absent("concurrent", ttl).unwrap())
+    }).collect();
+    let inserted: Vec<bool> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+    assert_eq!(inserted.iter().filter(|&&new| new).count(), 1);
+}
+
+#[test]
+fn key_id_selects_secret_and_algorithm() {
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha512));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac =
+            "19177808c2701ba7d6201ef8ddf56e3182266ce430dcb6de7ce4136631e5ed89\
+             0a618dddf4bd85c4d2421a028b62d20551f2a88810ae2c869af22f939d0cd583";
+        let expected_hmac =
+            b"727ec55e8423b559b2229daaea5af0c7ceb2de3257962cc126ad37107f124ed1\
+              a3552376d112daef84df6705e6d74dc742fe0a977496f52cfafda24f18e5ef8a";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_hmac = &res.headers().get_raw(HMAC_HEADER_NAME).unwrap()[0];
+        assert_eq!(&actual_hmac[..], &expected_hmac[..]);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("unknown".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn secret_store_keys_can_be_revoked() {
+    use std::sync::Arc;
+    use iron_hmac::MemorySecretStore;
+
+    let store = Arc::new(MemorySecretStore::new());
+    store.insert("partner", Key::new("partner", Algorithm::Sha512));
+    let config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        secret_store: Some(store.clone()),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac =
+            "19177808c2701ba7d6201ef8ddf56e3182266ce430dcb6de7ce4136631e5ed89\
+             0a618dddf4bd85c4d2421a028b62d20551f2a88810ae2c869af22f939d0cd583";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        assert!(store.revoke("partner"));
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn secret_provider_supplies_secret() {
+    use std::sync::Arc;
+    use iron_hmac::{SecretKey, SecretProvider};
+
+    struct TenantSecrets;
+
+    impl SecretProvider for TenantSecrets {
+        fn secret_for(&self, _req: &Request, key_id: Option<&str>) -> Option<SecretKey> {
+            match key_id {
+                Some("tenant") => Some("rust :)".into()),
+                _ => None
+            }
+        }
+    }
+
+    let config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        secret_provider: Some(Arc::new(TenantSecrets)),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("tenant".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("other".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn previous_secrets_are_accepted() {
+    let config = Config { previous_secrets: vec!["rust :)".into()], ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "new secret", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let old_response_hmac = "ccc7dfe24de0375cc49067576b69ba4d68be554c9f86fb3dadfc053ce84f71a0";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_hmac = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert!(&actual_response_hmac[..] != old_response_hmac.as_bytes());
+    }
+}
+
+#[test]
+fn retired_key_is_forbidden() {
+    use std::time::{Duration, SystemTime};
+
+    let retired = SystemTime::now() - Duration::from_secs(60);
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(),
+                       Key::new("partner", Algorithm::Sha512).valid_until(retired));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac =
+            "19177808c2701ba7d6201ef8ddf56e3182266ce430dcb6de7ce4136631e5ed89\
+             0a618dddf4bd85c4d2421a028b62d20551f2a88810ae2c869af22f939d0cd583";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn caching_secret_provider_caches_secrets() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use iron_hmac::{CachingSecretProvider, SecretKey, SecretProvider};
+
+    struct CountingProvider(Arc<AtomicUsize>);
+
+    impl SecretProvider for CountingProvider {
+        fn secret_for(&self, _req: &Request, key_id: Option<&str>) -> Option<SecretKey> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match key_id {
+                Some(_) => None,
+                None => Some(SecretKey::new(b"rust :)"))
+            }
+        }
+    }
+
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let provider = CachingSecretProvider::new(CountingProvider(lookups.clone()));
+    let config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        secret_provider: Some(Arc::new(provider)),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        for _ in 0..3 {
+            let res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .send().unwrap();
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+        for _ in 0..3 {
+            let res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .header(XHmacKeyId("unknown".to_owned()))
+                                .send().unwrap();
+            assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+        }
+    }
+
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "secrets-sqlite")]
+#[test]
+fn sqlite_secret_provider_skips_disabled_and_expired_keys() {
+    use std::sync::Arc;
+    use iron_hmac::SqliteSecretProvider;
+
+    let path = std::env::temp_dir().join("iron-hmac-sqlite-test.db");
+    let _ = std::fs::remove_file(&path);
+    {
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.execute_batch("
+            CREATE TABLE hmac_keys (key_id TEXT PRIMARY KEY, secret BLOB NOT NULL,
+                                    enabled INTEGER NOT NULL DEFAULT 1, expires_at INTEGER);
+            INSERT INTO hmac_keys VALUES ('partner', CAST('rust :)' AS BLOB), 1, NULL);
+            INSERT INTO hmac_keys VALUES ('disabled', CAST('rust :)' AS BLOB), 0, NULL);
+            INSERT INTO hmac_keys VALUES ('expired', CAST('rust :)' AS BLOB), 1, 1);
+        ").unwrap();
+    }
+
+    let provider = SqliteSecretProvider::open(&path).unwrap().default_key("partner");
+    let config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        secret_provider: Some(Arc::new(provider)),
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        for &(key_id, status) in &[("partner", hyper::StatusCode::Ok),
+                                   ("disabled", hyper::StatusCode::Forbidden),
+                                   ("expired", hyper::StatusCode::Forbidden),
+                                   ("unknown", hyper::StatusCode::Forbidden)] {
+            let res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .header(XHmacKeyId(key_id.to_owned()))
+                                .send().unwrap();
+            assert_eq!(res.status(), status);
+        }
+    }
+
+    let empty = rusqlite::Connection::open_in_memory().unwrap();
+    assert!(SqliteSecretProvider::new(empty).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn secrets_are_chosen_by_route() {
+    use std::sync::Arc;
+    use iron_hmac::{HmacDigest, RouteSecretProvider, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let routes = RouteSecretProvider::new()
+        .route("/webhooks/github", "github secret")
+        .route("/webhooks", "webhook secret");
+    let config = Config { secret_provider: Some(Arc::new(routes)), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = |secret: &str, path: &str| {
+            let secret = SecretKey::new(secret.as_bytes());
+            let hmac = |data: &[u8]| Sha256::default().hmac(&secret, data);
+            let mut merged = Vec::new();
+            for part in &["GET", path, ""] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            hmac(&merged[..]).to_hex()
+        };
+
+        let client = Client::new();
+        let status = |path: &str, secret: &str| {
+            client.get(&format!("{}{}", url, path)[..])
+                  .header(XHmac(request_hmac(secret, path)))
+                  .send().unwrap()
+                  .status()
+        };
+
+        assert_eq!(status("/webhooks/github", "github secret"), hyper::StatusCode::Ok);
+        assert_eq!(status("/webhooks/gitlab", "webhook secret"), hyper::StatusCode::Ok);
+        assert_eq!(status("/webhooks/gitlab", "github secret"), hyper::StatusCode::Forbidden);
+        assert_eq!(status("/webhooks/githubx", "webhook secret"), hyper::StatusCode::Ok);
+        assert_eq!(status("/api", "webhook secret"), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn secrets_are_chosen_by_tenant() {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use iron_hmac::{SecretKey, TenantSecretProvider, TenantSource};
+
+    header! { (XTenantId, "x-tenant-id") => [String] }
+
+    let mut secrets = HashMap::new();
+    secrets.insert("acme".to_owned(), SecretKey::new(b"rust :)"));
+    secrets.insert("globex".to_owned(), SecretKey::new(b"globex secret"));
+    let tenants = TenantSecretProvider::new(TenantSource::Header("x-tenant-id".to_owned()),
+                                            secrets);
+    let config = Config { secret_provider: Some(Arc::new(tenants)), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let status = |tenant: Option<&str>| {
+            let mut req = client.get(&url[..]).header(XHmac(request_hmac.to_owned()));
+            if let Some(tenant) = tenant {
+                req = req.header(XTenantId(tenant.to_owned()));
+            }
+            req.send().unwrap().status()
+        };
+
+        assert_eq!(status(Some("acme")), hyper::StatusCode::Ok);
+        assert_eq!(status(Some("globex")), hyper::StatusCode::Forbidden);
+        assert_eq!(status(Some("initech")), hyper::StatusCode::Forbidden);
+        assert_eq!(status(None), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn verified_key_scopes_are_exposed_to_handlers() {
+    use iron_hmac::VerifiedKey;
+
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        ..test_config()
+    };
+    let key = Key::new("rust :)", Algorithm::Sha256).with_scope("read")
+                                                    .with_metadata("account", "42");
+    config.keys.insert("reader".to_owned(), key);
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+
+    let mut chain = Chain::new(|req: &mut Request| {
+        let key = req.extensions.get::<VerifiedKey>().unwrap();
+        let body = format!("{} {} {}", key.id, key.has_scope("read"), key.metadata["account"]);
+        Ok(Response::with((iron::status::Ok, body)))
+    });
+    chain.link_before(persistent::Read::<bodyparser::MaxBodyLength>::one(1024 * 1024 * 10));
+    chain.link_before(hmac_before);
+    chain.link_after(hmac_after);
+    let server = CloseGuard(Iron::new(chain).http("127.0.0.1:0").unwrap());
+    let url = format!("http://{}", server.0.socket);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let mut res = client.get(&url[..])
+                                .header(XHmac(request_hmac.to_owned()))
+                                .header(XHmacKeyId("reader".to_owned()))
+                                .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "reader true 42");
+    }
+}
+
+#[test]
+fn secret_is_rotated_through_handle() {
+    let (hmac_before, hmac_after, rotation) =
+        Hmac256Authentication::with_secret_rotation(Sha256::default(), "rust :)", "x-hmac",
+                                                    test_config()).unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let status = || {
+            client.get(&url[..])
+                  .header(XHmac(request_hmac.to_owned()))
+                  .send().unwrap()
+                  .status()
+        };
+
+        assert_eq!(status(), hyper::StatusCode::Ok);
+
+        rotation.set_primary_secret("rotated");
+        assert_eq!(status(), hyper::StatusCode::Forbidden);
+
+        rotation.add_secondary_secret("rust :)");
+        assert_eq!(status(), hyper::StatusCode::Ok);
+
+        assert!(rotation.revoke("rust :)"));
+        assert_eq!(status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn verification_attempts_are_audited() {
+    use std::sync::{Arc, Mutex};
+    use iron_hmac::{AuditEvent, AuditOutcome};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("rust :)", Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let hmac_before = hmac_before.with_audit(move |event: &AuditEvent| {
+        recorded.lock().unwrap().push(event.clone());
+    });
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacKeyId("partner".to_owned()))
+                            .send().unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].key_id, Some("partner".to_owned()));
+    assert_eq!(events[0].outcome, AuditOutcome::Verified);
+    assert_eq!(events[0].method, "GET");
+    assert_eq!(events[0].path, "/");
+    assert_eq!(events[1].key_id, None);
+    match events[1].outcome {
+        AuditOutcome::Rejected(_) => (),
+        ref other => panic!("expected Rejected, got {:?}", other)
+    }
+}
+
+#[test]
+fn secret_file_is_reloaded() {
+    use std::fs::File;
+    use std::io::Write;
+    use std::sync::Arc;
+    use iron_hmac::{FileSecretProvider, SecretKey};
+
+    let path = std::env::temp_dir().join("iron-hmac-secret-file-test");
+    let write_secret = |secret: &str| {
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "# rotated by ops").unwrap();
+        writeln!(file, "{}", SecretKey::new(secret.as_bytes()).to_hex()).unwrap();
+    };
+
+    write_secret("rust :)");
+    let provider = FileSecretProvider::load(&path).unwrap();
+    let config = Config { secret_provider: Some(Arc::new(provider.clone())), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        write_secret("rotated");
+        provider.reload().unwrap();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn secret_file_supplies_every_secret() {
+    use std::fs::File;
+    use std::io::Write;
+    use iron_hmac::SecretKey;
+
+    let path = std::env::temp_dir().join("iron-hmac-with-secret-file-test");
+    {
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}", SecretKey::new(b"rust :)").to_hex()).unwrap();
+    }
+
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_secret_file(Sha256::default(), &path, "x-hmac", test_config())
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "keystore")]
+#[test]
+fn keystore_keys_are_loaded() {
+    use std::fs::File;
+    use std::io::Write;
+    use iron_hmac::{Algorithm, Keystore, SecretKey};
+
+    let path = std::env::temp_dir().join("iron-hmac-keystore-test.toml");
+    {
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "secret = \"hex:{}\"", SecretKey::new(b"rust :)").to_hex()).unwrap();
+        writeln!(file, "[keys.partner]").unwrap();
+        writeln!(file, "secret = \"partner secret\"").unwrap();
+        writeln!(file, "algorithm = \"hmac-sha512\"").unwrap();
+        writeln!(file, "not_after = 1798761600").unwrap();
+        writeln!(file, "scopes = [\"read\"]").unwrap();
+    }
+
+    let keystore = Keystore::load(&path).unwrap();
+    assert_eq!(keystore.keys["partner"].algorithm, Algorithm::Sha512);
+    assert_eq!(keystore.keys["partner"].scopes, vec!["read".to_owned()]);
+    assert!(keystore.keys["partner"].not_after.is_some());
+
+    let json = Keystore::from_json(r#"{"keys": {"partner": {"secret": "base64:cnVzdA=="}}}"#)
+        .unwrap();
+    assert_eq!(json.keys["partner"].algorithm, Algorithm::Sha256);
+    assert!(json.secret.is_none());
+    assert!(Keystore::from_json(r#"{"keys": {"partner": {"secret": "x", "algo": "sha1"}}}"#)
+        .is_err());
+
+    let config = Config { key_id_header: Some("x-hmac-key-id".to_owned()), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_keystore(Sha256::default(), &path, "x-hmac", config).unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn structured_signature_header_is_parsed() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey, Sha512};
+    use rustc_serialize::hex::ToHex;
+
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        timestamp_header: Some("x-hmac-timestamp".to_owned()),
+        structured_signature: true,
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha512));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let secret: SecretKey = "partner".into();
+        let hmac = |data: &[u8]| Sha512::default().hmac(&secret, data);
+        let signature = |timestamp: u64| {
+            let headers = format!("x-hmac-timestamp:{}\n", timestamp);
+            let mut merged = Vec::new();
+            for part in &["GET", "/", "", &headers[..]] {
+                merged.extend_from_slice(&hmac(part.as_bytes())[..]);
+            }
+            format!("keyId=partner,ts={},sig={}", timestamp, hmac(&merged[..]).to_hex())
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(signature(now)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&url[..])
+                            .header(XHmac(signature(now - 600)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let mut headers = hyper::Headers::new();
+        headers.append_raw("x-hmac", signature(now).into_bytes());
+        headers.append_raw("x-hmac", signature(now - 600).into_bytes());
+        let res = client.get(&url[..])
+                            .headers(headers)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[test]
+fn authorization_header_is_read() {
+    header! { (Authorization, "Authorization") => [String] }
+
+    let mut config = Config {
+        key_id_header: Some("x-hmac-key-id".to_owned()),
+        authorization_scheme: Some("HMAC".to_owned()),
+        ..test_config()
+    };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha512));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let default_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+        let partner_hmac = "19177808c2701ba7d6201ef8ddf56e3182266ce430dcb6de7ce4136631e5ed890a\
+                            618dddf4bd85c4d2421a028b62d20551f2a88810ae2c869af22f939d0cd583";
+
+        let client = Client::new();
+        let credentials = [format!("HMAC {}", default_hmac),
+                           format!("HMAC partner:{}", partner_hmac)];
+        for authorization in &credentials {
+            let res = client.get(&url[..])
+                                .header(Authorization(authorization.clone()))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Ok);
+        }
+
+        let res = client.get(&url[..])
+                            .header(Authorization(format!("HMAC partner:{}", default_hmac)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let mut headers = hyper::Headers::new();
+        for authorization in &credentials {
+            headers.append_raw("authorization", authorization.clone().into_bytes());
+        }
+        let res = client.get(&url[..])
+                            .headers(headers)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[cfg(feature = "hmac-sha1")]
+#[test]
+fn oauth1_signature_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, Scheme, SecretKey, Sha1};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Authorization, "Authorization") => [String] }
+
+    let config = Config { scheme: Scheme::OAuth1, ..test_config() };
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::with_config(Sha1, "lti secret", "x-hmac", config).unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let authorization = |nonce: &str, timestamp: u64| {
+            let base_string = format!(
+                "GET&{}%2Fpath&a%3D1%26b%3Dx%2520y%26oauth_consumer_key%3Dconsumer\
+                 %26oauth_nonce%3D{}%26oauth_signature_method%3DHMAC-SHA1\
+                 %26oauth_timestamp%3D{}",
+                url.replace(":", "%3A").replace("/", "%2F"), nonce, timestamp);
+            let key: SecretKey = "lti%20secret&".into();
+            let signature = Sha1.hmac(&key, base_string.as_bytes()).to_base64(STANDARD);
+
+            format!("OAuth realm=\"lti\", oauth_consumer_key=\"consumer\", oauth_nonce=\"{}\", \
+                     oauth_signature_method=\"HMAC-SHA1\", oauth_timestamp=\"{}\", \
+                     oauth_signature=\"{}\"",
+                    nonce, timestamp,
+                    signature.replace("+", "%2B").replace("/", "%2F").replace("=", "%3D"))
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/path?b=x+y&a=1", url)[..])
+                            .header(Authorization(authorization("abc", now)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/path?b=z&a=1", url)[..])
+                            .header(Authorization(authorization("def", now)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        // Replayed nonces and stale timestamps are rejected
+        for &(nonce, timestamp) in &[("abc", now), ("ghi", now - 3600)] {
+            let res = client.get(&format!("{}/path?b=x+y&a=1", url)[..])
+                                .header(Authorization(authorization(nonce, timestamp)))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+        }
+
+        let res = client.get(&format!("{}/path", url)[..])
+                            .header(Authorization("oauth\u{e9}".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn disallowed_algorithm_is_bad_request() {
+    let config = Config {
+        algorithm_header: Some("x-hmac-alg".to_owned()),
+        allowed_algorithms: vec![Algorithm::Sha256],
+        ..test_config()
+    };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .header(XHmacAlg("hmac-sha512".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::BadRequest);
+    }
+}
+
+#[cfg(feature = "ed25519")]
+#[test]
+fn correct_ed25519_signature_is_ok() {
+    use iron_hmac::{Ed25519, Ed25519Authentication};
+    use rustc_serialize::hex::FromHex;
+
+    let public_key = "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8"
+        .from_hex().unwrap();
+    let private_key = (0..32).collect::<Vec<u8>>();
+    let scheme = Ed25519::new(&public_key[..]).unwrap()
+                         .with_signing_key(&private_key[..]).unwrap();
+
+    let (before, after) = Ed25519Authentication::middleware(scheme, "x-hmac");
+    let (_close_guard, url) = build_hello_world(before, after);
+    {
+        let expected_response_signature =
+            "2c62a52f1d5e97458a0e39cf8db654fd49337148727afa7470b4ce69dbb689be\
+             31932095bd35df6f7b06f59197cc65a3494cee4be33de7f0db11645310c8f800";
+
+        let request_signature =
+            "9108ffb49559641183fac8d729dbaceab6256a923e5bb52d4a3fad89241cbf29\
+             7df5c976a606f0801ba4cc3637ae2b82dcec0aa317752f1ac138fa081c233206";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let actual_response_signature = &res.headers().get_raw("x-hmac").unwrap()[0];
+        let actual_signature = std::str::from_utf8(&actual_response_signature[..]).unwrap();
+        assert_eq!(actual_signature, expected_response_signature);
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[cfg(feature = "ed25519")]
+#[test]
+fn ed25519_signature_covers_configured_request_parts() {
+    use iron_hmac::{Ed25519, Ed25519Authentication, QuerySigning, SignatureScheme};
+    use rustc_serialize::hex::{FromHex, ToHex};
+
+    header! { (XHmacNonce, "x-hmac-nonce") => [String] }
+
+    let public_key = "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8"
+        .from_hex().unwrap();
+    let private_key = (0..32).collect::<Vec<u8>>();
+    let signer = Ed25519::new(&public_key[..]).unwrap()
+                         .with_signing_key(&private_key[..]).unwrap();
+
+    let config = Config {
+        query: QuerySigning::Raw,
+        nonce_header: Some("x-hmac-nonce".to_owned()),
+        ..Config::default()
+    };
+    let (before, after) =
+        Ed25519Authentication::with_config(Ed25519::new(&public_key[..]).unwrap(), "x-hmac",
+                                           config).unwrap();
+    let (_close_guard, url) = build_hello_world(before, after);
+    {
+        let length_prefixed = |data: &[u8]| {
+            let mut prefixed = vec![0, 0, 0, 0, 0, 0, 0, data.len() as u8];
+            prefixed.extend_from_slice(data);
+            prefixed
+        };
+        let mut parts = Vec::new();
+        for part in &["GET", "/?a=1", "", "x-hmac-nonce:abc\n"] {
+            parts.extend(length_prefixed(part.as_bytes()));
+        }
+        let request_signature = signer.sign(&length_prefixed(&parts[..])[..]).unwrap().to_hex();
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?a=2", url)[..])
+                            .header(XHmac(request_signature.clone()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        let res = client.get(&format!("{}/?a=1", url)[..])
+                            .header(XHmac(request_signature.clone()))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?a=1", url)[..])
+                            .header(XHmac(request_signature))
+                            .header(XHmacNonce("abc".to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[cfg(feature = "ecdsa-p256")]
+#[test]
+fn correct_ecdsa_p256_signature_is_ok() {
+    use iron_hmac::{EcdsaP256, EcdsaP256Authentication};
+    use rustc_serialize::hex::FromHex;
+
+    let public_key = "039fad84aeae08bbef7f010014d82cef6a09de2b0cf871b5ce0c4f1d13a59a5934"
+        .from_hex().unwrap();
+    let private_key = "0000000000000000000000000000000000000000000000001234567890abcdef"
+        .from_hex().unwrap();
+    let scheme = EcdsaP256::new(&public_key[..]).unwrap()
+                           .with_signing_key(&private_key[..]).unwrap();
+
+    let (before, after) = EcdsaP256Authentication::middleware(scheme, "x-hmac");
+    let (_close_guard, url) = build_hello_world(before, after);
+    {
+        // DER encoded
+        let request_signature =
+            "304602210099a17c40e6a54da59c354fe73d2f76021a428ca2cda63dce7c4ca9168e300dd1\
+             022100b714d02c64493fbf243ebab467cb7929d8694129c4fc361343c639a3e7a4cdcf";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let response_signature = &res.headers().get_raw("x-hmac").unwrap()[0];
+        assert_eq!(response_signature.len(), 128);
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[cfg(feature = "rsa-sha256")]
+#[test]
+fn correct_rsa_sha256_signature_is_ok() {
+    use iron_hmac::RsaSha256;
+
+    let public_key = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQC6y8umK0IKySf6fiCaw8aQkbxI
+F/hkklKz7/KXtCwSqLfUaku+VH5beFj5UNol3cTLf2Wnt/jIPJS6OHa/tHMExYvn
+duw5SpEKCJtyf6ppyBABI8xSDs7FdJcRbsx3sPNxmfl8HoLLLmHDYCLmlGI6MIJR
+vrQ/bdULXJiwBeelFQIDAQAB
+-----END PUBLIC KEY-----";
+
+    let public_key = RsaSha256::from_pem(public_key).unwrap();
+    let (before, after) =
+        Hmac256Authentication::with_public_key(public_key, "x-hmac", Config::default()).unwrap();
+    let (_close_guard, url) = build_hello_world(before, after);
+    {
+        let request_signature =
+            "5c7573f04a59f8dfccadfda8bc09513bf15ab334d423a112f17b9d4fd97765ef\
+             7510239b4c3c96f174c645b7160250cfbbf61663b208f0ff940fbc4bde8d76ae\
+             b7be005fa4efc19931d408c90bdef8c05fdcc2c54abd05138cf09249ea22b6a0\
+             ddcb30e98b1c13b920e95080b6b6d7a3f85a6e5695832374375c02543c74550a";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        assert!(res.headers().get_raw("x-hmac").is_none());
+
+        let res = client.post(&url[..])
+                            .header(XHmac(request_signature.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn http_message_signature_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, Scheme, SecretKey, Sha512};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Signature, "Signature") => [String] }
+    header! { (SignatureInput, "Signature-Input") => [String] }
+
+    let mut config = Config { scheme: Scheme::HttpMessageSignatures, ..test_config() };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha512));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let params = format!("(\"@method\" \"@path\");created={};keyid=\"partner\"", now);
+        let base = format!("\"@method\": GET\n\"@path\": /\n\"@signature-params\": {}", params);
+        let secret: SecretKey = "partner".into();
+        let signature = Sha512::default().hmac(&secret, base.as_bytes()).to_base64(STANDARD);
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(SignatureInput(format!("sig1={}", params)))
+                            .header(Signature(format!("sig1=:{}:", signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/other", url)[..])
+                            .header(SignatureInput(format!("sig1={}", params)))
+                            .header(Signature(format!("sig1=:{}:", signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn draft_cavage_signature_is_verified() {
+    use iron_hmac::{HmacDigest, Scheme, SecretKey, Sha512};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Signature, "Signature") => [String] }
+    header! { (XDate, "x-date") => [String] }
+
+    let mut config = Config { scheme: Scheme::DraftCavage, ..test_config() };
+    config.keys.insert("partner".to_owned(), Key::new("partner", Algorithm::Sha512));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let date = "Tue, 07 Jun 2014 20:51:35 GMT";
+        let secret: SecretKey = "partner".into();
+        let signing_string = format!("(request-target): get /?a=1\nx-date: {}", date);
+        let signature =
+            Sha512::default().hmac(&secret, signing_string.as_bytes()).to_base64(STANDARD);
+        let header = format!("keyId=\"partner\",algorithm=\"hmac-sha512\",\
+                              headers=\"(request-target) x-date\",signature=\"{}\"", signature);
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?a=1", url)[..])
+                            .header(XDate(date.to_owned()))
+                            .header(Signature(header.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?a=2", url)[..])
+                            .header(XDate(date.to_owned()))
+                            .header(Signature(header))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[cfg(feature = "aws-sigv4")]
+#[test]
+fn aws_sigv4_signature_is_verified() {
+    use std::time::Duration;
+    use iron_hmac::{AwsSigV4, HmacDigest, Scheme, SecretKey};
+    use rustc_serialize::hex::ToHex;
+    use sha2::Digest;
+
+    header! { (Authorization, "Authorization") => [String] }
+    header! { (XAmzDate, "x-amz-date") => [String] }
+
+    let secret = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    let mut config = Config {
+        scheme: Scheme::AwsSigV4(AwsSigV4::new("us-east-1", "iam")),
+        timestamp_max_age: Some(Duration::from_secs(200 * 365 * 86400)),
+        ..test_config()
+    };
+    config.keys.insert("AKIDEXAMPLE".to_owned(), Key::new(secret, Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let host = url.trim_start_matches("http://");
+        let canonical_request = format!(
+            "GET\n/\nAction=ListUsers&Version=2010-05-08\nhost:{}\nx-amz-date:20150830T123600Z\n\n\
+             host;x-amz-date\n{}",
+            host, sha2::Sha256::digest(b"").to_hex());
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/iam/aws4_request\n{}",
+            sha2::Sha256::digest(canonical_request.as_bytes()).to_hex());
+
+        let mut key = SecretKey::new(format!("AWS4{}", secret).as_bytes());
+        for part in &["20150830", "us-east-1", "iam", "aws4_request"] {
+            key = SecretKey::new(&Sha256::default().hmac(&key, part.as_bytes()));
+        }
+        let signature = Sha256::default().hmac(&key, string_to_sign.as_bytes()).to_hex();
+        let authorization = |signature: &str| {
+            format!("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+                     SignedHeaders=host;x-amz-date, Signature={}", signature)
+        };
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?Version=2010-05-08&Action=ListUsers", url)[..])
+                            .header(XAmzDate("20150830T123600Z".to_owned()))
+                            .header(Authorization(authorization(&signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?Version=2010-05-08&Action=DeleteUser", url)[..])
+                            .header(XAmzDate("20150830T123600Z".to_owned()))
+                            .header(Authorization(authorization(&signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+
+        for timestamp in &["201\u{e9}083T123600Z", "20150830T243600Z", "20150830T12360+Z"] {
+            let res = client.get(&format!("{}/?Version=2010-05-08&Action=ListUsers", url)[..])
+                                .header(XAmzDate(timestamp.to_string()))
+                                .header(Authorization(authorization(&signature)))
+                                .send().unwrap();
+
+            assert_eq!(res.status(), hyper::StatusCode::Forbidden, "{}", timestamp);
+        }
+    }
+}
+
+#[cfg(feature = "secrets-aws")]
+#[test]
+fn aws_secrets_manager_secret_is_used() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use iron_hmac::{AwsSecretsManagerProvider, AwsSigV4, Scheme};
+
+    // Secrets Manager stand-in, which only answers requests signed with the test credentials
+    let secret_access_key = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    std::env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", secret_access_key);
+    std::env::remove_var("AWS_SESSION_TOKEN");
+
+    let mut config = Config {
+        scheme: Scheme::AwsSigV4(AwsSigV4::new("us-east-1", "secretsmanager")),
+        timestamp_max_age: Some(Duration::from_secs(300)),
+        ..test_config()
+    };
+    config.keys.insert("AKIDEXAMPLE".to_owned(), Key::new(secret_access_key, Algorithm::Sha256));
+    let (sigv4_before, _) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let mut chain = Chain::new(|_: &mut Request| {
+        let secret = r#"{"default": "rust :)", "partner": "hex:00ff"}"#;
+        let body = format!(r#"{{"Name": "hmac", "SecretString": {:?}}}"#, secret);
+        Ok(Response::with((iron::status::Ok, body)))
+    });
+    chain.link_before(persistent::Read::<bodyparser::MaxBodyLength>::one(1024 * 1024 * 10));
+    chain.link_before(sigv4_before);
+    let secrets_manager = CloseGuard(Iron::new(chain).http("127.0.0.1:0").unwrap());
+    let endpoint = format!("http://{}", secrets_manager.0.socket);
+
+    let provider = AwsSecretsManagerProvider::with_endpoint(&endpoint, "us-east-1", "hmac")
+        .unwrap();
+    let config = Config { secret_provider: Some(Arc::new(provider)), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+        let client = Client::new();
+        let res = client.get(&url[..])
+                            .header(XHmac(request_hmac.to_owned()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+
+    assert!(AwsSecretsManagerProvider::with_endpoint(&endpoint, "eu-west-1", "hmac").is_err());
+}
+
+#[cfg(feature = "secrets-vault")]
+#[test]
+fn vault_refresh_does_not_block_other_requests() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use iron_hmac::VaultSecretProvider;
+
+    // Vault stand-in, slow to answer every fetch after the first
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let vault = CloseGuard(Iron::new(move |req: &mut Request| {
+        assert_eq!(req.url.path().join("/"), "v1/secret/data/hmac");
+        if counter.fetch_add(1, Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_secs(2));
+        }
+        let body = r#"{"data": {"data": {"default": "rust :)"}}, "lease_duration": 0}"#;
+        Ok(Response::with((iron::status::Ok, body)))
+    }).http("127.0.0.1:0").unwrap());
+    let address = format!("http://{}", vault.0.socket);
+
+    let provider = VaultSecretProvider::new(&address, "token", "secret", "hmac", "default")
+        .unwrap()
+        .refresh_interval(Duration::from_secs(0));
+    let config = Config { secret_provider: Some(Arc::new(provider)), ..test_config() };
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "unused", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    let request_hmac = "fa64feb94f1d649d435ae6dce009ff0767f57c0f20867dde5f8f6712fea3a7be";
+
+    let refreshing_url = url.clone();
+    let refreshing = thread::spawn(move || {
+        Client::new().get(&refreshing_url[..])
+                     .header(XHmac(request_hmac.to_owned()))
+                     .send().unwrap()
+                     .status()
+    });
+    while fetches.load(Ordering::SeqCst) < 2 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let started = Instant::now();
+    let res = Client::new().get(&url[..])
+                           .header(XHmac(request_hmac.to_owned()))
+                           .send().unwrap();
+
+    assert_eq!(res.status(), hyper::StatusCode::Ok);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(refreshing.join().unwrap(), hyper::StatusCode::Ok);
+}
+
+#[test]
+fn azure_shared_key_is_verified() {
+    use std::time::SystemTime;
+    use iron_hmac::{HmacDigest, Scheme, SecretKey};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Authorization, "Authorization") => [String] }
+    header! { (XMsDate, "x-ms-date") => [String] }
+    header! { (XMsVersion, "x-ms-version") => [String] }
+
+    let account_key = SecretKey::from_base64("c2VjcmV0IGFjY291bnQga2V5").unwrap();
+    let mut config = Config { scheme: Scheme::AzureSharedKey, ..test_config() };
+    config.keys.insert("myaccount".to_owned(), Key::new(account_key.clone(), Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let date = hyper::header::HttpDate::from(SystemTime::now()).to_string();
+        let string_to_sign = format!("GET\n\n\n\n\n\n\n\n\n\n\n\nx-ms-date:{}\n\
+                                      x-ms-version:2015-02-21\n/myaccount/\ncomp:list", date);
+        let signature =
+            Sha256::default().hmac(&account_key, string_to_sign.as_bytes()).to_base64(STANDARD);
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/?comp=list", url)[..])
+                            .header(XMsDate(date.clone()))
+                            .header(XMsVersion("2015-02-21".to_owned()))
+                            .header(Authorization(format!("SharedKey myaccount:{}", signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/?comp=metadata", url)[..])
+                            .header(XMsDate(date))
+                            .header(XMsVersion("2015-02-21".to_owned()))
+                            .header(Authorization(format!("SharedKey myaccount:{}", signature)))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn hawk_authorization_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, Scheme, SecretKey};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Authorization, "Authorization") => [String] }
+
+    let mut config = Config { scheme: Scheme::Hawk, ..test_config() };
+    let secret = "werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn";
+    config.keys.insert("dh37fgj492je".to_owned(), Key::new(secret, Algorithm::Sha256));
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::with_config(Sha256::default(), "rust :)", "x-hmac", config)
+            .unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let port = url.rsplit(':').next().unwrap();
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let normalized = format!("hawk.1.header\n{}\nj4h3g2\nGET\n/resource?a=1\n127.0.0.1\n{}\n\n\
+                                  some-app-ext-data\n", ts, port);
+        let secret: SecretKey = secret.into();
+        let mac = Sha256::default().hmac(&secret, normalized.as_bytes()).to_base64(STANDARD);
+        let authorization = format!("Hawk id=\"dh37fgj492je\", ts=\"{}\", nonce=\"j4h3g2\", \
+                                     ext=\"some-app-ext-data\", mac=\"{}\"", ts, mac);
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/resource?a=1", url)[..])
+                            .header(Authorization(authorization.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/resource?a=1", url)[..])
+                            .header(Authorization(authorization))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn api_auth_request_is_verified() {
+    use std::time::SystemTime;
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (Authorization, "Authorization") => [String] }
+    header! { (Date, "Date") => [String] }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::api_auth("rust :)");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let date = hyper::header::HttpDate::from(SystemTime::now()).to_string();
+        let canonical = format!("GET,,,/resource?a=1,{}", date);
+        let secret: SecretKey = "rust :)".into();
+        let signature = Sha256::default().hmac(&secret, canonical.as_bytes()).to_base64(STANDARD);
+        let authorization = format!("APIAuth-HMAC-SHA256 rails-client:{}", signature);
+
+        let client = Client::new();
+        let res = client.get(&format!("{}/resource?a=1", url)[..])
+                            .header(Date(date.clone()))
+                            .header(Authorization(authorization.clone()))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.get(&format!("{}/resource?a=2", url)[..])
+                            .header(Date(date))
+                            .header(Authorization(authorization))
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn github_webhook_is_verified() {
+    header! { (XHubSignature256, "X-Hub-Signature-256") => [String] }
+
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::github_webhook("It's a Secret to Everybody");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+        let client = Client::new();
+        let res = client.post(&format!("{}/payload", url)[..])
+                            .header(XHubSignature256(signature.to_owned()))
+                            .body("Hello, World!")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+        assert!(res.headers().get_raw("x-hub-signature-256").is_none());
+
+        let res = client.post(&format!("{}/payload", url)[..])
+                            .header(XHubSignature256(signature.to_owned()))
+                            .body("Hello, World?")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn stripe_webhook_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    header! { (StripeSignature, "Stripe-Signature") => [String] }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::stripe_webhook("whsec_new");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let body = "{\"id\": \"evt_1\"}";
+        let sign = |secret: &str, timestamp: u64| {
+            let secret = SecretKey::new(secret.as_bytes());
+            let payload = format!("{}.{}", timestamp, body);
+            Sha256::default().hmac(&secret, payload.as_bytes()).to_hex()
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(StripeSignature(format!("t={},v1={},v1={},v0=00", now,
+                                                            sign("whsec_old", now),
+                                                            sign("whsec_new", now))))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let stale = now - 600;
+        let res = client.post(&url[..])
+                            .header(StripeSignature(format!("t={},v1={}", stale,
+                                                            sign("whsec_new", stale))))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn slack_request_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    header! { (XSlackSignature, "X-Slack-Signature") => [String] }
+    header! { (XSlackRequestTimestamp, "X-Slack-Request-Timestamp") => [String] }
+
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::slack("8f742231b10e8888abcd99yyyzzz85a5");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let body = "token=xyzz0WbapA4vBCDEFasx0q6G&command=%2Fweather&text=94070";
+        let sign = |timestamp: u64| {
+            let secret: SecretKey = "8f742231b10e8888abcd99yyyzzz85a5".into();
+            let base = format!("v0:{}:{}", timestamp, body);
+            format!("v0={}", Sha256::default().hmac(&secret, base.as_bytes()).to_hex())
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XSlackRequestTimestamp(now.to_string()))
+                            .header(XSlackSignature(sign(now)))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let stale = now - 600;
+        let res = client.post(&url[..])
+                            .header(XSlackRequestTimestamp(stale.to_string()))
+                            .header(XSlackSignature(sign(stale)))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn shopify_webhook_is_verified() {
+    header! { (XShopifyHmacSha256, "X-Shopify-Hmac-Sha256") => [String] }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::shopify_webhook("shpss_secret");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let signature = "jqbalbO3Z8mDmrF2/+wzM3KYvbgqzVBF969vgiIy80I=";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XShopifyHmacSha256(signature.to_owned()))
+                            .body("{\"id\":820982911946154508}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XShopifyHmacSha256(signature.to_owned()))
+                            .body("{\"id\":820982911946154509}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn from_preset_builds_provider_middleware() {
+    use iron_hmac::{Preset, PresetAuthentication};
+
+    header! { (XShopifyHmacSha256, "X-Shopify-Hmac-Sha256") => [String] }
+
+    let (hmac_before, hmac_after) =
+        PresetAuthentication::from_preset(Preset::Shopify, "shpss_secret").unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let signature = "jqbalbO3Z8mDmrF2/+wzM3KYvbgqzVBF969vgiIy80I=";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XShopifyHmacSha256(signature.to_owned()))
+                            .body("{\"id\":820982911946154508}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+
+    assert!(PresetAuthentication::from_preset(Preset::GitLabSigned, "whsec_!!!").is_err());
+}
+
+#[cfg(feature = "hmac-sha1")]
+#[test]
+fn twilio_request_is_verified() {
+    use iron_hmac::{HmacDigest, SecretKey, Sha1};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (XTwilioSignature, "X-Twilio-Signature") => [String] }
+
+    let (hmac_before, hmac_after) = HmacAuthentication::<Sha1>::twilio("12345");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let request_url = format!("{}/voice?foo=1", url);
+        let data = format!("{}CallSid{}Digits1234From+14158675310", request_url,
+                           "CA1234567890ABCDE");
+        let secret: SecretKey = "12345".into();
+        let signature = Sha1.hmac(&secret, data.as_bytes()).to_base64(STANDARD);
+
+        let client = Client::new();
+        let res = client.post(&request_url[..])
+                            .header(XTwilioSignature(signature.clone()))
+                            .body("Digits=1234&CallSid=CA1234567890ABCDE&From=%2B14158675310")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&request_url[..])
+                            .header(XTwilioSignature(signature))
+                            .body("Digits=4321&CallSid=CA1234567890ABCDE&From=%2B14158675310")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn mailgun_webhook_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::hex::ToHex;
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::mailgun_webhook("key-mailgun");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let token = "e2c3a5b6f0d94d7c8a1b";
+        let secret: SecretKey = "key-mailgun".into();
+        let signature =
+            Sha256::default().hmac(&secret, format!("{}{}", timestamp, token).as_bytes()).to_hex();
+        let body = format!("{{\"signature\": {{\"timestamp\": \"{}\", \"token\": \"{}\", \
+                            \"signature\": \"{}\"}}, \"event-data\": {{\"event\": \"opened\"}}}}",
+                           timestamp, token, signature);
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .body(body.clone())
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn meta_webhook_is_verified() {
+    header! { (XHubSignature256, "X-Hub-Signature-256") => [String] }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::meta_webhook("meta app secret");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let signature = "sha256=baba422337c6861db4080d754ad73d6853520e38730b3c1e1437ef24a8aca1dc";
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XHubSignature256(signature.to_owned()))
+                            .body("{\"object\":\"page\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+    }
+}
+
+#[cfg(feature = "hmac-sha1")]
+#[test]
+fn meta_webhook_sha1_is_verified() {
+    use iron_hmac::Sha1;
+
+    header! { (XHubSignature, "X-Hub-Signature") => [String] }
+
+    let (hmac_before, hmac_after) =
+        HmacAuthentication::<Sha1>::meta_webhook_sha1("meta app secret");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XHubSignature("sha1=5c97d889ed446b457dcb4cc56f8af9e4ac24583e"
+                                                      .to_owned()))
+                            .body("{\"object\":\"page\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XHubSignature("sha1=5c97d889ed446b457dcb4cc56f8af9e4ac24583e"
+                                                      .to_owned()))
+                            .body("{\"object\":\"user\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn gitlab_webhook_token_is_verified() {
+    header! { (XGitlabToken, "X-Gitlab-Token") => [String] }
+
+    let (hmac_before, hmac_after) = Hmac256Authentication::gitlab_webhook("gitlab token");
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(XGitlabToken("gitlab token".to_owned()))
+                            .body("{\"object_kind\":\"push\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(XGitlabToken("gitlab tokem".to_owned()))
+                            .body("{\"object_kind\":\"push\"}")
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}
+
+#[test]
+fn gitlab_signed_webhook_is_verified() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use iron_hmac::{HmacDigest, SecretKey};
+    use rustc_serialize::base64::{ToBase64, STANDARD};
+
+    header! { (WebhookId, "webhook-id") => [String] }
+    header! { (WebhookTimestamp, "webhook-timestamp") => [String] }
+    header! { (WebhookSignature, "webhook-signature") => [String] }
+
+    let (hmac_before, hmac_after) =
+        Hmac256Authentication::gitlab_signed_webhook("whsec_c2lnbmluZyB0b2tlbg==").unwrap();
+    let (_close_guard, url) = build_hello_world(hmac_before, hmac_after);
+    {
+        let body = "{\"object_kind\":\"push\"}";
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let secret = SecretKey::from_base64("c2lnbmluZyB0b2tlbg==").unwrap();
+        let content = format!("msg_1.{}.{}", timestamp, body);
+        let signature = Sha256::default().hmac(&secret, content.as_bytes()).to_base64(STANDARD);
+
+        let client = Client::new();
+        let res = client.post(&url[..])
+                            .header(WebhookId("msg_1".to_owned()))
+                            .header(WebhookTimestamp(timestamp.to_string()))
+                            .header(WebhookSignature(format!("v1,b2xk v1,{}", signature)))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Ok);
+
+        let res = client.post(&url[..])
+                            .header(WebhookId("msg_1".to_owned()))
+                            .header(WebhookTimestamp(timestamp.to_string()))
+                            .header(WebhookSignature(format!("v1,{}", signature)))
+                            .body(body)
+                            .send().unwrap();
+
+        assert_eq!(res.status(), hyper::StatusCode::Forbidden);
+    }
+}